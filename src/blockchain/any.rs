@@ -37,7 +37,7 @@
 //! )?;
 //! # }
 //!
-//! # #[cfg(feature = "esplora")]
+//! # #[cfg(any(feature = "esplora", feature = "esplora-blocking"))]
 //! # {
 //! let esplora_blockchain = EsploraBlockchain::new("...", None);
 //! let wallet_esplora: Wallet<AnyBlockchain, _> = Wallet::new(
@@ -90,7 +90,7 @@ macro_rules! impl_inner_method {
         match $self {
             #[cfg(feature = "electrum")]
             AnyBlockchain::Electrum(inner) => inner.$name( $($args, )* ),
-            #[cfg(feature = "esplora")]
+            #[cfg(any(feature = "esplora", feature = "esplora-blocking"))]
             AnyBlockchain::Esplora(inner) => inner.$name( $($args, )* ),
             #[cfg(feature = "compact_filters")]
             AnyBlockchain::CompactFilters(inner) => inner.$name( $($args, )* ),
@@ -108,8 +108,11 @@ pub enum AnyBlockchain {
     #[cfg_attr(docsrs, doc(cfg(feature = "electrum")))]
     /// Electrum client
     Electrum(electrum::ElectrumBlockchain),
-    #[cfg(feature = "esplora")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "esplora")))]
+    #[cfg(any(feature = "esplora", feature = "esplora-blocking"))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(feature = "esplora", feature = "esplora-blocking")))
+    )]
     /// Esplora client
     Esplora(esplora::EsploraBlockchain),
     #[cfg(feature = "compact_filters")]
@@ -152,6 +155,20 @@ impl Blockchain for AnyBlockchain {
             progress_update
         ))
     }
+    fn refresh<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        database: &mut D,
+        progress_update: P,
+    ) -> Result<(), Error> {
+        maybe_await!(impl_inner_method!(
+            self,
+            refresh,
+            stop_gap,
+            database,
+            progress_update
+        ))
+    }
 
     fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error> {
         maybe_await!(impl_inner_method!(self, get_tx, txid))
@@ -166,10 +183,13 @@ impl Blockchain for AnyBlockchain {
     fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
         maybe_await!(impl_inner_method!(self, estimate_fee, target))
     }
+    fn estimate_fees(&self, targets: &[usize]) -> Result<Vec<FeeRate>, Error> {
+        maybe_await!(impl_inner_method!(self, estimate_fees, targets))
+    }
 }
 
 impl_from!(electrum::ElectrumBlockchain, AnyBlockchain, Electrum, #[cfg(feature = "electrum")]);
-impl_from!(esplora::EsploraBlockchain, AnyBlockchain, Esplora, #[cfg(feature = "esplora")]);
+impl_from!(esplora::EsploraBlockchain, AnyBlockchain, Esplora, #[cfg(any(feature = "esplora", feature = "esplora-blocking"))]);
 impl_from!(compact_filters::CompactFiltersBlockchain, AnyBlockchain, CompactFilters, #[cfg(feature = "compact_filters")]);
 
 /// Type that can contain any of the blockchain configurations defined by the library
@@ -198,6 +218,7 @@ impl_from!(compact_filters::CompactFiltersBlockchain, AnyBlockchain, CompactFilt
 ///         url: "ssl://electrum.blockstream.info:50002".into(),
 ///         retry: 2,
 ///         socks5: None,
+///         socks5_credentials: None,
 ///         timeout: None
 ///     })
 /// );
@@ -210,8 +231,11 @@ pub enum AnyBlockchainConfig {
     #[cfg_attr(docsrs, doc(cfg(feature = "electrum")))]
     /// Electrum client
     Electrum(electrum::ElectrumBlockchainConfig),
-    #[cfg(feature = "esplora")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "esplora")))]
+    #[cfg(any(feature = "esplora", feature = "esplora-blocking"))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(feature = "esplora", feature = "esplora-blocking")))
+    )]
     /// Esplora client
     Esplora(esplora::EsploraBlockchainConfig),
     #[cfg(feature = "compact_filters")]
@@ -229,7 +253,7 @@ impl ConfigurableBlockchain for AnyBlockchain {
             AnyBlockchainConfig::Electrum(inner) => {
                 AnyBlockchain::Electrum(electrum::ElectrumBlockchain::from_config(inner)?)
             }
-            #[cfg(feature = "esplora")]
+            #[cfg(any(feature = "esplora", feature = "esplora-blocking"))]
             AnyBlockchainConfig::Esplora(inner) => {
                 AnyBlockchain::Esplora(esplora::EsploraBlockchain::from_config(inner)?)
             }
@@ -242,5 +266,5 @@ impl ConfigurableBlockchain for AnyBlockchain {
 }
 
 impl_from!(electrum::ElectrumBlockchainConfig, AnyBlockchainConfig, Electrum, #[cfg(feature = "electrum")]);
-impl_from!(esplora::EsploraBlockchainConfig, AnyBlockchainConfig, Esplora, #[cfg(feature = "esplora")]);
+impl_from!(esplora::EsploraBlockchainConfig, AnyBlockchainConfig, Esplora, #[cfg(any(feature = "esplora", feature = "esplora-blocking"))]);
 impl_from!(compact_filters::CompactFiltersBlockchainConfig, AnyBlockchainConfig, CompactFilters, #[cfg(feature = "compact_filters")]);
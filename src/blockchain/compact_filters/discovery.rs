@@ -0,0 +1,221 @@
+// Bitcoin Dev Kit
+// Written in 2021 by Alekos Filini <alekos.filini@gmail.com>
+//
+// Copyright (c) 2020-2021 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+use std::collections::HashSet;
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bitcoin::network::constants::ServiceFlags;
+use bitcoin::Network;
+
+use super::peer::Peer;
+use super::store::{ChainStore, Full, KnownAddress};
+use super::{BitcoinPeerConfig, CompactFiltersError};
+
+/// Addresses that haven't been seen for more than two weeks are removed from the address book
+const MAX_ADDRESS_AGE_SECS: u32 = 14 * 24 * 60 * 60;
+
+/// Trait for types that can resolve the hostnames of the DNS seeds
+pub trait DnsResolver {
+    /// Resolve a hostname to a list of IP addresses
+    fn resolve(&self, host: &str) -> Result<Vec<IpAddr>, io::Error>;
+}
+
+/// [`DnsResolver`] that uses the resolver of the operating system
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemResolver;
+
+impl DnsResolver for SystemResolver {
+    fn resolve(&self, host: &str) -> Result<Vec<IpAddr>, io::Error> {
+        Ok((host, 0).to_socket_addrs()?.map(|addr| addr.ip()).collect())
+    }
+}
+
+/// Services a peer must advertise to be used for the sync
+pub(crate) fn required_services() -> ServiceFlags {
+    let mut services = ServiceFlags::NETWORK;
+    services.add(ServiceFlags::WITNESS);
+    services.add(ServiceFlags::COMPACT_FILTERS);
+
+    services
+}
+
+fn dns_seeds(network: Network) -> &'static [&'static str] {
+    match network {
+        Network::Bitcoin => &[
+            "seed.bitcoin.sipa.be",
+            "dnsseed.bluematt.me",
+            "dnsseed.bitcoin.dashjr.org",
+            "seed.bitcoinstats.com",
+            "seed.bitcoin.jonasschnelli.ch",
+            "seed.btc.petertodd.org",
+            "seed.bitcoin.sprovoost.nl",
+            "dnsseed.emzy.de",
+        ],
+        Network::Testnet => &[
+            "testnet-seed.bitcoin.jonasschnelli.ch",
+            "seed.tbtc.petertodd.org",
+            "seed.testnet.bitcoin.sprovoost.nl",
+            "testnet-seed.bluematt.me",
+        ],
+        Network::Signet => &["seed.signet.bitcoin.sprovoost.nl"],
+        Network::Regtest => &[],
+    }
+}
+
+fn default_port(network: Network) -> u16 {
+    match network {
+        Network::Bitcoin => 8333,
+        Network::Testnet => 18333,
+        Network::Signet => 38333,
+        Network::Regtest => 18444,
+    }
+}
+
+/// Resolve the DNS seeds of a network
+///
+/// The seeds are first asked for peers that advertise the [`required_services`], using the
+/// `x<services>.` subdomain. If a seed doesn't support it, the unfiltered list is used instead.
+pub(crate) fn resolve_seeds<R: DnsResolver>(resolver: &R, network: Network) -> Vec<SocketAddr> {
+    let port = default_port(network);
+
+    let mut addresses = Vec::new();
+    for seed in dns_seeds(network) {
+        let filtered_seed = format!("x{:x}.{}", required_services(), seed);
+        let ips = match resolver.resolve(&filtered_seed) {
+            Ok(ips) if !ips.is_empty() => ips,
+            _ => match resolver.resolve(seed) {
+                Ok(ips) => ips,
+                Err(e) => {
+                    log::debug!("Unable to resolve DNS seed {}: {:?}", seed, e);
+                    continue;
+                }
+            },
+        };
+
+        addresses.extend(ips.into_iter().map(|ip| SocketAddr::new(ip, port)));
+    }
+
+    addresses
+}
+
+/// Return the list of peers that can be connected, taken from the address book and the DNS seeds
+///
+/// The most recently seen peers in the address book come first.
+pub(crate) fn find_peers<R: DnsResolver>(
+    store: &ChainStore<Full>,
+    resolver: &R,
+    network: Network,
+) -> Result<Vec<BitcoinPeerConfig>, CompactFiltersError> {
+    let mut known_addresses = store
+        .iter_known_addresses()?
+        .into_iter()
+        .filter(|known| known.services.has(required_services()))
+        .collect::<Vec<_>>();
+    known_addresses.sort_by_key(|known| std::cmp::Reverse(known.last_seen));
+
+    let mut seen = HashSet::new();
+    Ok(known_addresses
+        .into_iter()
+        .map(|known| known.address)
+        .chain(resolve_seeds(resolver, network))
+        .filter(|address| seen.insert(*address))
+        .map(peer_config)
+        .collect())
+}
+
+/// Return the configuration to connect directly to a peer
+pub(crate) fn peer_config(address: SocketAddr) -> BitcoinPeerConfig {
+    BitcoinPeerConfig {
+        address: address.to_string(),
+        socks5: None,
+        socks5_credentials: None,
+    }
+}
+
+/// Save the addresses learned by `peers` that advertise the [`required_services`] in the address
+/// book, and drop the ones that haven't been seen in a while
+///
+/// Returns the new addresses.
+pub(crate) fn update_address_book(
+    store: &ChainStore<Full>,
+    peers: &[std::sync::Arc<Peer>],
+) -> Result<Vec<KnownAddress>, CompactFiltersError> {
+    let learned = peers
+        .iter()
+        .flat_map(|peer| peer.take_learned_addresses())
+        .filter(|known| known.services.has(required_services()))
+        .collect::<Vec<_>>();
+    for known in &learned {
+        store.save_known_address(known)?;
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32;
+    store.delete_known_addresses_before(now.saturating_sub(MAX_ADDRESS_AGE_SECS))?;
+
+    Ok(learned)
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct StubResolver {
+        queries: RefCell<Vec<String>>,
+    }
+
+    impl DnsResolver for StubResolver {
+        fn resolve(&self, host: &str) -> Result<Vec<IpAddr>, io::Error> {
+            self.queries.borrow_mut().push(host.to_string());
+
+            match host {
+                // this seed supports service filtering
+                "x49.seed.bitcoin.sipa.be" => Ok(vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))]),
+                "seed.bitcoin.sipa.be" => Ok(vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))]),
+                // this one doesn't
+                "dnsseed.bluematt.me" => Ok(vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3))]),
+                _ => Err(io::Error::from(io::ErrorKind::NotFound)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve_seeds() {
+        let resolver = StubResolver::default();
+
+        let addresses = resolve_seeds(&resolver, Network::Bitcoin);
+        assert_eq!(
+            addresses,
+            vec![
+                "10.0.0.1:8333".parse::<SocketAddr>().unwrap(),
+                "10.0.0.3:8333".parse::<SocketAddr>().unwrap(),
+            ]
+        );
+
+        let queries = resolver.queries.borrow();
+        assert_eq!(queries[0], "x49.seed.bitcoin.sipa.be");
+        assert!(!queries.contains(&"seed.bitcoin.sipa.be".to_string()));
+        assert!(queries.contains(&"dnsseed.bluematt.me".to_string()));
+    }
+
+    #[test]
+    fn test_resolve_seeds_regtest() {
+        let resolver = StubResolver::default();
+
+        assert!(resolve_seeds(&resolver, Network::Regtest).is_empty());
+        assert!(resolver.queries.borrow().is_empty());
+    }
+}
@@ -16,13 +16,32 @@
 //! by downloading compact filters from the P2P network.
 //!
 //! Since there are currently very few peers "in the wild" that advertise the required service
-//! flag, this implementation requires that one or more known peers are provided by the user.
-//! No dns or other kinds of peer discovery are done internally.
+//! flag, this implementation requires that one or more known peers are provided by the user,
+//! unless peer discovery is enabled.
 //!
-//! Moreover, this module doesn't currently support detecting and resolving conflicts between
-//! messages received by different peers. Thus, it's recommended to use this module by only
-//! connecting to a single peer at a time, optionally by opening multiple connections if it's
-//! desirable to use multiple threads at once to sync in parallel.
+//! The peers are used as a pool: the filters are downloaded in parallel from all of them, and the
+//! filter checkpoints they return are cross-checked, banning the peers that disagree with the
+//! majority. Peers that disconnect, time out or misbehave are replaced with the spare peers added
+//! with [`CompactFiltersBlockchain::add_spare_peers`], if any.
+//!
+//! Peer discovery is disabled by default, and can be enabled with
+//! [`CompactFiltersBlockchain::discover_peers`]: the DNS seeds of the network are resolved and the
+//! connected peers are asked for the addresses of other peers, which are kept in an address book
+//! in the storage dir.
+//!
+//! Unconfirmed transactions are normally only learned from the peers' mempool if they advertise
+//! the [`BLOOM`](bitcoin::network::constants::ServiceFlags::BLOOM) service flag. With a
+//! [`Mempool`] created with [`Mempool::with_monitoring`] the peers are also asked to announce the
+//! new transactions they receive, and the ones relevant for the wallet are added to the database
+//! as unconfirmed during the sync.
+//!
+//! Reorgs are detected while syncing the headers: the blocks disconnected from the chain are
+//! rolled back together with their compact filters, and the transactions confirmed in them are
+//! removed from the database, unless they are found again in the new chain or in the mempool.
+//!
+//! Fee rates are estimated from the average fee rate of the recently downloaded blocks. At the end
+//! of every sync the last few blocks of the chain are downloaded just for this purpose, even if
+//! they don't contain any transaction related to the wallet.
 //!
 //! This is an **EXPERIMENTAL** feature, API and other major changes are expected.
 //!
@@ -52,46 +71,59 @@
 use std::collections::HashSet;
 use std::fmt;
 use std::path::Path;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 #[allow(unused_imports)]
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 
 use bitcoin::network::message_blockdata::Inventory;
 use bitcoin::{Network, OutPoint, Transaction, Txid};
 
 use rocksdb::{Options, SliceTransform, DB};
 
+mod discovery;
 mod peer;
+mod pool;
 mod store;
 mod sync;
 
 use super::{Blockchain, Capability, ConfigurableBlockchain, Progress};
 use crate::database::{BatchDatabase, BatchOperations, DatabaseUtils};
 use crate::error::Error;
-use crate::types::{KeychainKind, LocalUtxo, TransactionDetails};
+use crate::types::{Birthday, KeychainKind, LocalUtxo, TransactionDetails};
 use crate::{ConfirmationTime, FeeRate};
 
 use peer::*;
+use pool::*;
 use store::*;
 use sync::*;
 
+pub use discovery::{DnsResolver, SystemResolver};
 pub use peer::{Mempool, Peer};
 
 const SYNC_HEADERS_COST: f32 = 1.0;
 const SYNC_FILTERS_COST: f32 = 11.6 * 1_000.0;
 const PROCESS_BLOCKS_COST: f32 = 20_000.0;
 
+/// Number of blocks at the tip of the chain that are always downloaded to sample their fee rates
+const FEE_SAMPLE_BLOCKS: usize = 6;
+/// Number of blocks for which fee statistics are kept in the store
+const FEE_HISTORY_BLOCKS: usize = 1008;
+
+/// Default number of peers connected when peer discovery is enabled
+const DEFAULT_DISCOVERY_PEERS: usize = 4;
+
 /// Structure implementing the required blockchain traits
 ///
 /// ## Example
 /// See the [`blockchain::compact_filters`](crate::blockchain::compact_filters) module for a usage example.
 #[derive(Debug)]
 pub struct CompactFiltersBlockchain {
-    peers: Vec<Arc<Peer>>,
+    peers: PeerPool,
     headers: Arc<ChainStore<Full>>,
     skip_blocks: Option<usize>,
+    discovery_enabled: AtomicBool,
 }
 
 impl CompactFiltersBlockchain {
@@ -100,24 +132,36 @@ impl CompactFiltersBlockchain {
     /// from the genesis while scanning for the wallet's outputs.
     ///
     /// For each [`Peer`] specified a new thread will be spawned to download and verify the filters
-    /// in parallel. It's currently recommended to only connect to a single peer to avoid
-    /// inconsistencies in the data returned, optionally with multiple connections in parallel to
-    /// speed-up the sync process.
+    /// in parallel. Connecting to multiple different peers allows the filters to be cross-checked
+    /// between them.
     pub fn new<P: AsRef<Path>>(
         peers: Vec<Peer>,
         storage_dir: P,
         skip_blocks: Option<usize>,
     ) -> Result<Self, CompactFiltersError> {
-        if peers.is_empty() {
-            return Err(CompactFiltersError::NoPeers);
-        }
+        let max_peers = peers.len();
+        let peers = PeerPool::new(
+            peers.into_iter().map(|peer| (peer, None)).collect(),
+            max_peers,
+        )?;
+        let headers = Self::open_store(storage_dir, peers.get_network())?;
 
+        Ok(CompactFiltersBlockchain {
+            peers,
+            headers,
+            skip_blocks,
+            discovery_enabled: AtomicBool::new(false),
+        })
+    }
+
+    fn open_store<P: AsRef<Path>>(
+        storage_dir: P,
+        network: Network,
+    ) -> Result<Arc<ChainStore<Full>>, CompactFiltersError> {
         let mut opts = Options::default();
         opts.create_if_missing(true);
         opts.set_prefix_extractor(SliceTransform::create_fixed_prefix(16));
 
-        let network = peers[0].get_network();
-
         let cfs = DB::list_cf(&opts, &storage_dir).unwrap_or_else(|_| vec!["default".to_string()]);
         let db = DB::open_cf(&opts, &storage_dir, &cfs)?;
         let headers = Arc::new(ChainStore::new(db, network)?);
@@ -132,11 +176,39 @@ impl CompactFiltersBlockchain {
             headers.recover_snapshot(cf_name)?;
         }
 
-        Ok(CompactFiltersBlockchain {
-            peers: peers.into_iter().map(Arc::new).collect(),
-            headers,
-            skip_blocks,
-        })
+        Ok(headers)
+    }
+
+    /// Add some peers that will be connected to replace the ones that disconnect or misbehave
+    pub fn add_spare_peers<I: IntoIterator<Item = BitcoinPeerConfig>>(&self, spare_peers: I) {
+        self.peers.add_spare_peers(spare_peers);
+    }
+
+    /// Look for more peers in the address book and through the DNS seeds of the network, and add
+    /// them to the spare peers
+    ///
+    /// This also enables asking the connected peers for the addresses of other peers during the
+    /// sync. Only peers that advertise the
+    /// [`COMPACT_FILTERS`](bitcoin::network::constants::ServiceFlags::COMPACT_FILTERS) service flag
+    /// are used.
+    ///
+    /// Note that the DNS seeds are resolved with `resolver`, and the peers found are connected
+    /// directly, without going through a proxy.
+    pub fn discover_peers<R: DnsResolver>(&self, resolver: &R) -> Result<(), CompactFiltersError> {
+        self.discovery_enabled.store(true, Ordering::SeqCst);
+
+        let found = discovery::find_peers(&self.headers, resolver, self.peers.get_network())?;
+        info!("Found {} peers", found.len());
+        self.peers.add_spare_peers(found);
+
+        Ok(())
+    }
+
+    /// Return the number of blocks to skip for a wallet born at `birthday_height`, keeping the
+    /// whole bundle of filters that contains it
+    fn skip_blocks_for(&self, birthday_height: u32) -> usize {
+        let birthday_skip = (birthday_height as usize).saturating_sub(1) / 1000 * 1000;
+        std::cmp::max(self.skip_blocks.unwrap_or(0), birthday_skip)
     }
 
     /// Process a transaction by looking for inputs that spend from a UTXO in the database or
@@ -185,6 +257,9 @@ impl CompactFiltersBlockchain {
                     outpoint: OutPoint::new(tx.txid(), i as u32),
                     txout: output.clone(),
                     keychain,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase: tx.is_coin_base(),
                 })?;
                 incoming += output.value;
 
@@ -209,6 +284,7 @@ impl CompactFiltersBlockchain {
                 confirmation_time: ConfirmationTime::new(height, timestamp),
                 verified: height.is_some(),
                 fee: Some(inputs_sum.saturating_sub(outputs_sum)),
+                label: None,
             };
 
             info!("Saving tx {}", tx.txid);
@@ -233,39 +309,60 @@ impl Blockchain for CompactFiltersBlockchain {
         database: &mut D,
         progress_update: P,
     ) -> Result<(), Error> {
-        let first_peer = &self.peers[0];
+        let peers_height = self
+            .peers
+            .get_peers()
+            .iter()
+            .map(|peer| peer.get_version().start_height as usize)
+            .max()
+            .ok_or(CompactFiltersError::NoPeers)?;
+
+        if self.discovery_enabled.load(Ordering::SeqCst) {
+            for peer in self.peers.get_peers() {
+                if let Err(e) = peer.ask_for_addresses() {
+                    debug!("Unable to ask for addresses: {:?}", e);
+                }
+            }
+        }
 
-        let skip_blocks = self.skip_blocks.unwrap_or(0);
+        // a birthday given as a timestamp can only be resolved once the headers are synced, until
+        // then it's only used to estimate the progress if it was already resolved in a past sync
+        let birthday = database.get_birthday()?;
+        let skip_blocks = self.skip_blocks_for(match birthday {
+            Some(Birthday::Height(height)) => height,
+            _ => 0,
+        });
 
-        let cf_sync = Arc::new(CfSync::new(Arc::clone(&self.headers), skip_blocks, 0x00)?);
+        let mut cf_sync = Arc::new(CfSync::new(Arc::clone(&self.headers), skip_blocks, 0x00)?);
 
         let initial_height = self.headers.get_height()?;
-        let total_bundles = (first_peer.get_version().start_height as usize)
+        let total_bundles = peers_height
             .checked_sub(skip_blocks)
             .map(|x| x / 1000)
             .unwrap_or(0)
             + 1;
         let expected_bundles_to_sync = total_bundles.saturating_sub(cf_sync.pruned_bundles()?);
 
-        let headers_cost = (first_peer.get_version().start_height as usize)
-            .saturating_sub(initial_height) as f32
-            * SYNC_HEADERS_COST;
+        let headers_cost = peers_height.saturating_sub(initial_height) as f32 * SYNC_HEADERS_COST;
         let filters_cost = expected_bundles_to_sync as f32 * SYNC_FILTERS_COST;
 
         let total_cost = headers_cost + filters_cost + PROCESS_BLOCKS_COST;
 
-        if let Some(snapshot) = sync::sync_headers(
-            Arc::clone(&first_peer),
-            Arc::clone(&self.headers),
-            |new_height| {
-                let local_headers_cost =
-                    new_height.saturating_sub(initial_height) as f32 * SYNC_HEADERS_COST;
-                progress_update.update(
-                    local_headers_cost / total_cost * 100.0,
-                    Some(format!("Synced headers to {}", new_height)),
-                )
-            },
-        )? {
+        let headers_progress = |new_height: usize| {
+            let local_headers_cost =
+                new_height.saturating_sub(initial_height) as f32 * SYNC_HEADERS_COST;
+            progress_update.update(
+                local_headers_cost / total_cost * 100.0,
+                Some(format!("Synced headers to {}", new_height)),
+            )
+        };
+        if let Some(snapshot) = self.peers.run(|peer| {
+            sync::sync_headers(
+                Arc::clone(peer),
+                Arc::clone(&self.headers),
+                &headers_progress,
+            )
+        })? {
             if snapshot.work()? > self.headers.work()? {
                 info!("Applying snapshot with work: {}", snapshot.work()?);
                 self.headers.apply_snapshot(snapshot)?;
@@ -276,7 +373,37 @@ impl Blockchain for CompactFiltersBlockchain {
         let buried_height = synced_height.saturating_sub(sync::BURIED_CONFIRMATIONS);
         info!("Synced headers to height: {}", synced_height);
 
-        cf_sync.prepare_sync(Arc::clone(&first_peer))?;
+        if let Some(birthday @ Birthday::Timestamp(_)) = birthday {
+            let height = birthday.find_height(synced_height as u32, |height| {
+                self.headers
+                    .get_block_time(height as usize)?
+                    .map(|time| time as u64)
+                    .ok_or(CompactFiltersError::DataCorruption)
+            })?;
+            info!("Wallet birthday resolved to height {}", height);
+            database.set_birthday(&Birthday::Height(height))?;
+
+            let birthday_skip_blocks = self.skip_blocks_for(height);
+            if birthday_skip_blocks != skip_blocks {
+                cf_sync = Arc::new(CfSync::new(
+                    Arc::clone(&self.headers),
+                    birthday_skip_blocks,
+                    0x00,
+                )?);
+            }
+        }
+
+        // the blocks after this height have been disconnected by a reorg, either in this sync or
+        // in a previous one that didn't complete
+        let reorg_height = self.headers.get_reorg_height()?;
+        if let Some(reorg_height) = reorg_height {
+            info!(
+                "Rolling back the transactions confirmed after height {}",
+                reorg_height
+            );
+        }
+
+        cf_sync.prepare_sync(&self.peers)?;
 
         let all_scripts = Arc::new(
             database
@@ -287,68 +414,94 @@ impl Blockchain for CompactFiltersBlockchain {
         );
 
         #[allow(clippy::mutex_atomic)]
-        let last_synced_block = Arc::new(Mutex::new(synced_height));
+        let last_synced_block = Arc::new(Mutex::new(match reorg_height {
+            Some(reorg_height) => std::cmp::min(synced_height, reorg_height + 1),
+            None => synced_height,
+        }));
 
         let synced_bundles = Arc::new(AtomicUsize::new(0));
         let progress_update = Arc::new(Mutex::new(progress_update));
 
-        let mut threads = Vec::with_capacity(self.peers.len());
-        for peer in &self.peers {
-            let cf_sync = Arc::clone(&cf_sync);
-            let peer = Arc::clone(&peer);
-            let headers = Arc::clone(&self.headers);
-            let all_scripts = Arc::clone(&all_scripts);
-            let last_synced_block = Arc::clone(&last_synced_block);
-            let progress_update = Arc::clone(&progress_update);
-            let synced_bundles = Arc::clone(&synced_bundles);
-
-            let thread = std::thread::spawn(move || {
-                cf_sync.capture_thread_for_sync(
-                    peer,
-                    |block_hash, filter| {
-                        if !filter
-                            .match_any(block_hash, &mut all_scripts.iter().map(AsRef::as_ref))?
-                        {
-                            return Ok(false);
-                        }
-
-                        let block_height = headers.get_height_for(block_hash)?.unwrap_or(0);
-                        let saved_correct_block = matches!(headers.get_full_block(block_height)?, Some(block) if &block.block_hash() == block_hash);
-
-                        if saved_correct_block {
-                            Ok(false)
-                        } else {
-                            let mut last_synced_block = last_synced_block.lock().unwrap();
-
-                            // If we download a block older than `last_synced_block`, we update it so that
-                            // we know to delete and re-process all txs starting from that height
-                            if block_height < *last_synced_block {
-                                *last_synced_block = block_height;
+        // keep going until all the bundles are processed, replacing the peers that fail
+        let mut rounds = 0;
+        while cf_sync.pending_bundles() > 0 {
+            let peers = self.peers.get_peers();
+            if peers.is_empty() {
+                return Err(CompactFiltersError::NoPeers.into());
+            }
+
+            let mut threads = Vec::with_capacity(peers.len());
+            for peer in peers {
+                let cf_sync = Arc::clone(&cf_sync);
+                let thread_peer = Arc::clone(&peer);
+                let headers = Arc::clone(&self.headers);
+                let all_scripts = Arc::clone(&all_scripts);
+                let last_synced_block = Arc::clone(&last_synced_block);
+                let progress_update = Arc::clone(&progress_update);
+                let synced_bundles = Arc::clone(&synced_bundles);
+
+                let thread = std::thread::spawn(move || {
+                    cf_sync.capture_thread_for_sync(
+                        thread_peer,
+                        |block_hash, filter| {
+                            if !filter
+                                .match_any(block_hash, &mut all_scripts.iter().map(AsRef::as_ref))?
+                            {
+                                return Ok(false);
                             }
 
-                            Ok(true)
-                        }
-                    },
-                    |index| {
-                        let synced_bundles = synced_bundles.fetch_add(1, Ordering::SeqCst);
-                        let local_filters_cost = synced_bundles as f32 * SYNC_FILTERS_COST;
-                        progress_update.lock().unwrap().update(
-                            (headers_cost + local_filters_cost) / total_cost * 100.0,
-                            Some(format!(
-                                "Synced filters {} - {}",
-                                index * 1000 + 1,
-                                (index + 1) * 1000
-                            )),
-                        )
-                    },
-                )
-            });
+                            let block_height = headers.get_height_for(block_hash)?.unwrap_or(0);
+                            let saved_correct_block = matches!(headers.get_full_block(block_height)?, Some(block) if &block.block_hash() == block_hash);
 
-            threads.push(thread);
-        }
+                            if saved_correct_block {
+                                Ok(false)
+                            } else {
+                                let mut last_synced_block = last_synced_block.lock().unwrap();
+
+                                // If we download a block older than `last_synced_block`, we update it so that
+                                // we know to delete and re-process all txs starting from that height
+                                if block_height < *last_synced_block {
+                                    *last_synced_block = block_height;
+                                }
 
-        for t in threads {
-            t.join().unwrap()?;
+                                Ok(true)
+                            }
+                        },
+                        |index| {
+                            let synced_bundles = synced_bundles.fetch_add(1, Ordering::SeqCst);
+                            let local_filters_cost = synced_bundles as f32 * SYNC_FILTERS_COST;
+                            progress_update.lock().unwrap().update(
+                                (headers_cost + local_filters_cost) / total_cost * 100.0,
+                                Some(format!(
+                                    "Synced filters {} - {}",
+                                    index * 1000 + 1,
+                                    (index + 1) * 1000
+                                )),
+                            )
+                        },
+                    )
+                });
+
+                threads.push((peer, thread));
+            }
+
+            // wait for all the threads before returning an error, so that none of them keeps
+            // running in the background
+            let mut error = None;
+            for (peer, t) in threads {
+                match t.join().unwrap() {
+                    Err(e) if rounds < MAX_PEER_RETRIES && self.peers.report(&peer, &e) => {}
+                    Err(e) => {
+                        error.get_or_insert(e);
+                    }
+                    Ok(()) => {}
+                }
+            }
+            if let Some(e) = error {
+                return Err(e.into());
+            }
+
+            rounds += 1;
         }
 
         progress_update.lock().unwrap().update(
@@ -362,16 +515,29 @@ impl Blockchain for CompactFiltersBlockchain {
             "Dropping transactions newer than `last_synced_block` = {}",
             last_synced_block
         );
+        let dropped_txs = database
+            .iter_txs(true)?
+            .into_iter()
+            .filter(|details| match details.confirmation_time {
+                Some(c) => c.height as usize >= last_synced_block,
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        // the transactions may have been disconnected by a reorg: they are added back if they are
+        // found in the new blocks or in the mempool
         let mut updates = database.begin_batch();
-        for details in database.iter_txs(false)? {
-            match details.confirmation_time {
-                Some(c) if (c.height as usize) < last_synced_block => continue,
-                _ => updates.del_tx(&details.txid, false)?,
-            };
-        }
+        database.drop_txs(&dropped_txs, &mut updates)?;
         database.commit_batch(updates)?;
 
-        match first_peer.ask_for_mempool() {
+        let mempool = self.peers.get_mempool();
+        if mempool.is_monitoring() {
+            mempool.set_watched(
+                database.iter_script_pubkeys(None)?,
+                database.iter_utxos()?.into_iter().map(|utxo| utxo.outpoint),
+            );
+        }
+
+        match self.peers.run(|peer| peer.ask_for_mempool()) {
             Err(CompactFiltersError::PeerBloomDisabled) => {
                 log::warn!("Peer has BLOOM disabled, we can't ask for the mempool")
             }
@@ -382,6 +548,8 @@ impl Blockchain for CompactFiltersBlockchain {
         let mut external_max_deriv = None;
 
         for (height, block) in self.headers.iter_full_blocks()? {
+            mempool.remove_confirmed(&block);
+
             for tx in &block.txdata {
                 self.process_tx(
                     database,
@@ -393,7 +561,7 @@ impl Blockchain for CompactFiltersBlockchain {
                 )?;
             }
         }
-        for tx in first_peer.get_mempool().iter_txs().iter() {
+        for tx in mempool.iter_txs().iter() {
             self.process_tx(
                 database,
                 tx,
@@ -422,9 +590,46 @@ impl Blockchain for CompactFiltersBlockchain {
             database.set_last_index(KeychainKind::Internal, first_int_new)?;
         }
 
+        if reorg_height.is_some() {
+            self.headers.clear_reorg_height()?;
+        }
+
         info!("Dropping blocks until {}", buried_height);
         self.headers.delete_blocks_until(buried_height)?;
 
+        // make sure we have fee statistics for the most recent blocks, even if they don't contain
+        // any of our transactions
+        for height in synced_height.saturating_sub(FEE_SAMPLE_BLOCKS - 1)..=synced_height {
+            if self.headers.get_fee_stats(height)?.is_some() {
+                continue;
+            }
+
+            let block_hash = match self.headers.get_block_hash(height)? {
+                Some(block_hash) => block_hash,
+                None => continue,
+            };
+            debug!("Downloading block {} to sample its fee rate", block_hash);
+            let block = self.peers.run(|peer| {
+                peer.get_block(block_hash)?
+                    .ok_or(CompactFiltersError::MissingBlock)
+            })?;
+            self.headers.save_fee_stats(
+                height,
+                &BlockFeeStats::from_block(&block, height, self.peers.get_network()),
+            )?;
+        }
+        self.headers
+            .delete_fee_stats_until(synced_height.saturating_sub(FEE_HISTORY_BLOCKS))?;
+
+        let learned = discovery::update_address_book(&self.headers, &self.peers.get_peers())?;
+        if self.discovery_enabled.load(Ordering::SeqCst) {
+            self.peers.add_spare_peers(
+                learned
+                    .into_iter()
+                    .map(|known| discovery::peer_config(known.address)),
+            );
+        }
+
         progress_update
             .lock()
             .unwrap()
@@ -434,25 +639,98 @@ impl Blockchain for CompactFiltersBlockchain {
     }
 
     fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error> {
-        Ok(self.peers[0]
+        Ok(self
+            .peers
             .get_mempool()
             .get_tx(&Inventory::Transaction(*txid)))
     }
 
     fn broadcast(&self, tx: &Transaction) -> Result<(), Error> {
-        self.peers[0].broadcast_tx(tx.clone())?;
+        // send the transaction to all the peers, it's enough if at least one of them accepts it
+        let mut last_error = None;
+        let mut sent = 0;
+        for peer in self.peers.get_peers() {
+            match peer.broadcast_tx(tx.clone()) {
+                Ok(_) => sent += 1,
+                Err(e) => {
+                    self.peers.report(&peer, &e);
+                    last_error = Some(e);
+                }
+            }
+        }
 
-        Ok(())
+        match (sent, last_error) {
+            (0, Some(e)) => Err(e.into()),
+            (0, None) => Err(CompactFiltersError::NoPeers.into()),
+            _ => Ok(()),
+        }
     }
 
     fn get_height(&self) -> Result<u32, Error> {
         Ok(self.headers.get_height()? as u32)
     }
 
-    fn estimate_fee(&self, _target: usize) -> Result<FeeRate, Error> {
-        // TODO
-        Ok(FeeRate::default())
+    fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
+        // never go below the min relay fee or the minimum fee rate our peers are willing to relay
+        let floor = self
+            .peers
+            .get_peers()
+            .iter()
+            .filter_map(|peer| peer.get_fee_filter())
+            .fold(FeeRate::default_min_relay_fee(), |floor, fee_filter| {
+                if fee_filter > floor {
+                    fee_filter
+                } else {
+                    floor
+                }
+            });
+
+        // look at the fee statistics of the last `target * FEE_SAMPLE_BLOCKS` blocks
+        let target = std::cmp::max(target, 1);
+        let window = std::cmp::min(target * FEE_SAMPLE_BLOCKS, FEE_HISTORY_BLOCKS);
+        let from_height = (self.headers.get_height()? + 1).saturating_sub(window);
+
+        let stats = self
+            .headers
+            .iter_fee_stats(from_height)?
+            .into_iter()
+            .map(|(_, stats)| stats)
+            .collect::<Vec<_>>();
+        let estimate = match select_fee_rate(&stats, target) {
+            Some(estimate) => estimate,
+            None => {
+                debug!("No fee statistics available, returning the minimum fee rate");
+                return Ok(floor);
+            }
+        };
+
+        Ok(if estimate > floor { estimate } else { floor })
+    }
+}
+
+/// Pick a fee rate for `target` blocks from the statistics of the last blocks
+///
+/// The average fee rates of the blocks are sorted and the one that would have been enough to be
+/// included in one every `target + 1` blocks is returned, or `None` if none of the blocks
+/// contains transactions other than the coinbase.
+///
+/// Ideally we would look at the minimum fee rate included in each block, but computing the fee of
+/// a single transaction requires the previous outputs it spends, which a light client generally
+/// doesn't have: the only figure we can derive from a block alone is the total amount of fees
+/// claimed by the coinbase, and thus the average fee rate.
+fn select_fee_rate(stats: &[BlockFeeStats], target: usize) -> Option<FeeRate> {
+    let mut fee_rates = stats
+        .iter()
+        .filter_map(BlockFeeStats::avg_fee_rate)
+        .map(|fee_rate| fee_rate.as_sat_vb())
+        .collect::<Vec<_>>();
+    if fee_rates.is_empty() {
+        return None;
     }
+    fee_rates.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    let index = (fee_rates.len() - 1) / (std::cmp::max(target, 1) + 1);
+    Some(FeeRate::from_sat_per_vb(fee_rates[index]))
 }
 
 /// Data to connect to a Bitcoin P2P peer
@@ -466,6 +744,28 @@ pub struct BitcoinPeerConfig {
     pub socks5_credentials: Option<(String, String)>,
 }
 
+impl BitcoinPeerConfig {
+    /// Connect to the peer, optionally through the socks5 proxy
+    pub fn connect(
+        &self,
+        mempool: Arc<Mempool>,
+        network: Network,
+    ) -> Result<Peer, CompactFiltersError> {
+        match &self.socks5 {
+            None => Peer::connect(&self.address, mempool, network),
+            Some(proxy) => Peer::connect_proxy(
+                self.address.as_str(),
+                proxy,
+                self.socks5_credentials
+                    .as_ref()
+                    .map(|(a, b)| (a.as_str(), b.as_str())),
+                mempool,
+                network,
+            ),
+        }
+    }
+}
+
 /// Configuration for a [`CompactFiltersBlockchain`]
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
 pub struct CompactFiltersBlockchainConfig {
@@ -477,36 +777,72 @@ pub struct CompactFiltersBlockchainConfig {
     pub storage_dir: String,
     /// Optionally skip initial `skip_blocks` blocks (default: 0)
     pub skip_blocks: Option<usize>,
+    /// Maximum number of peers connected at the same time, the remaining ones are used to
+    /// replace the peers that disconnect or misbehave (default: all of them, or at least 4 if
+    /// `discover_peers` is enabled)
+    pub max_peers: Option<usize>,
+    /// Look for more peers through the DNS seeds of the network and the addresses advertised by
+    /// the other peers, see [`CompactFiltersBlockchain::discover_peers`] (default: false)
+    pub discover_peers: Option<bool>,
+    /// Monitor the transactions relayed by the peers to find the unconfirmed ones relevant for the
+    /// wallet, see [`Mempool::with_monitoring`] (default: false)
+    pub monitor_mempool: Option<bool>,
 }
 
 impl ConfigurableBlockchain for CompactFiltersBlockchain {
     type Config = CompactFiltersBlockchainConfig;
 
     fn from_config(config: &Self::Config) -> Result<Self, Error> {
-        let mempool = Arc::new(Mempool::default());
-        let peers = config
-            .peers
-            .iter()
-            .map(|peer_conf| match &peer_conf.socks5 {
-                None => Peer::connect(&peer_conf.address, Arc::clone(&mempool), config.network),
-                Some(proxy) => Peer::connect_proxy(
-                    peer_conf.address.as_str(),
-                    proxy,
-                    peer_conf
-                        .socks5_credentials
-                        .as_ref()
-                        .map(|(a, b)| (a.as_str(), b.as_str())),
-                    Arc::clone(&mempool),
-                    config.network,
-                ),
-            })
-            .collect::<Result<_, _>>()?;
+        let headers = CompactFiltersBlockchain::open_store(&config.storage_dir, config.network)?;
+
+        let discovery_enabled = config.discover_peers.unwrap_or(false);
+        let mut candidates = config.peers.clone();
+        if discovery_enabled {
+            candidates.extend(discovery::find_peers(
+                &headers,
+                &SystemResolver,
+                config.network,
+            )?);
+        }
+        let max_peers = match config.max_peers {
+            Some(max_peers) => max_peers,
+            None if discovery_enabled => std::cmp::max(config.peers.len(), DEFAULT_DISCOVERY_PEERS),
+            None => config.peers.len(),
+        };
+
+        let mempool = Arc::new(match config.monitor_mempool {
+            Some(true) => Mempool::with_monitoring(),
+            _ => Mempool::default(),
+        });
+        let mut peers = Vec::new();
+        let mut spare_peers = Vec::new();
+        for peer_conf in candidates {
+            if peers.len() >= max_peers {
+                spare_peers.push(peer_conf);
+                continue;
+            }
 
-        Ok(CompactFiltersBlockchain::new(
+            match connect_peer(&peer_conf, Arc::clone(&mempool), config.network) {
+                Ok(peer) => peers.push((peer, Some(peer_conf))),
+                Err(CompactFiltersError::PeerMissingServices) => {
+                    warn!("Peer {} can't be used for the sync", peer_conf.address)
+                }
+                Err(e) => {
+                    warn!("Unable to connect to {}: {:?}", peer_conf.address, e);
+                    spare_peers.push(peer_conf);
+                }
+            }
+        }
+
+        let peers = PeerPool::new(peers, max_peers)?;
+        peers.add_spare_peers(spare_peers);
+
+        Ok(CompactFiltersBlockchain {
             peers,
-            &config.storage_dir,
-            config.skip_blocks,
-        )?)
+            headers,
+            skip_blocks: config.skip_blocks,
+            discovery_enabled: AtomicBool::new(discovery_enabled),
+        })
     }
 }
 
@@ -533,8 +869,15 @@ pub enum CompactFiltersError {
     /// The peer doesn't advertise the [`BLOOM`](bitcoin::network::constants::ServiceFlags::BLOOM) service flag
     PeerBloomDisabled,
 
+    /// The peer doesn't advertise the services required for the sync, like
+    /// [`COMPACT_FILTERS`](bitcoin::network::constants::ServiceFlags::COMPACT_FILTERS)
+    PeerMissingServices,
+
     /// No peers have been specified
     NoPeers,
+    /// Peers returned conflicting compact filter headers, and there's no majority to tell which
+    /// ones are correct
+    ConflictingFilterHeaders,
 
     /// Internal database error
     Db(rocksdb::Error),
@@ -567,3 +910,57 @@ impl From<crate::error::Error> for CompactFiltersError {
         CompactFiltersError::Global(Box::new(err))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stats_with_rate(sat_per_vb: u64) -> BlockFeeStats {
+        BlockFeeStats {
+            total_fees: sat_per_vb * 1_000,
+            total_weight: 4_000,
+        }
+    }
+
+    #[test]
+    fn test_select_fee_rate_empty() {
+        assert_eq!(select_fee_rate(&[], 1), None);
+        // blocks with only the coinbase don't have an average fee rate
+        assert_eq!(select_fee_rate(&[BlockFeeStats::default()], 1), None);
+    }
+
+    #[test]
+    fn test_select_fee_rate_target_1() {
+        let stats = [5, 1, 4, 2, 3]
+            .iter()
+            .map(|r| stats_with_rate(*r))
+            .collect::<Vec<_>>();
+
+        // sorted as [1, 2, 3, 4, 5], index (5 - 1) / 2 = 2
+        assert_eq!(
+            select_fee_rate(&stats, 1),
+            Some(FeeRate::from_sat_per_vb(3.0))
+        );
+        // a target of 0 is treated as 1
+        assert_eq!(
+            select_fee_rate(&stats, 0),
+            Some(FeeRate::from_sat_per_vb(3.0))
+        );
+    }
+
+    #[test]
+    fn test_select_fee_rate_large_target() {
+        let mut stats = (1..=10).map(stats_with_rate).collect::<Vec<_>>();
+        stats.push(BlockFeeStats::default());
+
+        assert_eq!(
+            select_fee_rate(&stats, 4),
+            Some(FeeRate::from_sat_per_vb(2.0))
+        );
+        // with a target larger than the number of samples we pick the lowest rate
+        assert_eq!(
+            select_fee_rate(&stats, 1008),
+            Some(FeeRate::from_sat_per_vb(1.0))
+        );
+    }
+}
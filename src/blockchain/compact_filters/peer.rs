@@ -9,8 +9,9 @@
 // You may not use this file except in accordance with one or both of these
 // licenses.
 
-use std::collections::HashMap;
-use std::net::{TcpStream, ToSocketAddrs};
+use std::collections::{HashMap, HashSet};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -28,18 +29,29 @@ use bitcoin::network::message_filter::*;
 use bitcoin::network::message_network::VersionMessage;
 use bitcoin::network::stream_reader::StreamReader;
 use bitcoin::network::Address;
-use bitcoin::{Block, Network, Transaction, Txid, Wtxid};
+use bitcoin::{Block, Network, OutPoint, Script, Transaction, Txid, Wtxid};
 
+use super::store::KnownAddress;
 use super::CompactFiltersError;
+use crate::FeeRate;
 
 type ResponsesMap = HashMap<&'static str, Arc<(Mutex<Vec<NetworkMessage>>, Condvar)>>;
 
 pub(crate) const TIMEOUT_SECS: u64 = 30;
+/// Maximum number of addresses learned from a peer that are kept in memory
+const MAX_LEARNED_ADDRESSES: usize = 1000;
+/// Time given to a peer to announce the transactions in its mempool after a `mempool` message
+const MEMPOOL_INV_WAIT_SECS: u64 = 5;
+/// Maximum number of relayed transactions remembered before the watched scripts are known
+const MAX_PENDING_TXIDS: usize = 100_000;
 
 /// Container for unconfirmed, but valid Bitcoin transactions
 ///
 /// It is normally shared between [`Peer`]s with the use of [`Arc`], so that transactions are not
 /// duplicated in memory.
+///
+/// A mempool created with [`Mempool::with_monitoring`] also collects the transactions relayed by
+/// the peers, keeping only the ones relevant for the wallet.
 #[derive(Debug, Default)]
 pub struct Mempool(RwLock<InnerMempool>);
 
@@ -47,6 +59,36 @@ pub struct Mempool(RwLock<InnerMempool>);
 struct InnerMempool {
     txs: HashMap<Txid, Transaction>,
     wtxids: HashMap<Wtxid, Txid>,
+
+    monitoring: bool,
+    watching: bool,
+    watched_scripts: HashSet<Script>,
+    watched_outpoints: HashSet<OutPoint>,
+    // txids of the transactions relayed before the first call to `set_watched`, that have to be
+    // downloaded again once we know which ones are relevant
+    pending_txids: HashSet<Txid>,
+}
+
+impl InnerMempool {
+    fn is_relevant(&self, tx: &Transaction) -> bool {
+        tx.output
+            .iter()
+            .any(|output| self.watched_scripts.contains(&output.script_pubkey))
+            || tx
+                .input
+                .iter()
+                .any(|input| self.watched_outpoints.contains(&input.previous_output))
+    }
+
+    fn watch_outputs(&mut self, tx: &Transaction) {
+        let txid = tx.txid();
+        for (vout, output) in tx.output.iter().enumerate() {
+            if self.watched_scripts.contains(&output.script_pubkey) {
+                self.watched_outpoints
+                    .insert(OutPoint::new(txid, vout as u32));
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -61,6 +103,115 @@ impl Mempool {
         Self::default()
     }
 
+    /// Create a new empty mempool that monitors the transactions relayed by the peers
+    ///
+    /// The [`Peer`]s connected with this mempool ask to receive the announcements of new
+    /// transactions, which are downloaded and kept if they spend from or send funds to the wallet.
+    /// Note that this means downloading every transaction relayed on the network.
+    pub fn with_monitoring() -> Self {
+        Mempool(RwLock::new(InnerMempool {
+            monitoring: true,
+            ..Default::default()
+        }))
+    }
+
+    /// Return whether or not the mempool monitors the transactions relayed by the peers
+    pub fn is_monitoring(&self) -> bool {
+        self.0.read().unwrap().monitoring
+    }
+
+    /// Replace the script_pubkeys and outpoints used to tell which relayed transactions are
+    /// relevant for the wallet
+    pub(crate) fn set_watched<S, O>(&self, scripts: S, outpoints: O)
+    where
+        S: IntoIterator<Item = Script>,
+        O: IntoIterator<Item = OutPoint>,
+    {
+        let mut guard = self.0.write().unwrap();
+        guard.watching = true;
+        guard.watched_scripts = scripts.into_iter().collect();
+        guard.watched_outpoints = outpoints.into_iter().collect();
+
+        // also watch the outputs of the unconfirmed transactions we already have
+        let txs = guard.txs.values().cloned().collect::<Vec<_>>();
+        for tx in &txs {
+            guard.watch_outputs(tx);
+        }
+    }
+
+    /// Add a transaction relayed by a peer, if it's relevant for the wallet
+    ///
+    /// Until the watched script_pubkeys and outpoints are set with [`Mempool::set_watched`] we
+    /// can't tell whether a transaction is relevant or not, so only its txid is kept, see
+    /// [`Mempool::get_pending_txids`].
+    fn add_relayed_tx(&self, tx: Transaction) {
+        let mut guard = self.0.write().unwrap();
+        if !guard.watching {
+            if guard.pending_txids.len() < MAX_PENDING_TXIDS {
+                guard.pending_txids.insert(tx.txid());
+            }
+            return;
+        }
+
+        guard.pending_txids.remove(&tx.txid());
+        if guard.txs.contains_key(&tx.txid()) || !guard.is_relevant(&tx) {
+            return;
+        }
+
+        log::debug!(
+            "Relayed tx {} is relevant, adding to the mempool",
+            tx.txid()
+        );
+
+        guard.watch_outputs(&tx);
+        guard.wtxids.insert(tx.wtxid(), tx.txid());
+        guard.txs.insert(tx.txid(), tx);
+    }
+
+    /// Return the txids of the transactions relayed before the watched script_pubkeys and
+    /// outpoints were set, which have to be requested again to the peers
+    pub(crate) fn get_pending_txids(&self) -> Vec<Txid> {
+        let guard = self.0.read().unwrap();
+        if !guard.watching {
+            return vec![];
+        }
+
+        guard.pending_txids.iter().cloned().collect()
+    }
+
+    /// Forget some of the pending txids, once they have been requested again
+    pub(crate) fn remove_pending_txids(&self, txids: &[Txid]) {
+        let mut guard = self.0.write().unwrap();
+        for txid in txids {
+            guard.pending_txids.remove(txid);
+        }
+    }
+
+    /// Remove the transactions confirmed in a block, and the ones that conflict with them
+    pub(crate) fn remove_confirmed(&self, block: &Block) {
+        let spent = block
+            .txdata
+            .iter()
+            .flat_map(|tx| tx.input.iter().map(|input| input.previous_output))
+            .collect::<HashSet<_>>();
+
+        let mut guard = self.0.write().unwrap();
+        let removed = guard
+            .txs
+            .values()
+            .filter(|tx| {
+                tx.input
+                    .iter()
+                    .any(|input| spent.contains(&input.previous_output))
+            })
+            .map(|tx| (tx.txid(), tx.wtxid()))
+            .collect::<Vec<_>>();
+        for (txid, wtxid) in removed {
+            guard.txs.remove(&txid);
+            guard.wtxids.remove(&wtxid);
+        }
+    }
+
     /// Add a transaction to the mempool
     ///
     /// Note that this doesn't propagate the transaction to other
@@ -119,6 +270,9 @@ pub struct Peer {
     connected: Arc<RwLock<bool>>,
 
     mempool: Arc<Mempool>,
+    mempool_requested: AtomicBool,
+    fee_filter: Arc<RwLock<Option<FeeRate>>>,
+    learned_addresses: Arc<Mutex<Vec<KnownAddress>>>,
 
     version: VersionMessage,
     network: Network,
@@ -134,9 +288,22 @@ impl Peer {
         mempool: Arc<Mempool>,
         network: Network,
     ) -> Result<Self, CompactFiltersError> {
-        let stream = TcpStream::connect(address)?;
+        let mut last_error = None;
+        for address in address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, Duration::from_secs(TIMEOUT_SECS)) {
+                Ok(stream) => return Peer::from_stream(stream, mempool, network),
+                Err(e) => last_error = Some(e),
+            }
+        }
 
-        Peer::from_stream(stream, mempool, network)
+        Err(last_error
+            .unwrap_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "could not resolve to any address",
+                )
+            })
+            .into())
     }
 
     /// Connect to a peer through a SOCKS5 proxy, optionally by using some credentials, specified
@@ -169,6 +336,8 @@ impl Peer {
         let writer = Arc::new(Mutex::new(stream.try_clone()?));
         let responses: Arc<RwLock<ResponsesMap>> = Arc::new(RwLock::new(HashMap::new()));
         let connected = Arc::new(RwLock::new(true));
+        let fee_filter = Arc::new(RwLock::new(None));
+        let learned_addresses = Arc::new(Mutex::new(Vec::new()));
 
         let mut locked_writer = writer.lock().unwrap();
 
@@ -176,6 +345,8 @@ impl Peer {
         let reader_thread_writer = Arc::clone(&writer);
         let reader_thread_mempool = Arc::clone(&mempool);
         let reader_thread_connected = Arc::clone(&connected);
+        let reader_thread_fee_filter = Arc::clone(&fee_filter);
+        let reader_thread_addresses = Arc::clone(&learned_addresses);
         let reader_thread = thread::spawn(move || {
             Self::reader_thread(
                 network,
@@ -184,6 +355,8 @@ impl Peer {
                 reader_thread_writer,
                 reader_thread_mempool,
                 reader_thread_connected,
+                reader_thread_fee_filter,
+                reader_thread_addresses,
             )
         });
 
@@ -196,31 +369,54 @@ impl Peer {
             port: 0,
         };
 
+        let mut version_message = VersionMessage::new(
+            ServiceFlags::WITNESS,
+            timestamp,
+            receiver,
+            sender,
+            nonce,
+            "MagicalBitcoinWallet".into(),
+            0,
+        );
+        // ask the peer to announce new transactions only if we are going to look at them
+        version_message.relay = mempool.is_monitoring();
+
         Self::_send(
             &mut locked_writer,
             network.magic(),
-            NetworkMessage::Version(VersionMessage::new(
-                ServiceFlags::WITNESS,
-                timestamp,
-                receiver,
-                sender,
-                nonce,
-                "MagicalBitcoinWallet".into(),
-                0,
-            )),
+            NetworkMessage::Version(version_message),
         )?;
-        let version = if let NetworkMessage::Version(version) =
-            Self::_recv(&responses, "version", None).unwrap()
-        {
-            version
-        } else {
-            return Err(CompactFiltersError::InvalidResponse);
+        let handshake_timeout = Some(Duration::from_secs(TIMEOUT_SECS));
+        let version = match Self::_recv(&responses, "version", handshake_timeout) {
+            Some(NetworkMessage::Version(version)) => version,
+            response => {
+                // stop the reader thread
+                let _ = locked_writer.shutdown(Shutdown::Both);
+
+                return Err(response
+                    .map(|_| CompactFiltersError::InvalidResponse)
+                    .unwrap_or(CompactFiltersError::Timeout));
+            }
         };
 
-        if let NetworkMessage::Verack = Self::_recv(&responses, "verack", None).unwrap() {
-            Self::_send(&mut locked_writer, network.magic(), NetworkMessage::Verack)?;
-        } else {
-            return Err(CompactFiltersError::InvalidResponse);
+        // BIP155: tell the peer we can receive `addrv2` messages, before our verack
+        Self::_send(
+            &mut locked_writer,
+            network.magic(),
+            NetworkMessage::SendAddrV2,
+        )?;
+
+        match Self::_recv(&responses, "verack", handshake_timeout) {
+            Some(NetworkMessage::Verack) => {
+                Self::_send(&mut locked_writer, network.magic(), NetworkMessage::Verack)?
+            }
+            response => {
+                let _ = locked_writer.shutdown(Shutdown::Both);
+
+                return Err(response
+                    .map(|_| CompactFiltersError::InvalidResponse)
+                    .unwrap_or(CompactFiltersError::Timeout));
+            }
         }
 
         std::mem::drop(locked_writer);
@@ -231,6 +427,9 @@ impl Peer {
             reader_thread,
             connected,
             mempool,
+            mempool_requested: AtomicBool::new(false),
+            fee_filter,
+            learned_addresses,
             version,
             network,
         })
@@ -299,12 +498,59 @@ impl Peer {
         Arc::clone(&self.mempool)
     }
 
+    /// Return the minimum fee rate of the transactions the peer is willing to relay, if it sent
+    /// us a [`feefilter`](NetworkMessage::FeeFilter) message
+    pub fn get_fee_filter(&self) -> Option<FeeRate> {
+        *self.fee_filter.read().unwrap()
+    }
+
     /// Return whether or not the peer is still connected
     pub fn is_connected(&self) -> bool {
         *self.connected.read().unwrap()
     }
 
+    /// Return the addresses of other peers learned from the `addr` and `addrv2` messages sent by
+    /// this peer since the last call
+    pub fn take_learned_addresses(&self) -> Vec<KnownAddress> {
+        std::mem::take(&mut *self.learned_addresses.lock().unwrap())
+    }
+
+    /// Ask the peer to send us the addresses of other peers it knows
+    ///
+    /// The addresses received are returned by [`Peer::take_learned_addresses`].
+    pub fn ask_for_addresses(&self) -> Result<(), CompactFiltersError> {
+        self.send(NetworkMessage::GetAddr)
+    }
+
+    /// Send a [`ping`](NetworkMessage::Ping) to the peer and wait for its reply
+    ///
+    /// Since the peer processes our messages in order, this can be used to make sure that it
+    /// has replied to the messages sent before.
+    pub fn ping(&self) -> Result<(), CompactFiltersError> {
+        let nonce = thread_rng().gen();
+        self.send(NetworkMessage::Ping(nonce))?;
+
+        loop {
+            match self.recv("pong", Some(Duration::from_secs(TIMEOUT_SECS)))? {
+                None => return Err(CompactFiltersError::Timeout),
+                Some(NetworkMessage::Pong(n)) if n == nonce => return Ok(()),
+                Some(NetworkMessage::Pong(_)) => continue,
+                Some(_) => return Err(CompactFiltersError::InvalidResponse),
+            }
+        }
+    }
+
+    /// Close the connection with the peer
+    pub fn disconnect(&self) {
+        if let Err(e) = self.writer.lock().unwrap().shutdown(Shutdown::Both) {
+            log::debug!("Error while disconnecting: {:?}", e);
+        }
+
+        *self.connected.write().unwrap() = false;
+    }
+
     /// Internal function called once the `reader_thread` is spawned
+    #[allow(clippy::too_many_arguments)]
     fn reader_thread(
         network: Network,
         connection: TcpStream,
@@ -312,6 +558,8 @@ impl Peer {
         reader_thread_writer: Arc<Mutex<TcpStream>>,
         reader_thread_mempool: Arc<Mempool>,
         reader_thread_connected: Arc<RwLock<bool>>,
+        reader_thread_fee_filter: Arc<RwLock<Option<FeeRate>>>,
+        reader_thread_addresses: Arc<Mutex<Vec<KnownAddress>>>,
     ) {
         macro_rules! check_disconnect {
             ($call:expr) => {
@@ -350,6 +598,65 @@ impl Peer {
                     continue;
                 }
                 NetworkMessage::Alert(_) => continue,
+                NetworkMessage::FeeFilter(sat_per_kvb) => {
+                    *reader_thread_fee_filter.write().unwrap() =
+                        Some(FeeRate::from_sat_per_vb(sat_per_kvb as f32 / 1000.0));
+
+                    continue;
+                }
+                NetworkMessage::Addr(ref addresses) => {
+                    let addresses = addresses.iter().filter_map(|(time, address)| {
+                        Some(KnownAddress {
+                            address: address.socket_addr().ok()?,
+                            services: address.services,
+                            last_seen: *time,
+                        })
+                    });
+                    Self::learn_addresses(&reader_thread_addresses, addresses);
+
+                    continue;
+                }
+                NetworkMessage::AddrV2(ref addresses) => {
+                    // addresses that can't be represented as a `SocketAddr`, like Tor ones, are
+                    // ignored
+                    let addresses = addresses.iter().filter_map(|address| {
+                        Some(KnownAddress {
+                            address: address.socket_addr().ok()?,
+                            services: address.services,
+                            last_seen: address.time,
+                        })
+                    });
+                    Self::learn_addresses(&reader_thread_addresses, addresses);
+
+                    continue;
+                }
+                NetworkMessage::Inv(ref inv) if reader_thread_mempool.is_monitoring() => {
+                    let getdata = inv
+                        .iter()
+                        .filter_map(|item| match item {
+                            Inventory::Transaction(txid) | Inventory::WitnessTransaction(txid)
+                                if !reader_thread_mempool.has_tx(txid) =>
+                            {
+                                Some(Inventory::WitnessTransaction(*txid))
+                            }
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+                    if !getdata.is_empty() {
+                        check_disconnect!(Self::_send(
+                            &mut reader_thread_writer.lock().unwrap(),
+                            network.magic(),
+                            NetworkMessage::GetData(getdata),
+                        ));
+                    }
+
+                    continue;
+                }
+                NetworkMessage::Tx(tx) if reader_thread_mempool.is_monitoring() => {
+                    reader_thread_mempool.add_relayed_tx(tx);
+
+                    continue;
+                }
                 NetworkMessage::GetData(ref inv) => {
                     let (found, not_found): (Vec<_>, Vec<_>) = inv
                         .iter()
@@ -389,6 +696,19 @@ impl Peer {
         }
     }
 
+    fn learn_addresses<I: Iterator<Item = KnownAddress>>(
+        learned_addresses: &Mutex<Vec<KnownAddress>>,
+        addresses: I,
+    ) {
+        let mut learned_addresses = learned_addresses.lock().unwrap();
+        learned_addresses.extend(addresses);
+
+        let excess = learned_addresses
+            .len()
+            .saturating_sub(MAX_LEARNED_ADDRESSES);
+        learned_addresses.drain(..excess);
+    }
+
     /// Send a raw Bitcoin message to the peer
     pub fn send(&self, payload: NetworkMessage) -> Result<(), CompactFiltersError> {
         let mut writer = self.writer.lock().unwrap();
@@ -527,6 +847,42 @@ impl InvPeer for Peer {
     }
 
     fn ask_for_mempool(&self) -> Result<(), CompactFiltersError> {
+        if self.mempool.is_monitoring() {
+            // the transactions are requested and filtered by the reader thread, here we only have
+            // to wait for it. The peer keeps announcing the new transactions once connected, so
+            // its mempool only has to be requested once
+            let mut wait = false;
+
+            let pending_txids = self.mempool.get_pending_txids();
+            if !pending_txids.is_empty() {
+                self.send(NetworkMessage::GetData(
+                    pending_txids
+                        .iter()
+                        .map(|txid| Inventory::WitnessTransaction(*txid))
+                        .collect(),
+                ))?;
+                wait = true;
+            }
+
+            if self.version.services.has(ServiceFlags::BLOOM)
+                && !self.mempool_requested.swap(true, Ordering::SeqCst)
+            {
+                self.send(NetworkMessage::MemPool)?;
+                thread::sleep(Duration::from_secs(MEMPOOL_INV_WAIT_SECS));
+                wait = true;
+            }
+
+            if wait {
+                // the first ping makes sure that the `getdata` for the announced transactions
+                // have been sent, the second one that the transactions have been received
+                self.ping()?;
+                self.ping()?;
+            }
+            self.mempool.remove_pending_txids(&pending_txids);
+
+            return Ok(());
+        }
+
         if !self.version.services.has(ServiceFlags::BLOOM) {
             return Err(CompactFiltersError::PeerBloomDisabled);
         }
@@ -570,3 +926,134 @@ impl InvPeer for Peer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bitcoin::{BlockHeader, TxIn, TxOut};
+
+    use super::*;
+
+    fn make_tx(inputs: Vec<OutPoint>, outputs: Vec<Script>) -> Transaction {
+        Transaction {
+            version: 1,
+            lock_time: 0,
+            input: inputs
+                .into_iter()
+                .map(|previous_output| TxIn {
+                    previous_output,
+                    script_sig: Script::new(),
+                    sequence: 0xFFFFFFFF,
+                    witness: vec![],
+                })
+                .collect(),
+            output: outputs
+                .into_iter()
+                .map(|script_pubkey| TxOut {
+                    value: 10_000,
+                    script_pubkey,
+                })
+                .collect(),
+        }
+    }
+
+    fn make_block(txdata: Vec<Transaction>) -> Block {
+        Block {
+            header: BlockHeader {
+                version: 1,
+                prev_blockhash: Default::default(),
+                merkle_root: Default::default(),
+                time: 0,
+                bits: 0,
+                nonce: 0,
+            },
+            txdata,
+        }
+    }
+
+    fn script(n: u8) -> Script {
+        Script::from(vec![n])
+    }
+
+    fn outpoint(n: u8) -> OutPoint {
+        OutPoint::new(make_tx(vec![], vec![script(n)]).txid(), 0)
+    }
+
+    #[test]
+    fn test_is_relevant() {
+        let mempool = Mempool::with_monitoring();
+        mempool.set_watched(vec![script(1)], vec![outpoint(1)]);
+        let inner = mempool.0.read().unwrap();
+
+        assert!(inner.is_relevant(&make_tx(vec![outpoint(2)], vec![script(1)])));
+        assert!(inner.is_relevant(&make_tx(vec![outpoint(1)], vec![script(2)])));
+        assert!(!inner.is_relevant(&make_tx(vec![outpoint(2)], vec![script(2)])));
+    }
+
+    #[test]
+    fn test_add_relayed_tx() {
+        let mempool = Mempool::with_monitoring();
+        mempool.set_watched(vec![script(1)], vec![]);
+
+        let receive = make_tx(vec![outpoint(2)], vec![script(1)]);
+        mempool.add_relayed_tx(receive.clone());
+        mempool.add_relayed_tx(make_tx(vec![outpoint(3)], vec![script(2)]));
+        assert_eq!(mempool.iter_txs(), vec![receive.clone()]);
+
+        // the outputs of the relevant transactions are watched as well
+        let spend = make_tx(vec![OutPoint::new(receive.txid(), 0)], vec![script(2)]);
+        mempool.add_relayed_tx(spend.clone());
+        assert!(mempool.has_tx(&spend.txid()));
+    }
+
+    #[test]
+    fn test_add_relayed_tx_before_set_watched() {
+        let mempool = Mempool::with_monitoring();
+
+        let tx = make_tx(vec![outpoint(2)], vec![script(1)]);
+        mempool.add_relayed_tx(tx.clone());
+        assert!(!mempool.has_tx(&tx.txid()));
+        // we don't know yet what we are looking for
+        assert!(mempool.get_pending_txids().is_empty());
+
+        mempool.set_watched(vec![script(1)], vec![]);
+        assert_eq!(mempool.get_pending_txids(), vec![tx.txid()]);
+
+        // downloaded again from a peer
+        mempool.add_relayed_tx(tx.clone());
+        assert!(mempool.has_tx(&tx.txid()));
+        assert!(mempool.get_pending_txids().is_empty());
+
+        let other = make_tx(vec![outpoint(3)], vec![script(2)]);
+        mempool
+            .0
+            .write()
+            .unwrap()
+            .pending_txids
+            .insert(other.txid());
+        mempool.remove_pending_txids(&[other.txid()]);
+        assert!(mempool.get_pending_txids().is_empty());
+    }
+
+    #[test]
+    fn test_remove_confirmed() {
+        let mempool = Mempool::new();
+
+        let confirmed = make_tx(vec![outpoint(1)], vec![script(1)]);
+        let conflict = make_tx(vec![outpoint(2), outpoint(1)], vec![script(2)]);
+        let unrelated = make_tx(vec![outpoint(3)], vec![script(3)]);
+        mempool.add_tx(confirmed.clone());
+        mempool.add_tx(conflict.clone());
+        mempool.add_tx(unrelated.clone());
+
+        mempool.remove_confirmed(&make_block(vec![confirmed.clone()]));
+
+        assert!(!mempool.has_tx(&confirmed.txid()));
+        assert!(!mempool.has_tx(&conflict.txid()));
+        assert!(mempool.has_tx(&unrelated.txid()));
+        assert_eq!(mempool.get_tx(&Inventory::WTx(confirmed.wtxid())), None,);
+        assert_eq!(
+            mempool.get_tx(&Inventory::WTx(unrelated.wtxid())),
+            Some(unrelated)
+        );
+    }
+}
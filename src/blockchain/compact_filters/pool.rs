@@ -0,0 +1,429 @@
+// Bitcoin Dev Kit
+// Written in 2021 by Alekos Filini <alekos.filini@gmail.com>
+//
+// Copyright (c) 2020-2021 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use bitcoin::Network;
+
+use super::discovery::required_services;
+use super::peer::{Mempool, Peer};
+use super::{BitcoinPeerConfig, CompactFiltersError};
+
+/// Maximum number of times a request is retried with a different peer
+pub(crate) const MAX_PEER_RETRIES: usize = 5;
+/// Time a peer that failed is kept out of the spare peers before trying to connect to it again
+const PEER_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct PoolPeer {
+    peer: Arc<Peer>,
+    config: Option<BitcoinPeerConfig>,
+}
+
+/// Set of peers used by a [`CompactFiltersBlockchain`](super::CompactFiltersBlockchain)
+///
+/// Peers that disconnect, time out or misbehave are removed from the pool and replaced with a new
+/// connection to one of the spare peers, if any is available. The address of a peer that
+/// misbehaves is banned, so that the pool never tries to connect to it again, while the other
+/// ones are only added back to the spare peers after a while.
+#[derive(Debug)]
+pub struct PeerPool {
+    peers: RwLock<Vec<PoolPeer>>,
+    spare: Mutex<VecDeque<BitcoinPeerConfig>>,
+    // peers that failed, with the time they can be added back to the spare ones
+    backoff: Mutex<Vec<(Instant, BitcoinPeerConfig)>>,
+    banned: Mutex<Vec<String>>,
+    max_peers: usize,
+
+    mempool: Arc<Mempool>,
+    network: Network,
+}
+
+impl PeerPool {
+    /// Create a new pool with a list of connected peers, optionally along with the configuration
+    /// used to connect to them
+    ///
+    /// Spare peers are connected when there are less than `max_peers` peers in the pool.
+    pub fn new(
+        peers: Vec<(Peer, Option<BitcoinPeerConfig>)>,
+        max_peers: usize,
+    ) -> Result<Self, CompactFiltersError> {
+        if peers.is_empty() {
+            return Err(CompactFiltersError::NoPeers);
+        }
+
+        let mempool = peers[0].0.get_mempool();
+        let network = peers[0].0.get_network();
+
+        Ok(PeerPool {
+            max_peers,
+            peers: RwLock::new(
+                peers
+                    .into_iter()
+                    .map(|(peer, config)| PoolPeer {
+                        peer: Arc::new(peer),
+                        config,
+                    })
+                    .collect(),
+            ),
+            spare: Mutex::new(VecDeque::new()),
+            backoff: Mutex::new(Vec::new()),
+            banned: Mutex::new(Vec::new()),
+            mempool,
+            network,
+        })
+    }
+
+    /// Add some peers that will be connected to replace the ones removed from the pool
+    ///
+    /// Peers that are banned, already connected, already in the list of spare peers or waiting to
+    /// be added back to it after a failure are ignored.
+    pub fn add_spare_peers<I: IntoIterator<Item = BitcoinPeerConfig>>(&self, spare: I) {
+        let peers = self.peers.read().unwrap();
+        let banned = self.banned.lock().unwrap();
+        let backoff = self.backoff.lock().unwrap();
+        let mut spare_peers = self.spare.lock().unwrap();
+
+        for config in spare {
+            let known = banned.contains(&config.address)
+                || spare_peers.iter().any(|s| s.address == config.address)
+                || backoff.iter().any(|(_, b)| b.address == config.address)
+                || peers
+                    .iter()
+                    .filter_map(|p| p.config.as_ref())
+                    .any(|c| c.address == config.address);
+            if !known {
+                spare_peers.push_back(config);
+            }
+        }
+    }
+
+    /// Return the Bitcoin [`Network`] in use
+    pub fn get_network(&self) -> Network {
+        self.network
+    }
+
+    /// Return the mempool shared by the peers in the pool
+    pub fn get_mempool(&self) -> Arc<Mempool> {
+        Arc::clone(&self.mempool)
+    }
+
+    /// Return the list of connected peers
+    ///
+    /// Peers that have disconnected since the last call are replaced with spare ones, and the
+    /// pool is filled again with the peers whose backoff has expired.
+    pub fn get_peers(&self) -> Vec<Arc<Peer>> {
+        let disconnected = self
+            .peers
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|p| !p.peer.is_connected())
+            .map(|p| Arc::clone(&p.peer))
+            .collect::<Vec<_>>();
+        for peer in disconnected {
+            log::debug!("Peer disconnected, removing it from the pool");
+            self.remove(&peer, false);
+        }
+        self.fill();
+
+        self.peers
+            .read()
+            .unwrap()
+            .iter()
+            .map(|p| Arc::clone(&p.peer))
+            .collect()
+    }
+
+    /// Report an error returned by a request sent to a peer
+    ///
+    /// If the error was caused by the peer, the peer is disconnected and replaced. If it sent an
+    /// invalid response, its address is also banned, otherwise it's only used again after a
+    /// while.
+    ///
+    /// Returns whether the error was caused by the peer, and the request can thus be retried with
+    /// a different one.
+    pub fn report(&self, peer: &Arc<Peer>, error: &CompactFiltersError) -> bool {
+        match error {
+            CompactFiltersError::Timeout
+            | CompactFiltersError::NotConnected
+            | CompactFiltersError::MissingBlock
+            | CompactFiltersError::Io(_) => {
+                log::warn!("Replacing peer after error: {:?}", error);
+                self.remove(peer, false);
+            }
+            CompactFiltersError::InvalidResponse
+            | CompactFiltersError::InvalidHeaders
+            | CompactFiltersError::InvalidFilterHeader
+            | CompactFiltersError::InvalidFilter => {
+                log::warn!("Banning peer after error: {:?}", error);
+                self.remove(peer, true);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Run `f` with one of the peers in the pool, retrying with a different one if the error
+    /// returned was caused by the peer
+    pub fn run<T, F>(&self, f: F) -> Result<T, CompactFiltersError>
+    where
+        F: Fn(&Arc<Peer>) -> Result<T, CompactFiltersError>,
+    {
+        let mut attempts = 0;
+        loop {
+            let peer = self
+                .get_peers()
+                .into_iter()
+                .next()
+                .ok_or(CompactFiltersError::NoPeers)?;
+
+            match f(&peer) {
+                Err(e) if attempts < MAX_PEER_RETRIES && self.report(&peer, &e) => attempts += 1,
+                res => return res,
+            }
+        }
+    }
+
+    fn remove(&self, peer: &Arc<Peer>, ban: bool) {
+        let removed = {
+            let mut peers = self.peers.write().unwrap();
+            match peers.iter().position(|p| Arc::ptr_eq(&p.peer, peer)) {
+                Some(index) => peers.remove(index),
+                // already removed by another thread
+                None => return,
+            }
+        };
+
+        removed.peer.disconnect();
+        if let Some(config) = removed.config {
+            if ban {
+                self.banned.lock().unwrap().push(config.address);
+            } else {
+                self.back_off(config);
+            }
+        }
+
+        self.fill();
+    }
+
+    /// Keep a peer that failed out of the spare peers for [`PEER_BACKOFF`]
+    fn back_off(&self, config: BitcoinPeerConfig) {
+        self.backoff
+            .lock()
+            .unwrap()
+            .push((Instant::now() + PEER_BACKOFF, config));
+    }
+
+    /// Connect to spare peers until the pool is full, trying each one of them at most once
+    fn fill(&self) {
+        // the peers whose backoff has expired can be tried again
+        {
+            let now = Instant::now();
+            let mut backoff = self.backoff.lock().unwrap();
+            let mut spare = self.spare.lock().unwrap();
+            let (expired, waiting): (Vec<_>, Vec<_>) =
+                backoff.drain(..).partition(|(until, _)| *until <= now);
+            *backoff = waiting;
+            spare.extend(expired.into_iter().map(|(_, config)| config));
+        }
+
+        let mut attempts = self.spare.lock().unwrap().len();
+        while attempts > 0 && self.peers.read().unwrap().len() < self.max_peers {
+            attempts -= 1;
+
+            let config = match self.spare.lock().unwrap().pop_front() {
+                Some(config) => config,
+                None => break,
+            };
+            if self.banned.lock().unwrap().contains(&config.address) {
+                continue;
+            }
+
+            match connect_peer(&config, Arc::clone(&self.mempool), self.network) {
+                Ok(peer) => {
+                    log::info!("Connected to spare peer {}", config.address);
+                    self.peers.write().unwrap().push(PoolPeer {
+                        peer: Arc::new(peer),
+                        config: Some(config),
+                    });
+                }
+                Err(CompactFiltersError::PeerMissingServices) => {
+                    log::debug!("Peer {} can't be used for the sync", config.address);
+                    self.banned.lock().unwrap().push(config.address);
+                }
+                Err(e) => {
+                    log::debug!("Unable to connect to {}: {:?}", config.address, e);
+                    self.back_off(config);
+                }
+            }
+        }
+    }
+}
+
+/// Connect to a peer, making sure it advertises the services required for the sync
+pub(crate) fn connect_peer(
+    config: &BitcoinPeerConfig,
+    mempool: Arc<Mempool>,
+    network: Network,
+) -> Result<Peer, CompactFiltersError> {
+    let peer = config.connect(mempool, network)?;
+    if !peer.get_version().services.has(required_services()) {
+        peer.disconnect();
+        return Err(CompactFiltersError::PeerMissingServices);
+    }
+
+    Ok(peer)
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use bitcoin::consensus::Encodable;
+    use bitcoin::network::message::{NetworkMessage, RawNetworkMessage};
+    use bitcoin::network::message_network::VersionMessage;
+    use bitcoin::network::stream_reader::StreamReader;
+
+    use super::*;
+
+    // Listen on a local port like a node that advertises the services required for the sync: the
+    // handshake is completed and every other message is ignored
+    fn fake_node() -> BitcoinPeerConfig {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+
+                thread::spawn(move || {
+                    let mut writer = stream.try_clone().unwrap();
+                    let mut reader = StreamReader::new(stream, None);
+                    while let Ok(message) = reader.read_next::<RawNetworkMessage>() {
+                        let replies = match message.payload {
+                            NetworkMessage::Version(version) => vec![
+                                NetworkMessage::Version(VersionMessage {
+                                    services: required_services(),
+                                    ..version
+                                }),
+                                NetworkMessage::Verack,
+                            ],
+                            _ => vec![],
+                        };
+                        for payload in replies {
+                            let raw_message = RawNetworkMessage {
+                                magic: message.magic,
+                                payload,
+                            };
+                            if raw_message.consensus_encode(&mut writer).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        BitcoinPeerConfig {
+            address,
+            socks5: None,
+            socks5_credentials: None,
+        }
+    }
+
+    fn make_pool(peers: &[&BitcoinPeerConfig], max_peers: usize) -> PeerPool {
+        let mempool = Arc::new(Mempool::new());
+        let peers = peers
+            .iter()
+            .map(|config| {
+                let peer = connect_peer(config, Arc::clone(&mempool), Network::Regtest).unwrap();
+                (peer, Some((*config).clone()))
+            })
+            .collect();
+
+        PeerPool::new(peers, max_peers).unwrap()
+    }
+
+    fn connected(pool: &PeerPool) -> Vec<String> {
+        pool.peers
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|p| p.config.as_ref().map(|c| c.address.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_ban_misbehaving_peer() {
+        let (node1, node2) = (fake_node(), fake_node());
+        let pool = make_pool(&[&node1], 1);
+        pool.add_spare_peers(vec![node2.clone()]);
+
+        let peer = pool.get_peers().pop().unwrap();
+        assert!(pool.report(&peer, &CompactFiltersError::InvalidFilterHeader));
+        assert!(!peer.is_connected());
+        assert_eq!(connected(&pool), vec![node2.address.clone()]);
+        assert_eq!(*pool.banned.lock().unwrap(), vec![node1.address.clone()]);
+
+        // a banned peer is never used again
+        pool.add_spare_peers(vec![node1]);
+        assert!(pool.spare.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_errors_not_caused_by_the_peer() {
+        let node = fake_node();
+        let pool = make_pool(&[&node], 1);
+
+        let peer = pool.get_peers().pop().unwrap();
+        assert!(!pool.report(&peer, &CompactFiltersError::NoPeers));
+        assert!(peer.is_connected());
+        assert_eq!(connected(&pool), vec![node.address]);
+    }
+
+    #[test]
+    fn test_replace_timed_out_peer() {
+        let (node1, node2) = (fake_node(), fake_node());
+        let pool = make_pool(&[&node1], 1);
+        pool.add_spare_peers(vec![node2.clone()]);
+
+        let peer = pool.get_peers().pop().unwrap();
+        assert!(pool.report(&peer, &CompactFiltersError::Timeout));
+        assert!(!peer.is_connected());
+        assert_eq!(connected(&pool), vec![node2.address.clone()]);
+        assert!(pool.banned.lock().unwrap().is_empty());
+
+        // the peer that timed out isn't used again right away
+        pool.add_spare_peers(vec![node1.clone()]);
+        assert!(pool.spare.lock().unwrap().is_empty());
+        let peer = pool.get_peers().pop().unwrap();
+        assert!(pool.report(&peer, &CompactFiltersError::Timeout));
+        assert!(connected(&pool).is_empty());
+        assert!(pool.get_peers().is_empty());
+
+        // once its backoff expires it can be connected again
+        for (until, config) in pool.backoff.lock().unwrap().iter_mut() {
+            if config.address == node1.address {
+                *until = Instant::now();
+            }
+        }
+        assert_eq!(pool.get_peers().len(), 1);
+        assert_eq!(connected(&pool), vec![node1.address]);
+    }
+}
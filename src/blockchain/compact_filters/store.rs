@@ -13,6 +13,7 @@ use std::convert::TryInto;
 use std::fmt;
 use std::io::{Read, Write};
 use std::marker::PhantomData;
+use std::net::SocketAddr;
 use std::ops::Deref;
 use std::sync::Arc;
 use std::sync::RwLock;
@@ -22,10 +23,13 @@ use rand::{thread_rng, Rng};
 
 use rocksdb::{Direction, IteratorMode, ReadOptions, WriteBatch, DB};
 
+use bitcoin::blockdata::constants::COIN_VALUE;
 use bitcoin::consensus::{deserialize, encode::VarInt, serialize, Decodable, Encodable};
 use bitcoin::hash_types::{FilterHash, FilterHeader};
 use bitcoin::hashes::hex::FromHex;
 use bitcoin::hashes::Hash;
+use bitcoin::network::constants::ServiceFlags;
+use bitcoin::network::Address;
 use bitcoin::util::bip158::BlockFilter;
 use bitcoin::util::uint::Uint256;
 use bitcoin::Block;
@@ -36,6 +40,7 @@ use bitcoin::Network;
 use lazy_static::lazy_static;
 
 use super::CompactFiltersError;
+use crate::FeeRate;
 
 lazy_static! {
     static ref MAINNET_GENESIS: Block = deserialize(&Vec::<u8>::from_hex("0100000000000000000000000000000000000000000000000000000000000000000000003BA3EDFD7A7B12B27AC72C3E67768F617FC81BC3888A51323A9FB8AA4B1E5E4A29AB5F49FFFF001D1DAC2B7C0101000000010000000000000000000000000000000000000000000000000000000000000000FFFFFFFF4D04FFFF001D0104455468652054696D65732030332F4A616E2F32303039204368616E63656C6C6F72206F6E206272696E6B206F66207365636F6E64206261696C6F757420666F722062616E6B73FFFFFFFF0100F2052A01000000434104678AFDB0FE5548271967F1A67130B7105CD6A828E03909A67962E0EA1F61DEB649F6BC3F4CEF38C4F35504E51EC112DE5C384DF7BA0B8D578A4C702B6BF11D5FAC00000000").unwrap()).unwrap();
@@ -58,6 +63,9 @@ pub enum StoreEntry {
     Block(Option<usize>),
     BlockHeaderIndex(Option<BlockHash>),
     CFilterTable((u8, Option<usize>)),
+    BlockFeeStats(Option<usize>),
+    KnownAddress(Option<SocketAddr>),
+    ReorgHeight,
 }
 
 impl StoreEntry {
@@ -67,6 +75,9 @@ impl StoreEntry {
             StoreEntry::Block(_) => b"x",
             StoreEntry::BlockHeaderIndex(_) => b"i",
             StoreEntry::CFilterTable(_) => b"t",
+            StoreEntry::BlockFeeStats(_) => b"f",
+            StoreEntry::KnownAddress(_) => b"a",
+            StoreEntry::ReorgHeight => b"r",
         }
         .to_vec()
     }
@@ -78,6 +89,12 @@ impl StoreEntry {
                 prefix.extend_from_slice(&height.to_be_bytes())
             }
             StoreEntry::Block(Some(height)) => prefix.extend_from_slice(&height.to_be_bytes()),
+            StoreEntry::BlockFeeStats(Some(height)) => {
+                prefix.extend_from_slice(&height.to_be_bytes())
+            }
+            StoreEntry::KnownAddress(Some(address)) => {
+                prefix.extend_from_slice(address.to_string().as_bytes())
+            }
             StoreEntry::BlockHeaderIndex(Some(hash)) => {
                 prefix.extend_from_slice(&hash.into_inner())
             }
@@ -214,6 +231,111 @@ impl Decodable for BundleStatus {
     }
 }
 
+/// Fee statistics of a block, computed when the block is downloaded
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BlockFeeStats {
+    /// Sum of the fees paid by the transactions in the block
+    pub total_fees: u64,
+    /// Sum of the weight of the transactions in the block, excluding the coinbase
+    pub total_weight: u64,
+}
+
+impl BlockFeeStats {
+    /// Compute the statistics of a block given its height
+    ///
+    /// Since the previous outputs spent by a transaction are generally not available, the total
+    /// fees are derived from the coinbase, by subtracting the block subsidy from the value it
+    /// claims.
+    pub fn from_block(block: &Block, height: usize, network: Network) -> Self {
+        let halving_interval = match network {
+            Network::Regtest => 150,
+            _ => 210_000,
+        };
+        let halvings = height / halving_interval;
+        let subsidy = if halvings >= 64 {
+            0
+        } else {
+            (50 * COIN_VALUE) >> halvings
+        };
+
+        let (coinbase, txs) = match block.txdata.split_first() {
+            Some((coinbase, txs)) => (coinbase, txs),
+            None => return BlockFeeStats::default(),
+        };
+        let claimed: u64 = coinbase.output.iter().map(|o| o.value).sum();
+
+        BlockFeeStats {
+            total_fees: claimed.saturating_sub(subsidy),
+            total_weight: txs.iter().map(|tx| tx.get_weight() as u64).sum(),
+        }
+    }
+
+    /// Return the average fee rate paid by the transactions in the block, or `None` if the block
+    /// only contains the coinbase
+    pub fn avg_fee_rate(&self) -> Option<FeeRate> {
+        if self.total_weight == 0 {
+            return None;
+        }
+
+        let vbytes = self.total_weight as f32 / 4.0;
+        Some(FeeRate::from_sat_per_vb(self.total_fees as f32 / vbytes))
+    }
+}
+
+impl Encodable for BlockFeeStats {
+    fn consensus_encode<W: Write>(&self, mut e: W) -> Result<usize, std::io::Error> {
+        let mut written = 0;
+
+        written += self.total_fees.consensus_encode(&mut e)?;
+        written += self.total_weight.consensus_encode(&mut e)?;
+
+        Ok(written)
+    }
+}
+
+impl Decodable for BlockFeeStats {
+    fn consensus_decode<D: Read>(mut d: D) -> Result<Self, bitcoin::consensus::encode::Error> {
+        Ok(BlockFeeStats {
+            total_fees: u64::consensus_decode(&mut d)?,
+            total_weight: u64::consensus_decode(&mut d)?,
+        })
+    }
+}
+
+/// Address of a peer learned from the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownAddress {
+    /// Address of the peer
+    pub address: SocketAddr,
+    /// Services advertised by the peer
+    pub services: ServiceFlags,
+    /// Unix timestamp of the last time the peer was seen online
+    pub last_seen: u32,
+}
+
+impl Encodable for KnownAddress {
+    fn consensus_encode<W: Write>(&self, mut e: W) -> Result<usize, std::io::Error> {
+        let mut written = 0;
+
+        written += Address::new(&self.address, self.services).consensus_encode(&mut e)?;
+        written += self.last_seen.consensus_encode(&mut e)?;
+
+        Ok(written)
+    }
+}
+
+impl Decodable for KnownAddress {
+    fn consensus_decode<D: Read>(mut d: D) -> Result<Self, bitcoin::consensus::encode::Error> {
+        let address = Address::consensus_decode(&mut d)?;
+
+        Ok(KnownAddress {
+            address: address.socket_addr()?,
+            services: address.services,
+            last_seen: u32::consensus_decode(&mut d)?,
+        })
+    }
+}
+
 pub struct ChainStore<T: StoreType> {
     store: Arc<RwLock<DB>>,
     cf_name: String,
@@ -367,10 +489,20 @@ impl ChainStore<Full> {
         Ok(())
     }
 
+    /// Replace the headers starting from the snapshot's first height with the ones in the snapshot
+    ///
+    /// Returns the height of the last block the two chains have in common. If the snapshot
+    /// disconnects some of our blocks, everything derived from them (full blocks, fee statistics
+    /// and compact filters) is rolled back as well, and the fork height is recorded so that the
+    /// transactions confirmed in those blocks can be dropped, see
+    /// [`ChainStore::get_reorg_height`].
     pub fn apply_snapshot(
         &self,
         snaphost: ChainStore<Snapshot>,
-    ) -> Result<(), CompactFiltersError> {
+    ) -> Result<usize, CompactFiltersError> {
+        let old_height = self.get_height()?;
+        let old_reorg_height = self.get_reorg_height()?;
+
         let mut batch = WriteBatch::default();
 
         let read_store = self.store.read().unwrap();
@@ -385,6 +517,7 @@ impl ChainStore<Full> {
 
         log::debug!("Removing items");
         batch.delete_range_cf(cf_handle, &from_key, &to_key);
+        let mut old_hashes = Vec::new();
         for (_, v) in read_store.iterator_cf_opt(
             cf_handle,
             opts,
@@ -396,12 +529,53 @@ impl ChainStore<Full> {
                 cf_handle,
                 StoreEntry::BlockHeaderIndex(Some(header.block_hash())).get_key(),
             );
+            old_hashes.push(header.block_hash());
         }
 
-        // Delete full blocks overriden by snapshot
-        let from_key = StoreEntry::Block(Some(snaphost.min_height)).get_key();
+        // The snapshot starts from one of our locators, but the chains may still share some
+        // blocks after that: look for the last one in common
+        let prefix = StoreEntry::BlockHeader(None).get_key();
+        let mut fork_height = snaphost.min_height;
+        for ((_, v), old_hash) in read_store
+            .prefix_iterator_cf(snapshot_cf_handle, &prefix)
+            .filter(|(k, _)| k.starts_with(&prefix))
+            .zip(old_hashes.iter())
+            .skip(1)
+        {
+            let (header, _): (BlockHeader, Uint256) = SerializeDb::deserialize(&v)?;
+            if &header.block_hash() != old_hash {
+                break;
+            }
+
+            fork_height += 1;
+        }
+
+        // Delete full blocks and fee statistics of the disconnected blocks
+        let from_key = StoreEntry::Block(Some(fork_height + 1)).get_key();
         let to_key = StoreEntry::Block(Some(usize::MAX)).get_key();
         batch.delete_range(&from_key, &to_key);
+        let from_key = StoreEntry::BlockFeeStats(Some(fork_height + 1)).get_key();
+        let to_key = StoreEntry::BlockFeeStats(Some(usize::MAX)).get_key();
+        batch.delete_range(&from_key, &to_key);
+
+        if fork_height < old_height {
+            log::info!(
+                "Reorg detected: disconnecting blocks from height {} to {}",
+                fork_height + 1,
+                old_height
+            );
+
+            rewind_bundles(&read_store, &mut batch, fork_height)?;
+
+            let reorg_height = match old_reorg_height {
+                Some(height) if height < fork_height => height,
+                _ => fork_height,
+            };
+            batch.put(
+                StoreEntry::ReorgHeight.get_key(),
+                &reorg_height.to_be_bytes(),
+            );
+        }
 
         log::debug!("Copying over new items");
         for (k, v) in read_store.iterator_cf(snapshot_cf_handle, IteratorMode::Start) {
@@ -413,6 +587,33 @@ impl ChainStore<Full> {
 
         self.store.write().unwrap().drop_cf(&snaphost.cf_name)?;
 
+        Ok(fork_height)
+    }
+
+    /// Return the height of the last block in common with the chain disconnected by a reorg, if
+    /// the reorg hasn't been acknowledged with [`ChainStore::clear_reorg_height`] yet
+    ///
+    /// If more than one reorg happened in the meantime, the lowest height is returned.
+    pub fn get_reorg_height(&self) -> Result<Option<usize>, CompactFiltersError> {
+        let read_store = self.store.read().unwrap();
+
+        let data = read_store.get_pinned(StoreEntry::ReorgHeight.get_key())?;
+        data.map(|data| {
+            Ok::<_, CompactFiltersError>(usize::from_be_bytes(
+                data.as_ref()
+                    .try_into()
+                    .map_err(|_| CompactFiltersError::DataCorruption)?,
+            ))
+        })
+        .transpose()
+    }
+
+    pub fn clear_reorg_height(&self) -> Result<(), CompactFiltersError> {
+        self.store
+            .read()
+            .unwrap()
+            .delete(StoreEntry::ReorgHeight.get_key())?;
+
         Ok(())
     }
 
@@ -449,9 +650,131 @@ impl ChainStore<Full> {
         .transpose()
     }
 
+    pub fn get_block_time(&self, height: usize) -> Result<Option<u32>, CompactFiltersError> {
+        let read_store = self.store.read().unwrap();
+        let cf_handle = read_store.cf_handle(&self.cf_name).unwrap();
+
+        let key = StoreEntry::BlockHeader(Some(height)).get_key();
+        let data = read_store.get_pinned_cf(cf_handle, key)?;
+        data.map(|data| {
+            let (header, _): (BlockHeader, Uint256) =
+                deserialize(&data).map_err(|_| CompactFiltersError::DataCorruption)?;
+            Ok::<_, CompactFiltersError>(header.time)
+        })
+        .transpose()
+    }
+
     pub fn save_full_block(&self, block: &Block, height: usize) -> Result<(), CompactFiltersError> {
-        let key = StoreEntry::Block(Some(height)).get_key();
-        self.store.read().unwrap().put(key, block.serialize())?;
+        let fee_stats = BlockFeeStats::from_block(block, height, self.network);
+
+        let mut batch = WriteBatch::default();
+        batch.put(StoreEntry::Block(Some(height)).get_key(), block.serialize());
+        batch.put(
+            StoreEntry::BlockFeeStats(Some(height)).get_key(),
+            fee_stats.serialize(),
+        );
+        self.store.read().unwrap().write(batch)?;
+
+        Ok(())
+    }
+
+    pub fn save_fee_stats(
+        &self,
+        height: usize,
+        fee_stats: &BlockFeeStats,
+    ) -> Result<(), CompactFiltersError> {
+        let key = StoreEntry::BlockFeeStats(Some(height)).get_key();
+        self.store.read().unwrap().put(key, fee_stats.serialize())?;
+
+        Ok(())
+    }
+
+    pub fn get_fee_stats(
+        &self,
+        height: usize,
+    ) -> Result<Option<BlockFeeStats>, CompactFiltersError> {
+        let read_store = self.store.read().unwrap();
+
+        let key = StoreEntry::BlockFeeStats(Some(height)).get_key();
+        let opt_stats = read_store.get_pinned(key)?;
+
+        opt_stats
+            .map(|data| SerializeDb::deserialize(&data))
+            .transpose()
+    }
+
+    /// Return the fee statistics stored for the blocks at height `from_height` or above, sorted
+    /// by height
+    pub fn iter_fee_stats(
+        &self,
+        from_height: usize,
+    ) -> Result<Vec<(usize, BlockFeeStats)>, CompactFiltersError> {
+        let read_store = self.store.read().unwrap();
+
+        let prefix = StoreEntry::BlockFeeStats(None).get_key();
+
+        let mut stats = Vec::new();
+        // same as in `iter_full_blocks`, we have to filter manually the keys with the wrong prefix
+        for (k, v) in read_store
+            .prefix_iterator(&prefix)
+            .filter(|(k, _)| k.starts_with(&prefix))
+        {
+            let height: usize = usize::from_be_bytes(
+                k[1..]
+                    .try_into()
+                    .map_err(|_| CompactFiltersError::DataCorruption)?,
+            );
+            if height >= from_height {
+                stats.push((height, SerializeDb::deserialize(&v)?));
+            }
+        }
+
+        Ok(stats)
+    }
+
+    pub fn save_known_address(&self, address: &KnownAddress) -> Result<(), CompactFiltersError> {
+        let key = StoreEntry::KnownAddress(Some(address.address)).get_key();
+        self.store.read().unwrap().put(key, address.serialize())?;
+
+        Ok(())
+    }
+
+    pub fn iter_known_addresses(&self) -> Result<Vec<KnownAddress>, CompactFiltersError> {
+        let read_store = self.store.read().unwrap();
+
+        let prefix = StoreEntry::KnownAddress(None).get_key();
+
+        // FIXME: we have to filter manually because rocksdb sometimes returns stuff that doesn't
+        // have the right prefix
+        read_store
+            .prefix_iterator(&prefix)
+            .filter(|(k, _)| k.starts_with(&prefix))
+            .map(|(_, v)| SerializeDb::deserialize(&v))
+            .collect::<Result<_, _>>()
+    }
+
+    /// Delete the addresses that haven't been seen since `timestamp`
+    pub fn delete_known_addresses_before(&self, timestamp: u32) -> Result<(), CompactFiltersError> {
+        let mut batch = WriteBatch::default();
+        for address in self.iter_known_addresses()? {
+            if address.last_seen < timestamp {
+                batch.delete(StoreEntry::KnownAddress(Some(address.address)).get_key());
+            }
+        }
+
+        self.store.read().unwrap().write(batch)?;
+
+        Ok(())
+    }
+
+    pub fn delete_fee_stats_until(&self, height: usize) -> Result<(), CompactFiltersError> {
+        let from_key = StoreEntry::BlockFeeStats(Some(0)).get_key();
+        let to_key = StoreEntry::BlockFeeStats(Some(height)).get_key();
+
+        let mut batch = WriteBatch::default();
+        batch.delete_range(&from_key, &to_key);
+
+        self.store.read().unwrap().write(batch)?;
 
         Ok(())
     }
@@ -630,6 +953,56 @@ pub enum BundleStatus {
     Pruned,
 }
 
+/// Roll back the compact filters bundles of every filter type after a reorg
+///
+/// The bundles whose checkpoint is in the disconnected part of the chain are deleted, they will
+/// be added back with the new checkpoints on the next sync. The filters of the bundle that
+/// contains the fork are truncated to the last block in common, so that only the new blocks are
+/// processed again.
+fn rewind_bundles(
+    store: &DB,
+    batch: &mut WriteBatch,
+    fork_height: usize,
+) -> Result<(), CompactFiltersError> {
+    let prefix = StoreEntry::CFilterTable((0, None)).get_prefix();
+
+    // FIXME: we have to filter manually because rocksdb sometimes returns stuff that doesn't
+    // have the right prefix
+    for (k, v) in store
+        .prefix_iterator(&prefix)
+        .filter(|(k, _)| k.starts_with(&prefix))
+    {
+        let index = usize::from_be_bytes(
+            k[2..]
+                .try_into()
+                .map_err(|_| CompactFiltersError::DataCorruption)?,
+        );
+        let checkpoint_height = index * 1000;
+
+        if checkpoint_height > fork_height {
+            log::debug!("Deleting bundle #{}", index);
+            batch.delete(&k);
+            continue;
+        }
+        if fork_height - checkpoint_height >= 1000 {
+            continue;
+        }
+
+        let (status, checkpoint) = BundleEntry::deserialize(&v)?;
+        let status = match status {
+            BundleStatus::Processed { mut cf_filters } | BundleStatus::Tip { mut cf_filters } => {
+                cf_filters.truncate(fork_height - checkpoint_height);
+                BundleStatus::Tip { cf_filters }
+            }
+            _ => BundleStatus::Init,
+        };
+        log::debug!("Rewinding bundle #{} to height {}", index, fork_height);
+        batch.put(&k, (status, checkpoint).serialize());
+    }
+
+    Ok(())
+}
+
 pub struct CfStore {
     store: Arc<RwLock<DB>>,
     filter_type: u8,
@@ -848,3 +1221,270 @@ impl CfStore {
         Ok(value.0)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use rocksdb::{Options, SliceTransform};
+
+    use super::*;
+
+    fn get_store() -> ChainStore<Full> {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("bdk_cf_store_{}", time.as_nanos()));
+
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.set_prefix_extractor(SliceTransform::create_fixed_prefix(16));
+
+        let db = DB::open_cf(&opts, &dir, &["default"]).unwrap();
+        ChainStore::new(db, Network::Regtest).unwrap()
+    }
+
+    fn make_headers(prev_blockhash: BlockHash, count: usize, nonce: u32) -> Vec<BlockHeader> {
+        let mut headers: Vec<BlockHeader> = Vec::with_capacity(count);
+        for i in 0..count {
+            headers.push(BlockHeader {
+                version: 1,
+                prev_blockhash: headers
+                    .last()
+                    .map(BlockHeader::block_hash)
+                    .unwrap_or(prev_blockhash),
+                merkle_root: Default::default(),
+                time: REGTEST_GENESIS.header.time + i as u32 + 1,
+                bits: REGTEST_GENESIS.header.bits,
+                nonce,
+            });
+        }
+
+        headers
+    }
+
+    fn put_bundle(store: &ChainStore<Full>, filter_type: u8, index: usize, status: BundleStatus) {
+        let key = StoreEntry::CFilterTable((filter_type, Some(index))).get_key();
+        let checkpoint = FilterHeader::from_hash(Default::default());
+
+        let read_store = store.store.read().unwrap();
+        read_store
+            .put(key, (status, checkpoint).serialize())
+            .unwrap();
+    }
+
+    fn get_bundle(store: &ChainStore<Full>, filter_type: u8, index: usize) -> Option<BundleStatus> {
+        let key = StoreEntry::CFilterTable((filter_type, Some(index))).get_key();
+
+        let read_store = store.store.read().unwrap();
+        read_store
+            .get_pinned(key)
+            .unwrap()
+            .map(|data| BundleEntry::deserialize(&data).unwrap().0)
+    }
+
+    fn filters(count: usize) -> Vec<Vec<u8>> {
+        vec![vec![0x00]; count]
+    }
+
+    /// Build a chain of 1010 blocks, then reorg it with a snapshot that starts from height 900
+    /// and shares the blocks up to `fork_height`
+    fn reorg_at(store: &mut ChainStore<Full>, fork_height: usize) -> Vec<BlockHeader> {
+        let genesis_hash = REGTEST_GENESIS.block_hash();
+        let old_headers = make_headers(genesis_hash, 1010, 0);
+        store.apply(0, old_headers.clone()).unwrap();
+        assert_eq!(store.get_height().unwrap(), 1010);
+
+        let mut new_headers = old_headers[900..fork_height].to_vec();
+        new_headers.extend(make_headers(
+            old_headers[fork_height - 1].block_hash(),
+            1012 - fork_height,
+            1,
+        ));
+
+        let mut snapshot = store.start_snapshot(900).unwrap();
+        snapshot.apply(900, new_headers.clone()).unwrap();
+
+        assert_eq!(store.apply_snapshot(snapshot).unwrap(), fork_height);
+        assert_eq!(store.get_height().unwrap(), 1012);
+        assert_eq!(store.get_reorg_height().unwrap(), Some(fork_height));
+        assert_eq!(
+            store.get_block_hash(fork_height + 1).unwrap(),
+            Some(new_headers[fork_height - 900].block_hash())
+        );
+
+        new_headers
+    }
+
+    #[test]
+    fn test_apply_snapshot_no_reorg() {
+        let mut store = get_store();
+
+        let genesis_hash = REGTEST_GENESIS.block_hash();
+        let headers = make_headers(genesis_hash, 10, 0);
+        store.apply(0, headers.clone()).unwrap();
+        put_bundle(
+            &store,
+            0,
+            0,
+            BundleStatus::Tip {
+                cf_filters: filters(10),
+            },
+        );
+
+        let mut snapshot = store.start_snapshot(5).unwrap();
+        let mut new_headers = headers[5..].to_vec();
+        new_headers.extend(make_headers(headers[9].block_hash(), 2, 0));
+        snapshot.apply(5, new_headers).unwrap();
+
+        assert_eq!(store.apply_snapshot(snapshot).unwrap(), 10);
+        assert_eq!(store.get_height().unwrap(), 12);
+        assert_eq!(store.get_reorg_height().unwrap(), None);
+        assert!(matches!(
+            get_bundle(&store, 0, 0),
+            Some(BundleStatus::Tip { cf_filters }) if cf_filters.len() == 10
+        ));
+    }
+
+    #[test]
+    fn test_apply_snapshot_fork_on_bundle_boundary() {
+        let mut store = get_store();
+
+        put_bundle(
+            &store,
+            0,
+            0,
+            BundleStatus::Processed {
+                cf_filters: filters(1000),
+            },
+        );
+        put_bundle(
+            &store,
+            0,
+            1,
+            BundleStatus::Tip {
+                cf_filters: filters(10),
+            },
+        );
+        put_bundle(&store, 0, 2, BundleStatus::Init);
+
+        reorg_at(&mut store, 1000);
+
+        // the bundle that ends at the fork is untouched
+        assert!(matches!(
+            get_bundle(&store, 0, 0),
+            Some(BundleStatus::Processed { cf_filters }) if cf_filters.len() == 1000
+        ));
+        // the one that starts at the fork loses all of its filters
+        assert!(matches!(
+            get_bundle(&store, 0, 1),
+            Some(BundleStatus::Tip { cf_filters }) if cf_filters.is_empty()
+        ));
+        // and the following ones are deleted
+        assert!(get_bundle(&store, 0, 2).is_none());
+    }
+
+    #[test]
+    fn test_apply_snapshot_fork_mid_bundle() {
+        let mut store = get_store();
+
+        put_bundle(
+            &store,
+            0,
+            0,
+            BundleStatus::Processed {
+                cf_filters: filters(1000),
+            },
+        );
+        put_bundle(
+            &store,
+            0,
+            1,
+            BundleStatus::Tip {
+                cf_filters: filters(10),
+            },
+        );
+        put_bundle(
+            &store,
+            1,
+            0,
+            BundleStatus::Processed {
+                cf_filters: filters(1000),
+            },
+        );
+        put_bundle(
+            &store,
+            1,
+            1,
+            BundleStatus::Processed {
+                cf_filters: filters(10),
+            },
+        );
+
+        reorg_at(&mut store, 1005);
+
+        for filter_type in 0..=1 {
+            assert!(matches!(
+                get_bundle(&store, filter_type, 0),
+                Some(BundleStatus::Processed { cf_filters }) if cf_filters.len() == 1000
+            ));
+            // only the filters of the blocks still in the chain are kept
+            assert!(matches!(
+                get_bundle(&store, filter_type, 1),
+                Some(BundleStatus::Tip { cf_filters }) if cf_filters.len() == 5
+            ));
+        }
+    }
+
+    #[test]
+    fn test_apply_snapshot_keeps_lowest_reorg_height() {
+        let mut store = get_store();
+
+        let new_headers = reorg_at(&mut store, 1005);
+
+        // reorg again one block above the previous fork
+        let mut headers = vec![new_headers[105]];
+        headers.extend(make_headers(new_headers[105].block_hash(), 10, 2));
+
+        let mut snapshot = store.start_snapshot(1005).unwrap();
+        snapshot.apply(1005, headers).unwrap();
+
+        assert_eq!(store.apply_snapshot(snapshot).unwrap(), 1006);
+        assert_eq!(store.get_reorg_height().unwrap(), Some(1005));
+
+        store.clear_reorg_height().unwrap();
+        assert_eq!(store.get_reorg_height().unwrap(), None);
+    }
+
+    #[test]
+    fn test_rewind_bundles_pruned() {
+        let store = get_store();
+
+        put_bundle(&store, 0, 0, BundleStatus::Pruned);
+        put_bundle(&store, 0, 1, BundleStatus::Pruned);
+        put_bundle(&store, 0, 2, BundleStatus::Pruned);
+        put_bundle(
+            &store,
+            1,
+            1,
+            BundleStatus::CfHeaders {
+                cf_headers: vec![FilterHeader::from_hash(Default::default()); 1000],
+            },
+        );
+
+        {
+            let read_store = store.store.read().unwrap();
+            let mut batch = WriteBatch::default();
+            rewind_bundles(&read_store, &mut batch, 1500).unwrap();
+            read_store.write(batch).unwrap();
+        }
+
+        assert!(matches!(
+            get_bundle(&store, 0, 0),
+            Some(BundleStatus::Pruned)
+        ));
+        // the filters of a pruned bundle are gone, so it has to be downloaded again from scratch
+        assert!(matches!(get_bundle(&store, 0, 1), Some(BundleStatus::Init)));
+        assert!(matches!(get_bundle(&store, 1, 1), Some(BundleStatus::Init)));
+        assert!(get_bundle(&store, 0, 2).is_none());
+    }
+}
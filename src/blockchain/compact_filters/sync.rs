@@ -19,6 +19,7 @@ use bitcoin::network::message_blockdata::GetHeadersMessage;
 use bitcoin::util::bip158::BlockFilter;
 
 use super::peer::*;
+use super::pool::PeerPool;
 use super::store::*;
 use super::CompactFiltersError;
 use crate::error::Error;
@@ -60,14 +61,30 @@ impl CfSync {
             }))
     }
 
-    pub fn prepare_sync(&self, peer: Arc<Peer>) -> Result<(), CompactFiltersError> {
+    /// Download the filter checkpoints from all the peers in the pool and cross-check them
+    ///
+    /// The checkpoints returned by the majority of the peers are used for the sync, the peers that
+    /// disagree with them are banned. If there's no majority the sync is aborted, since we have no
+    /// way to tell which peers are lying.
+    pub fn prepare_sync(&self, pool: &PeerPool) -> Result<(), CompactFiltersError> {
         let mut bundles_lock = self.bundles.lock().unwrap();
 
-        let resp = peer.get_cf_checkpt(
-            self.cf_store.get_filter_type(),
-            self.headers_store.get_tip_hash()?.unwrap(),
-        )?;
-        self.cf_store.replace_checkpoints(resp.filter_headers)?;
+        let tip_hash = self.headers_store.get_tip_hash()?.unwrap();
+        let mut votes: HashMap<Vec<FilterHeader>, Vec<Arc<Peer>>> = HashMap::new();
+        for peer in pool.get_peers() {
+            match peer.get_cf_checkpt(self.cf_store.get_filter_type(), tip_hash) {
+                Ok(resp) => votes.entry(resp.filter_headers).or_default().push(peer),
+                Err(e) if pool.report(&peer, &e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let (checkpoints, dissenting) = count_votes(votes)?;
+        for peer in dissenting {
+            pool.report(&peer, &CompactFiltersError::InvalidFilterHeader);
+        }
+
+        self.cf_store.replace_checkpoints(checkpoints)?;
 
         bundles_lock.clear();
         for (index, (status, checkpoint)) in self.cf_store.get_bundles()?.into_iter().enumerate() {
@@ -90,156 +107,221 @@ impl CfSync {
         let current_height = self.headers_store.get_height()?; // TODO: we should update it in case headers_store is also updated
 
         loop {
-            let (mut status, checkpoint, index) = match self.bundles.lock().unwrap().pop_front() {
+            let (status, checkpoint, index) = match self.bundles.lock().unwrap().pop_front() {
                 None => break,
                 Some(x) => x,
             };
 
-            log::debug!(
-                "Processing bundle #{} - height {} to {}",
-                index,
-                index * 1000 + 1,
-                (index + 1) * 1000
-            );
-
-            let process_received_filters =
-                |expected_filters| -> Result<BTreeMap<usize, Vec<u8>>, CompactFiltersError> {
-                    let mut filters_map = BTreeMap::new();
-                    for _ in 0..expected_filters {
-                        let filter = peer.pop_cf_filter_resp()?;
-                        if filter.filter_type != self.cf_store.get_filter_type() {
-                            return Err(CompactFiltersError::InvalidResponse);
-                        }
-
-                        match self.headers_store.get_height_for(&filter.block_hash)? {
-                            Some(height) => filters_map.insert(height, filter.filter),
-                            None => return Err(CompactFiltersError::InvalidFilter),
-                        };
+            if let Err(e) = self.process_bundle(
+                &peer,
+                (status, checkpoint, index),
+                current_height,
+                &process,
+                &completed_bundle,
+            ) {
+                // put the bundle back in the queue with its latest status, so that another peer
+                // can pick it up
+                if let Some((status, checkpoint)) =
+                    self.cf_store.get_bundles()?.into_iter().nth(index)
+                {
+                    self.bundles
+                        .lock()
+                        .unwrap()
+                        .push_front((status, checkpoint, index));
+                }
+
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the number of bundles that haven't been processed yet
+    pub fn pending_bundles(&self) -> usize {
+        self.bundles.lock().unwrap().len()
+    }
+
+    fn process_bundle<F, Q>(
+        &self,
+        peer: &Arc<Peer>,
+        (mut status, checkpoint, index): (BundleStatus, FilterHeader, usize),
+        current_height: usize,
+        process: &F,
+        completed_bundle: &Q,
+    ) -> Result<(), CompactFiltersError>
+    where
+        F: Fn(&BlockHash, &BlockFilter) -> Result<bool, CompactFiltersError>,
+        Q: Fn(usize) -> Result<(), Error>,
+    {
+        log::debug!(
+            "Processing bundle #{} - height {} to {}",
+            index,
+            index * 1000 + 1,
+            (index + 1) * 1000
+        );
+
+        let process_received_filters =
+            |expected_filters| -> Result<BTreeMap<usize, Vec<u8>>, CompactFiltersError> {
+                let mut filters_map = BTreeMap::new();
+                for _ in 0..expected_filters {
+                    let filter = peer.pop_cf_filter_resp()?;
+                    if filter.filter_type != self.cf_store.get_filter_type() {
+                        return Err(CompactFiltersError::InvalidResponse);
                     }
 
-                    Ok(filters_map)
-                };
+                    match self.headers_store.get_height_for(&filter.block_hash)? {
+                        Some(height) => filters_map.insert(height, filter.filter),
+                        None => return Err(CompactFiltersError::InvalidFilter),
+                    };
+                }
 
-            let start_height = index * 1000 + 1;
-            let mut already_processed = 0;
+                Ok(filters_map)
+            };
 
-            if start_height < self.skip_blocks {
-                status = self.cf_store.prune_filters(index, checkpoint)?;
-            }
+        let start_height = index * 1000 + 1;
+        let mut already_processed = 0;
+
+        if start_height < self.skip_blocks {
+            status = self.cf_store.prune_filters(index, checkpoint)?;
+        }
 
-            let stop_height = std::cmp::min(current_height, start_height + 999);
-            let stop_hash = self.headers_store.get_block_hash(stop_height)?.unwrap();
+        let stop_height = std::cmp::min(current_height, start_height + 999);
+        let stop_hash = self.headers_store.get_block_hash(stop_height)?.unwrap();
 
-            if let BundleStatus::Init = status {
-                log::trace!("status: Init");
+        if let BundleStatus::Init = status {
+            log::trace!("status: Init");
 
-                let resp = peer.get_cf_headers(0x00, start_height as u32, stop_hash)?;
+            let resp = peer.get_cf_headers(0x00, start_height as u32, stop_hash)?;
 
-                assert!(resp.previous_filter_header == checkpoint);
-                status =
-                    self.cf_store
-                        .advance_to_cf_headers(index, checkpoint, resp.filter_hashes)?;
-            }
-            if let BundleStatus::Tip { cf_filters } = status {
-                log::trace!("status: Tip (beginning) ");
-
-                already_processed = cf_filters.len();
-                let headers_resp = peer.get_cf_headers(0x00, start_height as u32, stop_hash)?;
-
-                let cf_headers = match self.cf_store.advance_to_cf_headers(
-                    index,
-                    checkpoint,
-                    headers_resp.filter_hashes,
-                )? {
-                    BundleStatus::CfHeaders { cf_headers } => cf_headers,
-                    _ => return Err(CompactFiltersError::InvalidResponse),
-                };
-
-                peer.get_cf_filters(
-                    self.cf_store.get_filter_type(),
-                    (start_height + cf_filters.len()) as u32,
-                    stop_hash,
-                )?;
-                let expected_filters = stop_height - start_height + 1 - cf_filters.len();
-                let filters_map = process_received_filters(expected_filters)?;
-                let filters = cf_filters
-                    .into_iter()
-                    .enumerate()
-                    .chain(filters_map.into_iter())
-                    .collect();
-                status = self
-                    .cf_store
-                    .advance_to_cf_filters(index, checkpoint, cf_headers, filters)?;
-            }
-            if let BundleStatus::CfHeaders { cf_headers } = status {
-                log::trace!("status: CFHeaders");
-
-                peer.get_cf_filters(
-                    self.cf_store.get_filter_type(),
-                    start_height as u32,
-                    stop_hash,
-                )?;
-                let expected_filters = stop_height - start_height + 1;
-                let filters_map = process_received_filters(expected_filters)?;
-                status = self.cf_store.advance_to_cf_filters(
-                    index,
-                    checkpoint,
-                    cf_headers,
-                    filters_map.into_iter().collect(),
-                )?;
+            if resp.previous_filter_header != checkpoint {
+                return Err(CompactFiltersError::InvalidFilterHeader);
             }
-            if let BundleStatus::CFilters { cf_filters } = status {
-                log::trace!("status: CFilters");
+            status = self
+                .cf_store
+                .advance_to_cf_headers(index, checkpoint, resp.filter_hashes)?;
+        }
+        if let BundleStatus::Tip { cf_filters } = status {
+            log::trace!("status: Tip (beginning) ");
 
-                let last_sync_buried_height =
-                    (start_height + already_processed).saturating_sub(BURIED_CONFIRMATIONS);
+            already_processed = cf_filters.len();
+            let headers_resp = peer.get_cf_headers(0x00, start_height as u32, stop_hash)?;
 
-                for (filter_index, filter) in cf_filters.iter().enumerate() {
-                    let height = filter_index + start_height;
+            let cf_headers = match self.cf_store.advance_to_cf_headers(
+                index,
+                checkpoint,
+                headers_resp.filter_hashes,
+            )? {
+                BundleStatus::CfHeaders { cf_headers } => cf_headers,
+                _ => return Err(CompactFiltersError::InvalidResponse),
+            };
 
-                    // do not download blocks that were already "buried" since the last sync
-                    if height < last_sync_buried_height {
-                        continue;
-                    }
+            peer.get_cf_filters(
+                self.cf_store.get_filter_type(),
+                (start_height + cf_filters.len()) as u32,
+                stop_hash,
+            )?;
+            let expected_filters = stop_height - start_height + 1 - cf_filters.len();
+            let filters_map = process_received_filters(expected_filters)?;
+            let filters = cf_filters
+                .into_iter()
+                .enumerate()
+                .chain(filters_map.into_iter())
+                .collect();
+            status = self
+                .cf_store
+                .advance_to_cf_filters(index, checkpoint, cf_headers, filters)?;
+        }
+        if let BundleStatus::CfHeaders { cf_headers } = status {
+            log::trace!("status: CFHeaders");
+
+            peer.get_cf_filters(
+                self.cf_store.get_filter_type(),
+                start_height as u32,
+                stop_hash,
+            )?;
+            let expected_filters = stop_height - start_height + 1;
+            let filters_map = process_received_filters(expected_filters)?;
+            status = self.cf_store.advance_to_cf_filters(
+                index,
+                checkpoint,
+                cf_headers,
+                filters_map.into_iter().collect(),
+            )?;
+        }
+        if let BundleStatus::CFilters { cf_filters } = status {
+            log::trace!("status: CFilters");
 
-                    let block_hash = self.headers_store.get_block_hash(height)?.unwrap();
+            let last_sync_buried_height =
+                (start_height + already_processed).saturating_sub(BURIED_CONFIRMATIONS);
 
-                    // TODO: also download random blocks?
-                    if process(&block_hash, &BlockFilter::new(&filter))? {
-                        log::debug!("Downloading block {}", block_hash);
+            for (filter_index, filter) in cf_filters.iter().enumerate() {
+                let height = filter_index + start_height;
 
-                        let block = peer
-                            .get_block(block_hash)?
-                            .ok_or(CompactFiltersError::MissingBlock)?;
-                        self.headers_store.save_full_block(&block, height)?;
-                    }
+                // do not download blocks that were already "buried" since the last sync
+                if height < last_sync_buried_height {
+                    continue;
                 }
 
-                status = BundleStatus::Processed { cf_filters };
-            }
-            if let BundleStatus::Processed { cf_filters } = status {
-                log::trace!("status: Processed");
+                let block_hash = self.headers_store.get_block_hash(height)?.unwrap();
 
-                if current_height - stop_height > 1000 {
-                    status = self.cf_store.prune_filters(index, checkpoint)?;
-                } else {
-                    status = self.cf_store.mark_as_tip(index, cf_filters, checkpoint)?;
-                }
+                // TODO: also download random blocks?
+                if process(&block_hash, &BlockFilter::new(&filter))? {
+                    log::debug!("Downloading block {}", block_hash);
 
-                completed_bundle(index)?;
-            }
-            if let BundleStatus::Pruned = status {
-                log::trace!("status: Pruned");
+                    let block = peer
+                        .get_block(block_hash)?
+                        .ok_or(CompactFiltersError::MissingBlock)?;
+                    self.headers_store.save_full_block(&block, height)?;
+                }
             }
-            if let BundleStatus::Tip { .. } = status {
-                log::trace!("status: Tip");
+
+            status = BundleStatus::Processed { cf_filters };
+        }
+        if let BundleStatus::Processed { cf_filters } = status {
+            log::trace!("status: Processed");
+
+            if current_height - stop_height > 1000 {
+                status = self.cf_store.prune_filters(index, checkpoint)?;
+            } else {
+                status = self.cf_store.mark_as_tip(index, cf_filters, checkpoint)?;
             }
+
+            completed_bundle(index)?;
+        }
+        if let BundleStatus::Pruned = status {
+            log::trace!("status: Pruned");
+        }
+        if let BundleStatus::Tip { .. } = status {
+            log::trace!("status: Tip");
         }
 
         Ok(())
     }
 }
 
+/// Return the checkpoints voted by the majority of the peers, along with the peers that voted
+/// for different ones
+///
+/// Fails with [`CompactFiltersError::ConflictingFilterHeaders`] if there's a tie.
+fn count_votes<T>(
+    votes: HashMap<Vec<FilterHeader>, Vec<T>>,
+) -> Result<(Vec<FilterHeader>, Vec<T>), CompactFiltersError> {
+    let mut votes = votes.into_iter().collect::<Vec<_>>();
+    votes.sort_by_key(|(_, voters)| std::cmp::Reverse(voters.len()));
+    if let [(_, first), (_, second), ..] = votes.as_slice() {
+        if first.len() == second.len() {
+            return Err(CompactFiltersError::ConflictingFilterHeaders);
+        }
+    }
+
+    let mut votes = votes.into_iter();
+    let (checkpoints, _) = votes.next().ok_or(CompactFiltersError::NoPeers)?;
+    let dissenting = votes.flat_map(|(_, voters)| voters).collect();
+
+    Ok((checkpoints, dissenting))
+}
+
 pub fn sync_headers<F>(
     peer: Arc<Peer>,
     store: Arc<ChainStore<Full>>,
@@ -256,9 +338,9 @@ where
         locators_vec,
         Default::default(),
     )))?;
-    let (mut snapshot, mut last_hash) = if let NetworkMessage::Headers(headers) = peer
-        .recv("headers", Some(Duration::from_secs(TIMEOUT_SECS)))?
-        .ok_or(CompactFiltersError::Timeout)?
+    let (mut snapshot, mut last_hash, mut sync_height) = if let NetworkMessage::Headers(headers) =
+        peer.recv("headers", Some(Duration::from_secs(TIMEOUT_SECS)))?
+            .ok_or(CompactFiltersError::Timeout)?
     {
         if headers.is_empty() {
             return Ok(None);
@@ -266,13 +348,17 @@ where
 
         match locators_map.get(&headers[0].prev_blockhash) {
             None => return Err(CompactFiltersError::InvalidHeaders),
-            Some(from) => (store.start_snapshot(*from)?, headers[0].prev_blockhash),
+            // in case of a reorg this is lower than our tip: start from the fork point
+            Some(from) => (
+                store.start_snapshot(*from)?,
+                headers[0].prev_blockhash,
+                *from,
+            ),
         }
     } else {
         return Err(CompactFiltersError::InvalidResponse);
     };
 
-    let mut sync_height = store.get_height()?;
     while sync_height < peer.get_version().start_height as usize {
         peer.send(NetworkMessage::GetHeaders(GetHeadersMessage::new(
             vec![last_hash],
@@ -294,3 +380,60 @@ where
 
     Ok(Some(snapshot))
 }
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+
+    fn checkpoints(n: u8) -> Vec<FilterHeader> {
+        vec![FilterHeader::hash(&[n]), FilterHeader::hash(&[n, n])]
+    }
+
+    #[test]
+    fn test_count_votes() {
+        let mut votes = HashMap::new();
+        votes.insert(checkpoints(1), vec!["peer1", "peer2"]);
+        votes.insert(checkpoints(2), vec!["peer3"]);
+        votes.insert(checkpoints(3), vec!["peer4"]);
+
+        let (winner, mut dissenting) = count_votes(votes).unwrap();
+        dissenting.sort_unstable();
+        assert_eq!(winner, checkpoints(1));
+        assert_eq!(dissenting, vec!["peer3", "peer4"]);
+    }
+
+    #[test]
+    fn test_count_votes_unanimous() {
+        let mut votes = HashMap::new();
+        votes.insert(checkpoints(1), vec!["peer1", "peer2", "peer3"]);
+
+        let (winner, dissenting) = count_votes(votes).unwrap();
+        assert_eq!(winner, checkpoints(1));
+        assert!(dissenting.is_empty());
+    }
+
+    #[test]
+    fn test_count_votes_tie() {
+        let mut votes = HashMap::new();
+        votes.insert(checkpoints(1), vec!["peer1", "peer2"]);
+        votes.insert(checkpoints(2), vec!["peer3", "peer4"]);
+        votes.insert(checkpoints(3), vec!["peer5"]);
+
+        assert!(matches!(
+            count_votes(votes),
+            Err(CompactFiltersError::ConflictingFilterHeaders)
+        ));
+    }
+
+    #[test]
+    fn test_count_votes_no_peers() {
+        let votes: HashMap<_, Vec<&str>> = HashMap::new();
+
+        assert!(matches!(
+            count_votes(votes),
+            Err(CompactFiltersError::NoPeers)
+        ));
+    }
+}
@@ -23,31 +23,203 @@
 //! let blockchain = ElectrumBlockchain::from(client);
 //! # Ok::<(), bdk::Error>(())
 //! ```
+//!
+//! ## Subscriptions
+//!
+//! Instead of calling [`Wallet::sync`](crate::Wallet::sync) periodically, a long-running service
+//! can subscribe to new blocks and to the wallet's script_pubkeys and only update the database when
+//! the server notifies a change:
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use bdk::blockchain::{noop_progress, ElectrumBlockchain};
+//! # use bdk::database::MemoryDatabase;
+//! # use bdk::*;
+//! # use bitcoin::Network;
+//! let client = electrum_client::Client::new("ssl://electrum.blockstream.info:60002")?;
+//! let wallet = Wallet::new(
+//!     "wpkh(tpubD6NzVbkrYhZ4X2yy78HWrr1M9NT8dKeWfzNiQqDdMqqa9UmmGztGGz6TaLFGsLfdft5iu32gxq1T4eMNxExNNWzVCpf9Y6JZi5TnqoC9wJq/*)",
+//!     None,
+//!     Network::Testnet,
+//!     MemoryDatabase::default(),
+//!     ElectrumBlockchain::from(client),
+//! )?;
+//!
+//! wallet.sync(noop_progress(), None)?;
+//! loop {
+//!     let notifications = wallet.sync_on_notification(noop_progress(), Some(Duration::from_secs(60)))?;
+//!     if !notifications.is_empty() {
+//!         println!("New balance: {}", wallet.get_balance()?);
+//!     }
+//! }
+//! # Ok::<(), bdk::Error>(())
+//! ```
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace};
 
+use bitcoin::hashes::{sha256, Hash};
 use bitcoin::{BlockHeader, Script, Transaction, Txid};
 
 use electrum_client::{Client, ConfigBuilder, ElectrumApi, Socks5Config};
 
 use self::utils::{ElectrumLikeSync, ElsGetHistoryRes};
 use super::*;
-use crate::database::BatchDatabase;
+use crate::database::{BatchDatabase, Database};
 use crate::error::Error;
 use crate::FeeRate;
 
+/// Interval between two checks for new notifications in
+/// [`ElectrumBlockchain::wait_for_notifications`]
+const NOTIFICATIONS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Wrapper over an Electrum Client that implements the required blockchain traits
 ///
+/// The script_pubkeys of the wallet are subscribed during the first sync, and their status is
+/// used to avoid downloading again the history of the ones that haven't changed. The client can
+/// only subscribe to one script_pubkey per request, so the first sync is slower than the
+/// following ones.
+///
 /// ## Example
 /// See the [`blockchain::electrum`](crate::blockchain::electrum) module for a usage example.
-pub struct ElectrumBlockchain(Client);
+pub struct ElectrumBlockchain {
+    client: Client,
+    // Last status received for the script_pubkeys we are subscribed to
+    script_status: Mutex<HashMap<Script, Option<sha256::Hash>>>,
+}
 
 impl std::convert::From<Client> for ElectrumBlockchain {
     fn from(client: Client) -> Self {
-        ElectrumBlockchain(client)
+        ElectrumBlockchain {
+            client,
+            script_status: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// Notification received from an Electrum server
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElectrumNotification {
+    /// A new block has been found
+    NewBlock {
+        /// Height of the new tip
+        height: u32,
+    },
+    /// The history of a script_pubkey has changed
+    ScriptStatusChanged(Script),
+}
+
+impl ElectrumBlockchain {
+    /// Subscribe to new block headers and to all the script_pubkeys stored in `database`
+    ///
+    /// Script_pubkeys that are already subscribed are skipped, so this method can be called again
+    /// after caching new addresses. The script_pubkeys queried during a sync are subscribed
+    /// anyway, this also subscribes the ones that haven't been synced yet.
+    pub fn subscribe<D: Database>(&self, database: &D) -> Result<(), Error> {
+        self.client.block_headers_subscribe()?;
+
+        // the client can only subscribe to one script_pubkey per request
+        let mut script_status = self.script_status.lock().unwrap();
+        for script in database.iter_script_pubkeys(None)? {
+            if !script_status.contains_key(&script) {
+                self.refresh_script_status(&script, &mut script_status)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the notifications sent by the server since the last call, without blocking
+    ///
+    /// Multiple block notifications are merged into a single [`ElectrumNotification::NewBlock`]
+    /// for the most recent tip.
+    pub fn poll_notifications(&self) -> Result<Vec<ElectrumNotification>, Error> {
+        // make sure that we have received all the notifications sent by the server so far
+        self.client.ping()?;
+
+        let mut notifications = vec![];
+
+        let mut new_tip = None;
+        while let Some(header) = self.client.block_headers_pop()? {
+            new_tip = Some(header.height as u32);
+        }
+        if let Some(height) = new_tip {
+            notifications.push(ElectrumNotification::NewBlock { height });
+        }
+
+        let mut script_status = self.script_status.lock().unwrap();
+        let subscribed = script_status.keys().cloned().collect::<Vec<_>>();
+        for script in subscribed {
+            let previous = script_status.get(&script).cloned();
+            if self.refresh_script_status(&script, &mut script_status)?
+                && script_status.get(&script).cloned() != previous
+            {
+                notifications.push(ElectrumNotification::ScriptStatusChanged(script));
+            }
+        }
+
+        Ok(notifications)
+    }
+
+    /// Block until the server sends at least one notification, or `timeout` expires
+    ///
+    /// Returns an empty list if no notification has been received before the timeout.
+    pub fn wait_for_notifications(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<ElectrumNotification>, Error> {
+        let start = Instant::now();
+        loop {
+            let notifications = self.poll_notifications()?;
+            if !notifications.is_empty() {
+                return Ok(notifications);
+            }
+
+            let sleep = match timeout {
+                Some(timeout) => match timeout.checked_sub(start.elapsed()) {
+                    Some(remaining) if remaining > Duration::from_secs(0) => {
+                        std::cmp::min(remaining, NOTIFICATIONS_POLL_INTERVAL)
+                    }
+                    _ => return Ok(notifications),
+                },
+                None => NOTIFICATIONS_POLL_INTERVAL,
+            };
+            std::thread::sleep(sleep);
+        }
+    }
+
+    // Update the status of `script` with the notifications received from the server, subscribing
+    // to it if the client isn't subscribed yet. Returns `false` if the status is unknown.
+    fn refresh_script_status(
+        &self,
+        script: &Script,
+        script_status: &mut HashMap<Script, Option<sha256::Hash>>,
+    ) -> Result<bool, Error> {
+        let status = match self.client.script_pop(script) {
+            Ok(Some(mut status)) => {
+                // we only care about the most recent one
+                while let Some(next) = self.client.script_pop(script)? {
+                    status = next;
+                }
+                Some(sha256::Hash::from_inner(*status))
+            }
+            // somebody else may have subscribed to this script using our client, in that case we
+            // don't know its status
+            Ok(None) => return Ok(script_status.contains_key(script)),
+            // we have never subscribed or the client had to reconnect to the server
+            Err(electrum_client::Error::NotSubscribed(_)) => self
+                .client
+                .script_subscribe(script)?
+                .map(|status| sha256::Hash::from_inner(*status)),
+            Err(e) => return Err(e.into()),
+        };
+
+        script_status.insert(script.clone(), status);
+        Ok(true)
     }
 }
 
@@ -68,40 +240,66 @@ impl Blockchain for ElectrumBlockchain {
         database: &mut D,
         progress_update: P,
     ) -> Result<(), Error> {
-        self.0
-            .electrum_like_setup(stop_gap, database, progress_update)
+        self.electrum_like_setup(stop_gap, database, progress_update)
+    }
+
+    fn refresh<D: BatchDatabase, P: Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        database: &mut D,
+        progress_update: P,
+    ) -> Result<(), Error> {
+        self.electrum_like_refresh(stop_gap, database, progress_update)
     }
 
     fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error> {
-        Ok(self.0.transaction_get(txid).map(Option::Some)?)
+        Ok(self.client.transaction_get(txid).map(Option::Some)?)
     }
 
     fn broadcast(&self, tx: &Transaction) -> Result<(), Error> {
-        Ok(self.0.transaction_broadcast(tx).map(|_| ())?)
+        Ok(self.client.transaction_broadcast(tx).map(|_| ())?)
     }
 
     fn get_height(&self) -> Result<u32, Error> {
         // TODO: unsubscribe when added to the client, or is there a better call to use here?
 
         Ok(self
-            .0
+            .client
             .block_headers_subscribe()
             .map(|data| data.height as u32)?)
     }
 
     fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
         Ok(FeeRate::from_btc_per_kvb(
-            self.0.estimate_fee(target)? as f32
+            self.client.estimate_fee(target)? as f32
         ))
     }
+
+    fn estimate_fees(&self, targets: &[usize]) -> Result<Vec<FeeRate>, Error> {
+        if targets.is_empty() {
+            return Ok(vec![]);
+        }
+
+        Ok(self
+            .client
+            .batch_estimate_fee(targets.iter().cloned())?
+            .into_iter()
+            .map(|rate| FeeRate::from_btc_per_kvb(rate as f32))
+            .collect())
+    }
 }
 
-impl ElectrumLikeSync for Client {
+impl ElectrumLikeSync for ElectrumBlockchain {
+    fn els_get_height(&self) -> Result<u32, Error> {
+        self.get_height()
+    }
+
     fn els_batch_script_get_history<'s, I: IntoIterator<Item = &'s Script> + Clone>(
         &self,
         scripts: I,
     ) -> Result<Vec<Vec<ElsGetHistoryRes>>, Error> {
-        self.batch_script_get_history(scripts)
+        self.client
+            .batch_script_get_history(scripts)
             .map(|v| {
                 v.into_iter()
                     .map(|v| {
@@ -125,14 +323,57 @@ impl ElectrumLikeSync for Client {
         &self,
         txids: I,
     ) -> Result<Vec<Transaction>, Error> {
-        self.batch_transaction_get(txids).map_err(Error::Electrum)
+        self.client
+            .batch_transaction_get(txids)
+            .map_err(Error::Electrum)
     }
 
     fn els_batch_block_header<I: IntoIterator<Item = u32> + Clone>(
         &self,
         heights: I,
     ) -> Result<Vec<BlockHeader>, Error> {
-        self.batch_block_header(heights).map_err(Error::Electrum)
+        self.client
+            .batch_block_header(heights)
+            .map_err(Error::Electrum)
+    }
+
+    fn els_batch_script_get_mempool<'s, I: IntoIterator<Item = &'s Script> + Clone>(
+        &self,
+        scripts: I,
+    ) -> Result<Option<Vec<Vec<ElsGetHistoryRes>>>, Error> {
+        // `blockchain.scripthash.get_mempool` isn't exposed by the client, the unconfirmed
+        // transactions are taken from the full history instead. The refresh only calls this for
+        // the scripts whose status has changed since the last sync, so the history of the others
+        // isn't downloaded
+        let history = self.els_batch_script_get_history(scripts)?;
+
+        Ok(Some(
+            history
+                .into_iter()
+                .map(|v| v.into_iter().filter(|el| el.height <= 0).collect())
+                .collect(),
+        ))
+    }
+
+    fn els_batch_script_status<'s, I: IntoIterator<Item = &'s Script> + Clone>(
+        &self,
+        scripts: I,
+    ) -> Result<Option<Vec<Option<sha256::Hash>>>, Error> {
+        // make sure that we have received all the notifications sent by the server so far
+        self.client.ping()?;
+
+        // the scripts that aren't subscribed yet are subscribed one by one, the following syncs
+        // only read the notifications received in the meantime
+        let mut script_status = self.script_status.lock().unwrap();
+        let mut statuses = vec![];
+        for script in scripts {
+            if !self.refresh_script_status(script, &mut script_status)? {
+                return Ok(None);
+            }
+            statuses.push(script_status[script]);
+        }
+
+        Ok(Some(statuses))
     }
 }
 
@@ -144,25 +385,44 @@ pub struct ElectrumBlockchainConfig {
     /// eg. `ssl://electrum.blockstream.info:60002`
     pub url: String,
     /// URL of the socks5 proxy server or a Tor service
+    ///
+    /// Can't be used together with `timeout`.
     pub socks5: Option<String>,
+    /// Optional socks5 proxy credentials, as a `(username, password)` pair
+    ///
+    /// Tor isolates the streams opened with different credentials on different circuits, so
+    /// using distinct credentials for each wallet prevents linking their traffic.
+    pub socks5_credentials: Option<(String, String)>,
     /// Request retry count
     pub retry: u8,
     /// Request timeout (seconds)
     pub timeout: Option<u8>,
 }
 
+/// Return the socks5 proxy configuration of `config`, along with its credentials
+fn socks5_config(config: &ElectrumBlockchainConfig) -> Option<Socks5Config> {
+    config
+        .socks5
+        .as_ref()
+        .map(|addr| match &config.socks5_credentials {
+            Some((username, password)) => {
+                Socks5Config::with_credentials(addr, username.clone(), password.clone())
+            }
+            None => Socks5Config::new(addr),
+        })
+}
+
 impl ConfigurableBlockchain for ElectrumBlockchain {
     type Config = ElectrumBlockchainConfig;
 
     fn from_config(config: &Self::Config) -> Result<Self, Error> {
-        let socks5 = config.socks5.as_ref().map(Socks5Config::new);
         let electrum_config = ConfigBuilder::new()
             .retry(config.retry)
             .timeout(config.timeout)?
-            .socks5(socks5)?
+            .socks5(socks5_config(config))?
             .build();
 
-        Ok(ElectrumBlockchain(Client::from_config(
+        Ok(ElectrumBlockchain::from(Client::from_config(
             config.url.as_str(),
             electrum_config,
         )?))
@@ -175,3 +435,83 @@ crate::bdk_blockchain_tests! {
         ElectrumBlockchain::from(Client::new(&testutils::blockchain_tests::get_electrum_url()).unwrap())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_socks5_config() {
+        let mut config = ElectrumBlockchainConfig {
+            url: "tcp://electrum.example:50001".to_string(),
+            socks5: None,
+            socks5_credentials: Some(("wallet1".to_string(), "password".to_string())),
+            retry: 0,
+            timeout: None,
+        };
+        assert!(socks5_config(&config).is_none());
+
+        config.socks5 = Some("127.0.0.1:9050".to_string());
+        let socks5 = socks5_config(&config).unwrap();
+        assert_eq!(socks5.addr, "127.0.0.1:9050");
+        let credentials = socks5.credentials.unwrap();
+        assert_eq!(credentials.username, "wallet1");
+        assert_eq!(credentials.password, "password");
+
+        config.socks5_credentials = None;
+        let socks5 = socks5_config(&config).unwrap();
+        assert_eq!(socks5.addr, "127.0.0.1:9050");
+        assert!(socks5.credentials.is_none());
+    }
+
+    #[cfg(feature = "test-blockchains")]
+    #[test]
+    #[serial_test::serial]
+    fn test_sync_on_notification() {
+        use crate::bitcoin::Network;
+        use crate::database::MemoryDatabase;
+        use crate::testutils;
+        use crate::testutils::blockchain_tests::{get_electrum_url, TestClient};
+        use crate::Wallet;
+
+        let descriptors = testutils! {
+            @descriptors ( "wpkh(Alice)" ) ( "wpkh(Alice)" ) ( @keys ( "Alice" => (@generate_xprv "/44'/0'/0'/0/*", "/44'/0'/0'/1/*") ) )
+        };
+        let mut test_client = TestClient::default();
+        let blockchain = ElectrumBlockchain::from(Client::new(&get_electrum_url()).unwrap());
+        let wallet = Wallet::new(
+            &descriptors.0,
+            descriptors.1.as_ref(),
+            Network::Regtest,
+            MemoryDatabase::new(),
+            blockchain,
+        )
+        .unwrap();
+
+        wallet.sync(noop_progress(), None).unwrap();
+        // subscribe to the wallet's scripts, nothing has happened yet
+        let notifications = wallet
+            .sync_on_notification(noop_progress(), Some(Duration::from_secs(1)))
+            .unwrap();
+        assert!(notifications.is_empty(), "unexpected notifications");
+
+        test_client.receive(testutils! {
+            @tx ( (@external descriptors, 0) => 50_000 )
+        });
+
+        let notifications = wallet
+            .sync_on_notification(noop_progress(), Some(Duration::from_secs(30)))
+            .unwrap();
+        assert!(
+            notifications
+                .iter()
+                .any(|n| matches!(n, ElectrumNotification::ScriptStatusChanged(_))),
+            "missing script notification"
+        );
+        assert_eq!(
+            wallet.get_balance().unwrap().get_total(),
+            50_000,
+            "incorrect balance"
+        );
+    }
+}
@@ -0,0 +1,955 @@
+// Bitcoin Dev Kit
+// Written in 2020 by Alekos Filini <alekos.filini@gmail.com>
+//
+// Copyright (c) 2020-2021 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Esplora
+//!
+//! This module defines a [`Blockchain`] struct that can query an Esplora backend
+//! populate the wallet's [database](crate::database::Database) by
+//!
+//! Two HTTP clients are available:
+//!
+//! * with the `esplora` feature requests are sent with the async [`reqwest`](crate::reqwest)
+//!   client, which uses `fetch` on wasm32
+//! * with the `esplora-blocking` feature requests are sent with the blocking
+//!   [`ureq`](https://docs.rs/ureq) client, which is lighter for native applications. The requests
+//!   are sent one at a time, so the `concurrency` option is ignored
+//!
+//! The two features are mutually exclusive.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # use bdk::blockchain::esplora::EsploraBlockchain;
+//! let blockchain = EsploraBlockchain::new("https://blockstream.info/testnet/api", None);
+//! # Ok::<(), bdk::Error>(())
+//! ```
+//!
+//! Requests that fail with a transient error (HTTP 429, 5xx, connection errors or timeouts) are
+//! retried with an exponential backoff, honoring the `Retry-After` header sent by the server. Once
+//! the retries are exhausted the next [fallback server](EsploraBlockchain::with_fallback_urls)
+//! is tried. The requests can also be [rate limited](EsploraBlockchain::with_rate_limit).
+//!
+//! With the async client the delays rely on the [`tokio`](https://docs.rs/tokio) timer on native
+//! platforms, so with the `async-interface` feature they require a tokio runtime with the time
+//! driver enabled. There is no timer on wasm32: the failed requests are retried immediately and
+//! the rate limit isn't enforced.
+//!
+//! ```no_run
+//! # use bdk::blockchain::esplora::EsploraBlockchain;
+//! let blockchain = EsploraBlockchain::new("https://blockstream.info/testnet/api", None)
+//!     .with_fallback_urls(vec!["https://mempool.space/testnet/api".to_string()])
+//!     .with_max_retries(5)
+//!     .with_rate_limit(10);
+//! # Ok::<(), bdk::Error>(())
+//! ```
+//!
+//! All the requests can be sent [through a proxy](EsploraBlockchain::with_proxy), for example to
+//! reach the servers over Tor:
+//!
+//! ```no_run
+//! # use bdk::blockchain::esplora::EsploraBlockchain;
+//! let blockchain = EsploraBlockchain::new(
+//!     "http://explorerzydxu5ecjrkwceayqybizmpjjznk5izmitf2modhcusuqlid.onion/testnet/api",
+//!     None,
+//! )
+//! .with_proxy("socks5://127.0.0.1:9050", Some(("wallet1", "password")))?;
+//! # Ok::<(), bdk::Error>(())
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+#[cfg(feature = "esplora")]
+use std::future::Future;
+#[cfg(feature = "esplora")]
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[cfg(feature = "esplora")]
+use futures::stream::{FuturesOrdered, TryStreamExt};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use bitcoin::consensus::{self, deserialize, serialize};
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::{BlockHash, BlockHeader, Script, Transaction, Txid};
+
+use self::utils::{ElectrumLikeSync, ElsGetHistoryRes};
+use super::*;
+use crate::database::BatchDatabase;
+use crate::error::Error;
+use crate::wallet::time::Instant;
+#[cfg(feature = "esplora")]
+use crate::wallet::utils::ChunksIterator;
+use crate::FeeRate;
+
+#[cfg(feature = "esplora")]
+mod reqwest;
+#[cfg(feature = "esplora")]
+type Client = self::reqwest::ReqwestClient;
+
+// The async client is used when both features are enabled
+#[cfg(all(feature = "esplora-blocking", not(feature = "esplora")))]
+mod ureq;
+#[cfg(all(feature = "esplora-blocking", not(feature = "esplora")))]
+type Client = self::ureq::UreqClient;
+
+/// Wait for a future returned by [`UrlClient`], using the runtime required by the HTTP client
+#[cfg(feature = "esplora")]
+macro_rules! await_client {
+    ($future:expr) => {
+        await_or_block!($future)
+    };
+}
+// The methods of the blocking client return the results directly
+#[cfg(not(feature = "esplora"))]
+macro_rules! await_client {
+    ($result:expr) => {
+        $result
+    };
+}
+
+/// Await a future returned by the HTTP client inside of [`UrlClient`]
+#[cfg(feature = "esplora")]
+macro_rules! maybe_client_await {
+    ($future:expr) => {
+        $future.await
+    };
+}
+#[cfg(not(feature = "esplora"))]
+macro_rules! maybe_client_await {
+    ($result:expr) => {
+        $result
+    };
+}
+
+const DEFAULT_CONCURRENT_REQUESTS: u8 = 4;
+const DEFAULT_MAX_RETRIES: u8 = 3;
+/// Delay before the first retry of a request, doubled at every following retry
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Maximum delay between two retries, unless the server asks for more with `Retry-After`
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Number of confirmed transactions returned by `/scripthash/:hash/txs/chain` in every page
+const HISTORY_PAGE_SIZE: usize = 25;
+
+/// Servers used by the client and policy for the requests that fail
+#[derive(Debug)]
+struct Servers {
+    /// Base URLs of the servers, in the order they are tried
+    urls: Vec<String>,
+    /// Index in `urls` of the server currently used
+    current_url: AtomicUsize,
+    max_retries: u8,
+    /// Minimum interval between the requests, if they are rate limited
+    request_interval: Option<Duration>,
+    /// Reference for `next_request`
+    started: Instant,
+    /// Time elapsed since `started` when the next request can be sent
+    next_request: Mutex<Duration>,
+}
+
+impl Servers {
+    fn new(base_url: &str) -> Self {
+        Servers {
+            urls: vec![base_url.to_string()],
+            current_url: AtomicUsize::new(0),
+            max_retries: DEFAULT_MAX_RETRIES,
+            request_interval: None,
+            started: Instant::new(),
+            next_request: Mutex::new(Duration::from_secs(0)),
+        }
+    }
+
+    /// Indexes of the servers in the order they should be tried, starting from the current one
+    fn order(&self) -> impl Iterator<Item = usize> {
+        let first = self.current_url.load(Ordering::Relaxed);
+        (first..self.urls.len()).chain(0..first)
+    }
+
+    /// Keep using the server at `index` for the next requests
+    fn set_current(&self, index: usize) {
+        self.current_url.store(index, Ordering::Relaxed);
+    }
+
+    /// Reserve a slot for a request, returning how long to wait before sending it to respect the
+    /// rate limit
+    fn reserve_request(&self) -> Duration {
+        match self.request_interval {
+            Some(interval) => {
+                let mut next_request = self.next_request.lock().unwrap();
+                let now = self.started.elapsed();
+                let slot = std::cmp::max(*next_request, now);
+                *next_request = slot + interval;
+                slot - now
+            }
+            None => Duration::from_secs(0),
+        }
+    }
+}
+
+/// Response to an HTTP request, with its body read in full
+struct HttpResponse {
+    status: u16,
+    /// Value of the `Retry-After` header, if present
+    retry_after: Option<String>,
+    body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Return an error if the server answered with an error status
+    fn error_for_status(self) -> Result<Self, EsploraError> {
+        if self.status >= 400 {
+            Err(EsploraError::HttpResponse(self.status))
+        } else {
+            Ok(self)
+        }
+    }
+
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    fn json<T: DeserializeOwned>(&self) -> Result<T, EsploraError> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+#[cfg(feature = "esplora")]
+type ResponseFuture<'a> = Pin<Box<dyn Future<Output = Result<HttpResponse, EsploraError>> + 'a>>;
+
+/// HTTP client used to send the requests to the Esplora servers
+///
+/// The clients only send single requests: the retries, the fallback servers and the parsing of
+/// the responses are handled by [`UrlClient`].
+trait HttpClient: Sized {
+    /// Create a client that connects directly to the servers
+    fn new() -> Self;
+
+    /// Create a client that sends the requests through a proxy, see
+    /// [`EsploraBlockchain::with_proxy`]
+    fn with_proxy(proxy: &str, credentials: Option<(&str, &str)>) -> Result<Self, EsploraError>;
+
+    /// Send a `POST` request with `body` to `url` if present, a `GET` request otherwise
+    ///
+    /// The responses with an error status are returned as well, only the failures to reach the
+    /// server or to read its response are errors.
+    #[cfg(feature = "esplora")]
+    fn send<'a>(&'a self, url: &'a str, body: Option<String>) -> ResponseFuture<'a>;
+    #[cfg(not(feature = "esplora"))]
+    fn send(&self, url: &str, body: Option<String>) -> Result<HttpResponse, EsploraError>;
+
+    /// Wait before sending the next request
+    #[cfg(feature = "esplora")]
+    fn sleep(duration: Duration) -> Pin<Box<dyn Future<Output = ()>>>;
+    #[cfg(not(feature = "esplora"))]
+    fn sleep(duration: Duration);
+}
+
+#[derive(Debug)]
+struct UrlClient {
+    servers: Servers,
+    client: Client,
+    /// The blocking client sends the requests one at a time
+    #[cfg_attr(not(feature = "esplora"), allow(dead_code))]
+    concurrency: u8,
+}
+
+/// Structure that implements the logic to sync with Esplora
+///
+/// ## Example
+/// See the [`blockchain::esplora`](crate::blockchain::esplora) module for a usage example.
+#[derive(Debug)]
+pub struct EsploraBlockchain(UrlClient);
+
+impl EsploraBlockchain {
+    /// Create a new instance of the client from a base URL
+    pub fn new(base_url: &str, concurrency: Option<u8>) -> Self {
+        EsploraBlockchain(UrlClient::new(
+            Servers::new(base_url),
+            concurrency.unwrap_or(DEFAULT_CONCURRENT_REQUESTS),
+        ))
+    }
+
+    /// Add the base URLs of the servers used when the previous ones fail
+    ///
+    /// The servers are tried in order. Once a fallback server is used it's kept for the
+    /// following requests, until it fails too.
+    pub fn with_fallback_urls<I: IntoIterator<Item = String>>(mut self, urls: I) -> Self {
+        self.0.servers.urls.extend(urls);
+        self
+    }
+
+    /// Set how many times a request that fails with a transient error is retried before trying
+    /// the next server (default: 3)
+    pub fn with_max_retries(mut self, max_retries: u8) -> Self {
+        self.0.servers.max_retries = max_retries;
+        self
+    }
+
+    /// Limit the number of requests sent every second, across all the servers
+    pub fn with_rate_limit(mut self, max_requests_per_second: u32) -> Self {
+        self.0.servers.request_interval =
+            Some(Duration::from_secs(1) / std::cmp::max(max_requests_per_second, 1));
+        self
+    }
+
+    /// Send all the requests through a proxy, such as Tor
+    ///
+    /// `proxy` is the URL of a SOCKS5 proxy (`socks5://host:port`) or of an HTTP proxy
+    /// (`http://host:port`). The host names of the servers are resolved by SOCKS5 proxies, so
+    /// that onion services can be reached and no DNS request is sent outside of the proxy. The
+    /// optional `credentials` are a `(username, password)` pair.
+    ///
+    /// Proxies aren't supported on wasm32.
+    pub fn with_proxy(
+        mut self,
+        proxy: &str,
+        credentials: Option<(&str, &str)>,
+    ) -> Result<Self, Error> {
+        self.0.client = Client::with_proxy(proxy, credentials)?;
+        Ok(self)
+    }
+}
+
+#[maybe_async]
+impl Blockchain for EsploraBlockchain {
+    fn get_capabilities(&self) -> HashSet<Capability> {
+        vec![
+            Capability::FullHistory,
+            Capability::GetAnyTx,
+            Capability::AccurateFees,
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    fn setup<D: BatchDatabase, P: Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        database: &mut D,
+        progress_update: P,
+    ) -> Result<(), Error> {
+        maybe_await!(self
+            .0
+            .electrum_like_setup(stop_gap, database, progress_update))
+    }
+
+    fn refresh<D: BatchDatabase, P: Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        database: &mut D,
+        progress_update: P,
+    ) -> Result<(), Error> {
+        maybe_await!(self
+            .0
+            .electrum_like_refresh(stop_gap, database, progress_update))
+    }
+
+    fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error> {
+        Ok(await_client!(self.0._get_tx(txid))?)
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<(), Error> {
+        Ok(await_client!(self.0._broadcast(tx))?)
+    }
+
+    fn get_height(&self) -> Result<u32, Error> {
+        Ok(await_client!(self.0._get_height())?)
+    }
+
+    fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
+        let estimates = await_client!(self.0._get_fee_estimates())?;
+        fee_rate_from_estimates(estimates, target)
+    }
+
+    fn estimate_fees(&self, targets: &[usize]) -> Result<Vec<FeeRate>, Error> {
+        let estimates = await_client!(self.0._get_fee_estimates())?;
+        targets
+            .iter()
+            .map(|target| fee_rate_from_estimates(estimates.clone(), *target))
+            .collect()
+    }
+}
+
+impl UrlClient {
+    fn new(servers: Servers, concurrency: u8) -> Self {
+        UrlClient {
+            servers,
+            client: Client::new(),
+            concurrency,
+        }
+    }
+}
+
+/// Implement the methods of [`UrlClient`] that send requests: they are `async` with the async
+/// client and return the results directly with the blocking one
+macro_rules! impl_url_client {
+    ($($async:tt)?) => {
+        impl UrlClient {
+            /// Send a request to the servers, starting from the one currently used
+            ///
+            /// Requests that fail with a transient error are retried with a backoff, and the next
+            /// server is tried once the retries are exhausted. The response is returned as soon as
+            /// its status isn't transient, even if it's an error.
+            $($async)? fn _send(
+                &self,
+                path: &str,
+                body: Option<String>,
+            ) -> Result<HttpResponse, EsploraError> {
+                let mut last_result = None;
+                for index in self.servers.order() {
+                    let url = format!("{}{}", self.servers.urls[index], path);
+                    let mut backoff = INITIAL_BACKOFF;
+                    for attempt in 0..=self.servers.max_retries {
+                        maybe_client_await!(Client::sleep(self.servers.reserve_request()));
+
+                        let result = maybe_client_await!(self.client.send(&url, body.clone()));
+                        let wait = match &result {
+                            Ok(resp) if is_transient(resp.status) => {
+                                debug!("{} returned {}", url, resp.status);
+                                parse_retry_after(resp.retry_after.as_deref()).unwrap_or(backoff)
+                            }
+                            Err(e) if e.is_transient() => {
+                                debug!("request to {} failed: {}", url, e);
+                                backoff
+                            }
+                            _ => {
+                                self.servers.set_current(index);
+                                return result;
+                            }
+                        };
+                        last_result = Some(result);
+
+                        if attempt < self.servers.max_retries {
+                            maybe_client_await!(Client::sleep(wait));
+                            backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+                        }
+                    }
+
+                    info!("Giving up on the server {}", self.servers.urls[index]);
+                }
+
+                last_result.expect("at least one request is sent")
+            }
+
+            $($async)? fn _get(&self, path: &str) -> Result<HttpResponse, EsploraError> {
+                maybe_client_await!(self._send(path, None))
+            }
+
+            $($async)? fn _get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, EsploraError> {
+                let resp = maybe_client_await!(self._get(&format!("/tx/{}/raw", txid)))?;
+
+                if resp.status == 404 {
+                    return Ok(None);
+                }
+
+                Ok(Some(deserialize(&resp.error_for_status()?.body)?))
+            }
+
+            $($async)? fn _get_tx_no_opt(&self, txid: &Txid) -> Result<Transaction, EsploraError> {
+                match maybe_client_await!(self._get_tx(txid)) {
+                    Ok(Some(tx)) => Ok(tx),
+                    Ok(None) => Err(EsploraError::TransactionNotFound(*txid)),
+                    Err(e) => Err(e),
+                }
+            }
+
+            $($async)? fn _get_header(
+                &self,
+                block_height: u32,
+            ) -> Result<BlockHeader, EsploraError> {
+                let path = format!("/block-height/{}", block_height);
+                let resp = maybe_client_await!(self._get(&path))?;
+
+                if resp.status == 404 {
+                    return Err(EsploraError::HeaderHeightNotFound(block_height));
+                }
+                let hash = resp.error_for_status()?.text();
+
+                let path = format!("/block/{}/header", hash.trim());
+                let resp = maybe_client_await!(self._get(&path))?;
+
+                if resp.status == 404 {
+                    return Err(EsploraError::HeaderHeightNotFound(block_height));
+                }
+                let header = deserialize(&Vec::from_hex(resp.error_for_status()?.text().trim())?)?;
+
+                Ok(header)
+            }
+
+            $($async)? fn _broadcast(&self, transaction: &Transaction) -> Result<(), EsploraError> {
+                maybe_client_await!(self._send("/tx", Some(serialize(transaction).to_hex())))?
+                    .error_for_status()?;
+
+                Ok(())
+            }
+
+            $($async)? fn _get_height(&self) -> Result<u32, EsploraError> {
+                let resp = maybe_client_await!(self._get("/blocks/tip/height"))?;
+
+                Ok(resp.error_for_status()?.text().trim().parse()?)
+            }
+
+            $($async)? fn _script_get_history(
+                &self,
+                script: &Script,
+            ) -> Result<Vec<ElsGetHistoryRes>, EsploraError> {
+                let scripthash = script_to_scripthash(script);
+
+                // Add the unconfirmed transactions first
+                let mut result = maybe_client_await!(self._script_get_mempool(script))?;
+
+                debug!(
+                    "Found {} mempool txs for {} - {:?}",
+                    result.len(),
+                    scripthash,
+                    script
+                );
+
+                // Then go through all the pages of confirmed transactions
+                let mut last_txid = String::new();
+                loop {
+                    let response = maybe_client_await!(self._get(&format!(
+                        "/scripthash/{}/txs/chain/{}",
+                        scripthash, last_txid
+                    )))?
+                    .error_for_status()?
+                        .json::<Vec<EsploraGetHistory>>()?;
+                    let len = response.len();
+                    if let Some(elem) = response.last() {
+                        last_txid = elem.txid.to_hex();
+                    }
+
+                    debug!("... adding {} confirmed transactions", len);
+
+                    result.extend(response.into_iter().map(EsploraGetHistory::into_els));
+
+                    if len < HISTORY_PAGE_SIZE {
+                        break;
+                    }
+                }
+
+                Ok(result)
+            }
+
+            $($async)? fn _script_get_mempool(
+                &self,
+                script: &Script,
+            ) -> Result<Vec<ElsGetHistoryRes>, EsploraError> {
+                let scripthash = script_to_scripthash(script);
+
+                let path = format!("/scripthash/{}/txs/mempool", scripthash);
+                Ok(maybe_client_await!(self._get(&path))?
+                    .error_for_status()?
+                    .json::<Vec<EsploraGetHistory>>()?
+                    .into_iter()
+                    .map(EsploraGetHistory::into_els)
+                    .collect())
+            }
+
+            $($async)? fn _get_fee_estimates(&self) -> Result<HashMap<String, f64>, EsploraError> {
+                maybe_client_await!(self._get("/fee-estimates"))?
+                    .error_for_status()?
+                    .json::<HashMap<String, f64>>()
+            }
+        }
+    };
+}
+
+#[cfg(feature = "esplora")]
+impl_url_client!(async);
+#[cfg(not(feature = "esplora"))]
+impl_url_client!();
+
+#[cfg(feature = "esplora")]
+impl UrlClient {
+    /// Call `f` for every item, sending up to `concurrency` requests at a time, and return the
+    /// results in the same order
+    async fn _batch<I, F, Fut, T>(&self, items: I, f: F) -> Result<Vec<T>, EsploraError>
+    where
+        I: IntoIterator,
+        F: Fn(I::Item) -> Fut,
+        Fut: Future<Output = Result<T, EsploraError>>,
+    {
+        let chunk_size = std::cmp::max(self.concurrency, 1) as usize;
+
+        let mut results = vec![];
+        for chunk in ChunksIterator::new(items.into_iter(), chunk_size) {
+            let partial_results: Vec<T> = chunk
+                .into_iter()
+                .map(&f)
+                .collect::<FuturesOrdered<_>>()
+                .try_collect()
+                .await?;
+            results.extend(partial_results);
+        }
+
+        Ok(results)
+    }
+}
+
+#[cfg(not(feature = "esplora"))]
+impl UrlClient {
+    /// Call `f` for every item, sending the requests one at a time, and return the results in
+    /// the same order
+    fn _batch<I, F, T>(&self, items: I, f: F) -> Result<Vec<T>, EsploraError>
+    where
+        I: IntoIterator,
+        F: Fn(I::Item) -> Result<T, EsploraError>,
+    {
+        items.into_iter().map(f).collect()
+    }
+}
+
+#[maybe_async]
+impl ElectrumLikeSync for UrlClient {
+    fn els_get_height(&self) -> Result<u32, Error> {
+        Ok(await_client!(self._get_height())?)
+    }
+
+    fn els_batch_script_get_history<'s, I: IntoIterator<Item = &'s Script>>(
+        &self,
+        scripts: I,
+    ) -> Result<Vec<Vec<ElsGetHistoryRes>>, Error> {
+        Ok(await_client!(
+            self._batch(scripts, |script| self._script_get_history(script))
+        )?)
+    }
+
+    fn els_batch_script_get_mempool<'s, I: IntoIterator<Item = &'s Script>>(
+        &self,
+        scripts: I,
+    ) -> Result<Option<Vec<Vec<ElsGetHistoryRes>>>, Error> {
+        Ok(Some(await_client!(
+            self._batch(scripts, |script| self._script_get_mempool(script))
+        )?))
+    }
+
+    fn els_batch_transaction_get<'s, I: IntoIterator<Item = &'s Txid>>(
+        &self,
+        txids: I,
+    ) -> Result<Vec<Transaction>, Error> {
+        Ok(await_client!(
+            self._batch(txids, |txid| self._get_tx_no_opt(txid))
+        )?)
+    }
+
+    fn els_batch_block_header<I: IntoIterator<Item = u32>>(
+        &self,
+        heights: I,
+    ) -> Result<Vec<BlockHeader>, Error> {
+        Ok(await_client!(
+            self._batch(heights, |height| self._get_header(height))
+        )?)
+    }
+}
+
+fn script_to_scripthash(script: &Script) -> String {
+    sha256::Hash::hash(script.as_bytes()).into_inner().to_hex()
+}
+
+/// Pick the fee rate for `target` from the response of `/fee-estimates`
+fn fee_rate_from_estimates(
+    estimates: HashMap<String, f64>,
+    target: usize,
+) -> Result<FeeRate, Error> {
+    let mut estimates = estimates
+        .into_iter()
+        .map(|(k, v)| Ok::<_, std::num::ParseIntError>((k.parse::<usize>()?, v)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::Generic(e.to_string()))?;
+    estimates.sort_by_key(|(k, _)| *k);
+
+    let fee_val = estimates
+        .into_iter()
+        .take_while(|(k, _)| k <= &target)
+        .map(|(_, v)| v)
+        .last()
+        .unwrap_or(1.0);
+
+    Ok(FeeRate::from_sat_per_vb(fee_val as f32))
+}
+
+/// Whether a request that failed with `status` may succeed if retried
+fn is_transient(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+/// Parse the delay requested by the server with the `Retry-After` header, if any
+///
+/// Only the delay in seconds is supported, not the HTTP date.
+fn parse_retry_after(header: Option<&str>) -> Option<Duration> {
+    header?.trim().parse().ok().map(Duration::from_secs)
+}
+
+#[derive(Deserialize)]
+struct EsploraGetHistoryStatus {
+    block_height: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct EsploraGetHistory {
+    txid: Txid,
+    status: EsploraGetHistoryStatus,
+}
+
+impl EsploraGetHistory {
+    fn into_els(self) -> ElsGetHistoryRes {
+        ElsGetHistoryRes {
+            tx_hash: self.txid,
+            height: self.status.block_height.unwrap_or(0) as i32,
+        }
+    }
+}
+
+/// Configuration for an [`EsploraBlockchain`]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+pub struct EsploraBlockchainConfig {
+    /// Base URL of the esplora service
+    ///
+    /// eg. `https://blockstream.info/api/`
+    pub base_url: String,
+    /// Number of parallel requests sent to the esplora service (default: 4)
+    ///
+    /// Ignored by the blocking client, used when `esplora-blocking` is enabled without `esplora`.
+    pub concurrency: Option<u8>,
+    /// Base URLs of the servers used when the previous ones fail, tried in order
+    pub fallback_urls: Option<Vec<String>>,
+    /// Number of times a request that fails with a transient error is retried before trying the
+    /// next server (default: 3)
+    pub max_retries: Option<u8>,
+    /// Maximum number of requests sent every second, across all the servers (default: unlimited)
+    pub max_requests_per_second: Option<u32>,
+    /// URL of the proxy used to send all the requests, such as `socks5://127.0.0.1:9050` for Tor
+    ///
+    /// See [`EsploraBlockchain::with_proxy`].
+    pub proxy: Option<String>,
+    /// Optional proxy credentials, as a `(username, password)` pair
+    ///
+    /// Tor sends the requests made with different credentials over different circuits, which
+    /// can be used to isolate the traffic of each wallet.
+    pub proxy_credentials: Option<(String, String)>,
+}
+
+impl ConfigurableBlockchain for EsploraBlockchain {
+    type Config = EsploraBlockchainConfig;
+
+    fn from_config(config: &Self::Config) -> Result<Self, Error> {
+        let mut blockchain = EsploraBlockchain::new(config.base_url.as_str(), config.concurrency)
+            .with_fallback_urls(config.fallback_urls.clone().unwrap_or_default());
+        if let Some(max_retries) = config.max_retries {
+            blockchain = blockchain.with_max_retries(max_retries);
+        }
+        if let Some(max_requests_per_second) = config.max_requests_per_second {
+            blockchain = blockchain.with_rate_limit(max_requests_per_second);
+        }
+        if let Some(proxy) = &config.proxy {
+            let credentials = config
+                .proxy_credentials
+                .as_ref()
+                .map(|(a, b)| (a.as_str(), b.as_str()));
+            blockchain = blockchain.with_proxy(proxy, credentials)?;
+        }
+
+        Ok(blockchain)
+    }
+}
+
+/// Errors that can happen during a sync with [`EsploraBlockchain`]
+#[derive(Debug)]
+pub enum EsploraError {
+    /// Error with the HTTP call
+    #[cfg(feature = "esplora")]
+    Reqwest(::reqwest::Error),
+    /// Error with the HTTP call
+    #[cfg(feature = "esplora-blocking")]
+    UreqTransport(Box<::ureq::Transport>),
+    /// The server answered with an HTTP error status
+    HttpResponse(u16),
+    /// Error while reading the HTTP response
+    #[cfg(feature = "esplora-blocking")]
+    Io(std::io::Error),
+    /// Invalid JSON returned
+    Json(serde_json::Error),
+    /// Invalid number returned
+    Parsing(std::num::ParseIntError),
+    /// Invalid Bitcoin data returned
+    BitcoinEncoding(bitcoin::consensus::encode::Error),
+    /// Invalid Hex data returned
+    Hex(bitcoin::hashes::hex::Error),
+
+    /// Transaction not found
+    TransactionNotFound(Txid),
+    /// Header height not found
+    HeaderHeightNotFound(u32),
+    /// Header hash not found
+    HeaderHashNotFound(BlockHash),
+    /// Proxies aren't supported by the HTTP client on this platform
+    ProxyNotSupported,
+    /// The proxy credentials can't be passed to the HTTP client
+    InvalidProxyCredentials,
+}
+
+impl fmt::Display for EsploraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for EsploraError {}
+
+impl EsploraError {
+    /// Whether the request that failed with this error may succeed if retried
+    fn is_transient(&self) -> bool {
+        match self {
+            #[cfg(feature = "esplora")]
+            EsploraError::Reqwest(e) => e.is_request() || e.is_timeout(),
+            #[cfg(feature = "esplora-blocking")]
+            EsploraError::UreqTransport(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(feature = "esplora")]
+impl_error!(::reqwest::Error, Reqwest, EsploraError);
+#[cfg(feature = "esplora-blocking")]
+impl_error!(std::io::Error, Io, EsploraError);
+impl_error!(serde_json::Error, Json, EsploraError);
+impl_error!(std::num::ParseIntError, Parsing, EsploraError);
+impl_error!(consensus::encode::Error, BitcoinEncoding, EsploraError);
+impl_error!(bitcoin::hashes::hex::Error, Hex, EsploraError);
+
+#[cfg(feature = "esplora-blocking")]
+impl From<::ureq::Error> for EsploraError {
+    fn from(other: ::ureq::Error) -> Self {
+        match other {
+            ::ureq::Error::Status(status, _) => EsploraError::HttpResponse(status),
+            ::ureq::Error::Transport(e) => e.into(),
+        }
+    }
+}
+
+#[cfg(feature = "esplora-blocking")]
+impl From<::ureq::Transport> for EsploraError {
+    fn from(other: ::ureq::Transport) -> Self {
+        EsploraError::UreqTransport(Box::new(other))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config_without_fallbacks() {
+        let config: EsploraBlockchainConfig =
+            serde_json::from_str(r#"{"base_url": "http://localhost:3002", "concurrency": 2}"#)
+                .unwrap();
+        assert_eq!(config.fallback_urls, None);
+
+        let blockchain = EsploraBlockchain::from_config(&config).unwrap();
+        let servers = &blockchain.0.servers;
+        assert_eq!(servers.urls, vec!["http://localhost:3002".to_string()]);
+        assert_eq!(servers.max_retries, DEFAULT_MAX_RETRIES);
+        assert_eq!(servers.request_interval, None);
+        assert_eq!(servers.reserve_request(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_config_fallbacks_and_rate_limit() {
+        let config = EsploraBlockchainConfig {
+            base_url: "http://localhost:3002".to_string(),
+            concurrency: None,
+            fallback_urls: Some(vec!["http://localhost:3003".to_string()]),
+            max_retries: Some(0),
+            max_requests_per_second: Some(4),
+            proxy: None,
+            proxy_credentials: None,
+        };
+
+        let blockchain = EsploraBlockchain::from_config(&config).unwrap();
+        let servers = &blockchain.0.servers;
+        assert_eq!(
+            servers.urls,
+            vec![
+                "http://localhost:3002".to_string(),
+                "http://localhost:3003".to_string()
+            ]
+        );
+        assert_eq!(servers.max_retries, 0);
+        assert_eq!(servers.request_interval, Some(Duration::from_millis(250)));
+
+        servers.set_current(1);
+        assert_eq!(servers.order().collect::<Vec<_>>(), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_config_with_proxy() {
+        let mut config: EsploraBlockchainConfig = serde_json::from_str(
+            r#"{
+                "base_url": "http://localhost:3002",
+                "proxy": "socks5://127.0.0.1:9050",
+                "proxy_credentials": ["wallet1", "password"]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.proxy_credentials,
+            Some(("wallet1".to_string(), "password".to_string()))
+        );
+        assert!(EsploraBlockchain::from_config(&config).is_ok());
+
+        config.proxy = Some("ftp://127.0.0.1:21".to_string());
+        assert!(EsploraBlockchain::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_transient_status() {
+        assert!(is_transient(429));
+        assert!(is_transient(503));
+        assert!(!is_transient(404));
+        assert!(!is_transient(400));
+
+        assert_eq!(parse_retry_after(Some(" 5")), Some(Duration::from_secs(5)));
+        assert_eq!(
+            parse_retry_after(Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+            None
+        );
+        assert_eq!(parse_retry_after(None), None);
+    }
+
+    #[test]
+    fn test_fee_rate_from_estimates() {
+        let estimates = vec![("1", 20.0), ("6", 10.0), ("3", 15.0), ("144", 1.5)]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect::<HashMap<_, _>>();
+
+        let fee_rate = fee_rate_from_estimates(estimates.clone(), 4).unwrap();
+        assert_eq!(fee_rate, FeeRate::from_sat_per_vb(15.0));
+        let fee_rate = fee_rate_from_estimates(estimates, 1000).unwrap();
+        assert_eq!(fee_rate, FeeRate::from_sat_per_vb(1.5));
+    }
+}
+
+#[cfg(feature = "test-blockchains")]
+crate::bdk_blockchain_tests! {
+    fn test_instance() -> EsploraBlockchain {
+        EsploraBlockchain::new(std::env::var("BDK_ESPLORA_URL").unwrap_or("127.0.0.1:3002".into()).as_str(), None)
+    }
+}
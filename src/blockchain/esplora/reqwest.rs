@@ -0,0 +1,88 @@
+// Bitcoin Dev Kit
+// Written in 2020 by Alekos Filini <alekos.filini@gmail.com>
+//
+// Copyright (c) 2020-2021 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Esplora by way of `reqwest` HTTP client.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use ::reqwest::header::RETRY_AFTER;
+use ::reqwest::Client;
+#[cfg(not(target_arch = "wasm32"))]
+use ::reqwest::Proxy;
+
+use super::{EsploraError, HttpClient, HttpResponse, ResponseFuture};
+
+// We use the async client instead of the blocking one because it automatically uses `fetch`
+// when the target platform is wasm32.
+#[derive(Debug)]
+pub(super) struct ReqwestClient(Client);
+
+impl HttpClient for ReqwestClient {
+    fn new() -> Self {
+        ReqwestClient(Client::new())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn with_proxy(proxy: &str, credentials: Option<(&str, &str)>) -> Result<Self, EsploraError> {
+        // With `socks5h` the host names are resolved by the proxy instead of locally
+        let proxy = match proxy.strip_prefix("socks5://") {
+            Some(address) => format!("socks5h://{}", address),
+            None => proxy.to_string(),
+        };
+        let mut proxy = Proxy::all(&proxy)?;
+        if let Some((username, password)) = credentials {
+            proxy = proxy.basic_auth(username, password);
+        }
+
+        Ok(ReqwestClient(Client::builder().proxy(proxy).build()?))
+    }
+    // The `fetch` API doesn't let us pick a proxy
+    #[cfg(target_arch = "wasm32")]
+    fn with_proxy(_proxy: &str, _credentials: Option<(&str, &str)>) -> Result<Self, EsploraError> {
+        Err(EsploraError::ProxyNotSupported)
+    }
+
+    fn send<'a>(&'a self, url: &'a str, body: Option<String>) -> ResponseFuture<'a> {
+        Box::pin(async move {
+            let request = match body {
+                Some(body) => self.0.post(url).body(body),
+                None => self.0.get(url),
+            };
+            let resp = request.send().await?;
+
+            let status = resp.status().as_u16();
+            let retry_after = resp
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from);
+            let body = resp.bytes().await?.to_vec();
+
+            Ok(HttpResponse {
+                status,
+                retry_after,
+                body,
+            })
+        })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn sleep(duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+    // There's no timer available on wasm32, don't wait
+    #[cfg(target_arch = "wasm32")]
+    fn sleep(_duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(async {})
+    }
+}
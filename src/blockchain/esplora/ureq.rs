@@ -0,0 +1,103 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2021 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Esplora by way of `ureq` HTTP client.
+
+use std::io::Read;
+use std::thread;
+use std::time::Duration;
+
+use ::ureq::{Agent, AgentBuilder, Proxy};
+
+use super::{EsploraError, HttpClient, HttpResponse};
+
+/// Blocking client, the requests are sent one at a time
+#[derive(Debug)]
+pub(super) struct UreqClient(Agent);
+
+impl HttpClient for UreqClient {
+    fn new() -> Self {
+        UreqClient(Agent::new())
+    }
+
+    fn with_proxy(proxy: &str, credentials: Option<(&str, &str)>) -> Result<Self, EsploraError> {
+        let (scheme, address) = match proxy.find("://") {
+            Some(index) => (&proxy[..index], &proxy[index + 3..]),
+            None => ("http", proxy),
+        };
+        // `ureq` always resolves the host names through SOCKS5 proxies, but doesn't know `socks5h`
+        let scheme = if scheme == "socks5h" {
+            "socks5"
+        } else {
+            scheme
+        };
+        // `ureq` doesn't percent-decode the credentials in the proxy URL: it splits them from the
+        // address at the last '@' and the username from the password at the first ':', so a
+        // username containing ':' can't be passed through
+        let proxy = match credentials {
+            Some((username, _)) if username.contains(':') => {
+                return Err(EsploraError::InvalidProxyCredentials)
+            }
+            Some((username, password)) => {
+                format!("{}://{}:{}@{}", scheme, username, password, address)
+            }
+            None => format!("{}://{}", scheme, address),
+        };
+
+        Ok(UreqClient(
+            AgentBuilder::new().proxy(Proxy::new(proxy)?).build(),
+        ))
+    }
+
+    fn send(&self, url: &str, body: Option<String>) -> Result<HttpResponse, EsploraError> {
+        let result = match body {
+            Some(body) => self.0.post(url).send_string(&body),
+            None => self.0.get(url).call(),
+        };
+        let resp = match result {
+            Ok(resp) | Err(::ureq::Error::Status(_, resp)) => resp,
+            Err(::ureq::Error::Transport(e)) => return Err(e.into()),
+        };
+
+        let status = resp.status();
+        let retry_after = resp.header("Retry-After").map(String::from);
+        let mut body = Vec::new();
+        resp.into_reader().read_to_end(&mut body)?;
+
+        Ok(HttpResponse {
+            status,
+            retry_after,
+            body,
+        })
+    }
+
+    fn sleep(duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_with_proxy_credentials() {
+        let proxy = "socks5h://127.0.0.1:9050";
+        assert!(UreqClient::with_proxy(proxy, None).is_ok());
+        assert!(UreqClient::with_proxy(proxy, Some(("wallet1", "password"))).is_ok());
+        // only the first ':' and the last '@' are special
+        assert!(UreqClient::with_proxy(proxy, Some(("wallet@1", "pass:w@rd/"))).is_ok());
+
+        assert!(matches!(
+            UreqClient::with_proxy(proxy, Some(("wallet:1", "password"))),
+            Err(EsploraError::InvalidProxyCredentials)
+        ));
+    }
+}
@@ -27,12 +27,26 @@ use crate::database::BatchDatabase;
 use crate::error::Error;
 use crate::FeeRate;
 
-#[cfg(any(feature = "electrum", feature = "esplora"))]
+#[cfg(any(
+    feature = "electrum",
+    feature = "esplora",
+    feature = "esplora-blocking"
+))]
 pub(crate) mod utils;
 
-#[cfg(any(feature = "electrum", feature = "esplora", feature = "compact_filters"))]
+#[cfg(any(
+    feature = "electrum",
+    feature = "esplora",
+    feature = "esplora-blocking",
+    feature = "compact_filters"
+))]
 pub mod any;
-#[cfg(any(feature = "electrum", feature = "esplora", feature = "compact_filters"))]
+#[cfg(any(
+    feature = "electrum",
+    feature = "esplora",
+    feature = "esplora-blocking",
+    feature = "compact_filters"
+))]
 pub use any::{AnyBlockchain, AnyBlockchainConfig};
 
 #[cfg(feature = "electrum")]
@@ -50,11 +64,23 @@ pub mod rpc;
 pub use self::rpc::RpcBlockchain;
 #[cfg(feature = "rpc")]
 pub use self::rpc::RpcConfig;
+#[cfg(feature = "rpc")]
+pub use self::rpc::RpcDescriptors;
+#[cfg(feature = "rpc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rpc")))]
+pub mod rpc_blocks;
+#[cfg(feature = "rpc")]
+pub use self::rpc_blocks::RpcBlocksBlockchain;
+#[cfg(feature = "rpc")]
+pub use self::rpc_blocks::RpcBlocksConfig;
 
-#[cfg(feature = "esplora")]
-#[cfg_attr(docsrs, doc(cfg(feature = "esplora")))]
+#[cfg(any(feature = "esplora", feature = "esplora-blocking"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(feature = "esplora", feature = "esplora-blocking")))
+)]
 pub mod esplora;
-#[cfg(feature = "esplora")]
+#[cfg(any(feature = "esplora", feature = "esplora-blocking"))]
 pub use self::esplora::EsploraBlockchain;
 
 #[cfg(feature = "compact_filters")]
@@ -124,6 +150,22 @@ pub trait Blockchain {
     ) -> Result<(), Error> {
         maybe_await!(self.setup(stop_gap, database, progress_update))
     }
+    /// Update the internal database with the changes since the last sync, doing as little work
+    /// as possible
+    ///
+    /// This is meant to be called frequently, for example to spot incoming payments. Backends
+    /// that can tell when nothing but the mempool has changed only look for new unconfirmed
+    /// transactions, and only run a full [`Blockchain::sync`] when a new block is found.
+    ///
+    /// If not overridden, it defaults to calling [`Blockchain::sync`] internally.
+    fn refresh<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        database: &mut D,
+        progress_update: P,
+    ) -> Result<(), Error> {
+        maybe_await!(self.sync(stop_gap, database, progress_update))
+    }
 
     /// Fetch a transaction from the blockchain given its txid
     fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error>;
@@ -134,6 +176,20 @@ pub trait Blockchain {
     fn get_height(&self) -> Result<u32, Error>;
     /// Estimate the fee rate required to confirm a transaction in a given `target` of blocks
     fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error>;
+    /// Estimate the fee rates required to confirm a transaction within each of the `targets`
+    ///
+    /// The estimates are returned in the same order as `targets`. Backends that can answer for
+    /// many targets at once override this to avoid a round trip per target.
+    ///
+    /// If not overridden, it defaults to calling [`Blockchain::estimate_fee`] for every target.
+    fn estimate_fees(&self, targets: &[usize]) -> Result<Vec<FeeRate>, Error> {
+        let mut estimates = Vec::with_capacity(targets.len());
+        for target in targets {
+            estimates.push(maybe_await!(self.estimate_fee(*target))?);
+        }
+
+        Ok(estimates)
+    }
 }
 
 /// Trait for [`Blockchain`] types that can be created given a configuration
@@ -234,6 +290,15 @@ impl<T: Blockchain> Blockchain for Arc<T> {
         maybe_await!(self.deref().sync(stop_gap, database, progress_update))
     }
 
+    fn refresh<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        database: &mut D,
+        progress_update: P,
+    ) -> Result<(), Error> {
+        maybe_await!(self.deref().refresh(stop_gap, database, progress_update))
+    }
+
     fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error> {
         maybe_await!(self.deref().get_tx(txid))
     }
@@ -247,4 +312,7 @@ impl<T: Blockchain> Blockchain for Arc<T> {
     fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
         maybe_await!(self.deref().estimate_fee(target))
     }
+    fn estimate_fees(&self, targets: &[usize]) -> Result<Vec<FeeRate>, Error> {
+        maybe_await!(self.deref().estimate_fees(targets))
+    }
 }
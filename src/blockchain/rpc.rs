@@ -15,6 +15,16 @@
 //!
 //! This is an **EXPERIMENTAL** feature, API and other major changes are expected.
 //!
+//! When the public descriptors of the wallet are given in [`RpcConfig::descriptors`] and the node
+//! supports them (Bitcoin Core 0.21 or newer), the backend creates a descriptor wallet in the node
+//! and imports them with `importdescriptors`, so that the node itself keeps track of the addresses
+//! of the wallet beyond the ones cached in the database. Otherwise a legacy wallet is created and
+//! the cached script_pubkeys are imported with `importmulti`.
+//!
+//! With [`RpcConfig::scan_utxo_set`] the backend only recovers the current UTXOs of the
+//! descriptors with `scantxoutset`, which is much faster than a rescan but doesn't recover the
+//! history of the wallet.
+//!
 //! ## Example
 //!
 //! ```no_run
@@ -25,28 +35,43 @@
 //!     network: bdk::bitcoin::Network::Testnet,
 //!     wallet_name: "wallet_name".to_string(),
 //!     skip_blocks: None,
+//!     descriptors: None,
+//!     scan_utxo_set: false,
+//!     fee_estimate_mode: None,
 //! };
 //! let blockchain = RpcBlockchain::from_config(&config);
 //! ```
 
 use crate::bitcoin::consensus::deserialize;
-use crate::bitcoin::{Address, Network, OutPoint, Transaction, TxOut, Txid};
+use crate::bitcoin::secp256k1::Secp256k1;
+use crate::bitcoin::{Address, Network, OutPoint, Script, Transaction, TxOut, Txid};
 use crate::blockchain::{Blockchain, Capability, ConfigurableBlockchain, Progress};
 use crate::database::{BatchDatabase, DatabaseUtils};
-use crate::descriptor::{get_checksum, IntoWalletDescriptor};
+use crate::descriptor::derived::AsDerived;
+use crate::descriptor::{get_checksum, ExtendedDescriptor, IntoWalletDescriptor};
 use crate::wallet::utils::SecpCtx;
-use crate::{ConfirmationTime, Error, FeeRate, KeychainKind, LocalUtxo, TransactionDetails};
+use crate::{
+    Birthday, ConfirmationTime, Error, FeeRate, KeychainKind, LocalUtxo, TransactionDetails,
+};
 use bitcoincore_rpc::json::{
-    GetAddressInfoResultLabel, ImportMultiOptions, ImportMultiRequest,
-    ImportMultiRequestScriptPubkey, ImportMultiRescanSince,
+    EstimateMode, EstimateSmartFeeResult, GetAddressInfoResultLabel,
+    GetTransactionResultDetailCategory, ImportMultiOptions, ImportMultiRequest,
+    ImportMultiRequestScriptPubkey, ImportMultiRescanSince, ScanTxOutRequest,
 };
 use bitcoincore_rpc::jsonrpc::serde_json::Value;
 use bitcoincore_rpc::{Auth, Client, RpcApi};
 use log::debug;
+use miniscript::descriptor::DescriptorTrait;
 use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 
+/// Number of blocks rescanned by the node with each `rescanblockchain` call during the setup
+const RESCAN_CHUNK_SIZE: u32 = 10_000;
+/// Number of script_pubkeys after the cached ones that are derived to find the index of a
+/// script_pubkey found by a descriptor wallet, which is the default keypool size of the node
+const DESCRIPTOR_LOOKAHEAD: u32 = 1_000;
+
 /// The main struct for RPC backend implementing the [crate::blockchain::Blockchain] trait
 #[derive(Debug)]
 pub struct RpcBlockchain {
@@ -58,11 +83,31 @@ pub struct RpcBlockchain {
     capabilities: HashSet<Capability>,
     /// Skip this many blocks of the blockchain at the first rescan, if None the rescan is done from the genesis block
     skip_blocks: Option<u32>,
+    /// Number of blocks rescanned with each `rescanblockchain` call, [`RESCAN_CHUNK_SIZE`]
+    /// outside of the tests
+    rescan_chunk_size: u32,
+
+    /// Descriptors of the wallet known by the node, `None` with a legacy wallet
+    descriptors: Option<Vec<NodeDescriptor>>,
+    /// Only recover the UTXOs of the wallet with `scantxoutset`
+    scan_utxo_set: bool,
+    /// Mode passed to `estimatesmartfee`, if None the default of the node is used
+    fee_estimate_mode: Option<EstimateMode>,
+    secp: SecpCtx,
 
     /// This is a fixed Address used as a hack key to store information on the node
     _storage_address: Address,
 }
 
+/// A descriptor of the wallet imported in a descriptor wallet of the node
+#[derive(Debug)]
+struct NodeDescriptor {
+    keychain: KeychainKind,
+    descriptor: ExtendedDescriptor,
+    /// Canonical form of the descriptor returned by `getdescriptorinfo`, including the checksum
+    canonical: String,
+}
+
 /// RpcBlockchain configuration options
 #[derive(Debug)]
 pub struct RpcConfig {
@@ -76,20 +121,302 @@ pub struct RpcConfig {
     pub wallet_name: String,
     /// Skip this many blocks of the blockchain at the first rescan, if None the rescan is done from the genesis block
     pub skip_blocks: Option<u32>,
+    /// Public descriptors of the wallet, imported in a descriptor wallet of the node if it
+    /// supports them
+    ///
+    /// This is only taken into account when the wallet is created in the node: an existing legacy
+    /// wallet keeps using `importmulti`.
+    pub descriptors: Option<RpcDescriptors>,
+    /// Only recover the current UTXOs of the wallet with `scantxoutset`, without using a wallet in
+    /// the node or rescanning the blockchain
+    ///
+    /// This is meant to quickly restore a wallet when only its balance matters: the history isn't
+    /// recovered, only the transactions that created the UTXOs are stored in the database, so
+    /// [`Capability::FullHistory`] isn't declared. Unconfirmed UTXOs aren't found either.
+    /// The inputs of the transactions recovered aren't looked up, so they are stored with
+    /// `sent: 0` and no fee, even if they spend some outputs of the wallet.
+    /// Requires [`RpcConfig::descriptors`].
+    pub scan_utxo_set: bool,
+    /// Mode passed to `estimatesmartfee` when estimating fees, either
+    /// [`EstimateMode::Economical`] or [`EstimateMode::Conservative`]
+    ///
+    /// If None the default mode of the node is used, which is conservative unless the node is
+    /// configured otherwise.
+    pub fee_estimate_mode: Option<EstimateMode>,
+}
+
+/// Descriptors of the wallet to import in the node, see [`RpcConfig::descriptors`]
+///
+/// Descriptors containing private keys are imported in their public form.
+#[derive(Debug, Clone)]
+pub struct RpcDescriptors {
+    /// Descriptor of the external keychain
+    pub descriptor: String,
+    /// Descriptor of the internal keychain, if any
+    pub change_descriptor: Option<String>,
 }
 
 impl RpcBlockchain {
-    fn get_node_synced_height(&self) -> Result<u32, Error> {
+    /// Get the synced height stored in the core node, or `default` if the wallet was never
+    /// synced
+    fn get_node_synced_height(&self, default: u32) -> Result<u32, Error> {
         let info = self.client.get_address_info(&self._storage_address)?;
         if let Some(GetAddressInfoResultLabel::Simple(label)) = info.labels.first() {
-            Ok(label
-                .parse::<u32>()
-                .unwrap_or_else(|_| self.skip_blocks.unwrap_or(0)))
+            Ok(label.parse::<u32>().unwrap_or(default))
         } else {
-            Ok(self.skip_blocks.unwrap_or(0))
+            Ok(default)
         }
     }
 
+    /// Import the script_pubkeys cached in `database` in a legacy wallet of the node
+    fn import_script_pubkeys<D: BatchDatabase>(&self, database: &D) -> Result<(), Error> {
+        let mut scripts_pubkeys = database.iter_script_pubkeys(Some(KeychainKind::External))?;
+        scripts_pubkeys.extend(database.iter_script_pubkeys(Some(KeychainKind::Internal))?);
+        debug!(
+            "importing {} script_pubkeys (some maybe already imported)",
+            scripts_pubkeys.len()
+        );
+        let requests: Vec<_> = scripts_pubkeys
+            .iter()
+            .map(|s| ImportMultiRequest {
+                timestamp: ImportMultiRescanSince::Timestamp(0),
+                script_pubkey: Some(ImportMultiRequestScriptPubkey::Script(&s)),
+                watchonly: Some(true),
+                ..Default::default()
+            })
+            .collect();
+        let options = ImportMultiOptions {
+            rescan: Some(false),
+        };
+        self.client.import_multi(&requests, Some(&options))?;
+
+        Ok(())
+    }
+
+    /// Import the descriptors in a descriptor wallet of the node, with a range that covers at
+    /// least the script_pubkeys cached in `database`
+    ///
+    /// Ranged descriptors are imported as active, so that the node extends their range by itself
+    /// when it finds their addresses used. The rescan is left to the caller.
+    fn import_descriptors<D: BatchDatabase>(
+        &self,
+        descriptors: &[NodeDescriptor],
+        database: &D,
+    ) -> Result<(), Error> {
+        let requests = descriptors
+            .iter()
+            .map(|d| {
+                let mut request = serde_json::json!({
+                    "desc": d.canonical,
+                    "timestamp": "now",
+                    "internal": d.keychain == KeychainKind::Internal,
+                });
+                if d.descriptor.is_deriveable() {
+                    let cached = database.iter_script_pubkeys(Some(d.keychain))?.len() as u32;
+                    request["active"] = true.into();
+                    request["range"] = serde_json::json!([0, cached.saturating_sub(1)]);
+                }
+                Ok(request)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        debug!("importing {} descriptors", requests.len());
+
+        let results: Vec<Value> = self
+            .client
+            .call("importdescriptors", &[Value::Array(requests)])?;
+        for (result, d) in results.iter().zip(descriptors) {
+            if result["success"].as_bool() != Some(true) {
+                return Err(Error::Generic(format!(
+                    "Unable to import descriptor {}: {}",
+                    d.canonical, result["error"]
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the number of script_pubkeys that should be cached for each keychain to include
+    /// the `scripts` found by a descriptor wallet that aren't cached in `db`
+    fn missing_cached_scripts<D: BatchDatabase>(
+        &self,
+        descriptors: &[NodeDescriptor],
+        db: &D,
+        scripts: &HashSet<Script>,
+        stop_gap: usize,
+    ) -> Result<Vec<(KeychainKind, u32)>, Error> {
+        let mut unknown = HashSet::new();
+        for script in scripts {
+            if db.get_path_from_script_pubkey(script)?.is_none() {
+                unknown.insert(script);
+            }
+        }
+        if unknown.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut required = vec![];
+        for d in descriptors.iter().filter(|d| d.descriptor.is_deriveable()) {
+            let cached = db.iter_script_pubkeys(Some(d.keychain))?.len() as u32;
+            let max_index = (cached..cached + DESCRIPTOR_LOOKAHEAD)
+                .filter(|i| {
+                    unknown.contains(&d.descriptor.as_derived(*i, &self.secp).script_pubkey())
+                })
+                .max();
+            if let Some(index) = max_index {
+                debug!("{:?} script_pubkey {} found by the node", d.keychain, index);
+                required.push((d.keychain, index + 1 + stop_gap as u32));
+            }
+        }
+
+        Ok(required)
+    }
+
+    /// Replace the UTXOs in `db` with the ones found by `scantxoutset` for the script_pubkeys
+    /// cached, storing the transactions that created them
+    fn sync_utxo_set<D: BatchDatabase, P: Progress>(
+        &self,
+        descriptors: &[NodeDescriptor],
+        stop_gap: usize,
+        db: &mut D,
+        progress_update: &P,
+    ) -> Result<(), Error> {
+        let mut requests = vec![];
+        let mut cached = HashMap::new();
+        for d in descriptors {
+            if d.descriptor.is_deriveable() {
+                let count = db.iter_script_pubkeys(Some(d.keychain))?.len() as u32;
+                cached.insert(d.keychain, count);
+                requests.push(ScanTxOutRequest::Extended {
+                    desc: d.canonical.clone(),
+                    range: (0, count.saturating_sub(1) as u64),
+                });
+            } else {
+                requests.push(ScanTxOutRequest::Single(d.canonical.clone()));
+            }
+        }
+
+        progress_update.update(0.0, Some("Scanning the UTXO set".into()))?;
+        debug!("scantxoutset for {} descriptors", requests.len());
+        let result = self.client.scan_tx_out_set_blocking(&requests)?;
+        if result.success == Some(false) {
+            return Err(Error::Generic(
+                "The scan of the UTXO set was aborted".into(),
+            ));
+        }
+        debug!("scantxoutset found {} utxos", result.unspents.len());
+
+        let mut indexes = HashMap::new();
+        let mut current_utxos = HashMap::new();
+        let mut heights = HashMap::new();
+        for utxo in result.unspents {
+            // only the cached script_pubkeys have been scanned, anything else isn't ours
+            let (keychain, index) = match db.get_path_from_script_pubkey(&utxo.script_pub_key)? {
+                Some(path) => path,
+                None => {
+                    debug!("skipping utxo with unknown script: {}", utxo.script_pub_key);
+                    continue;
+                }
+            };
+            let max_index = indexes.entry(keychain).or_insert(index);
+            *max_index = std::cmp::max(*max_index, index);
+
+            heights.insert(utxo.txid, utxo.height);
+            current_utxos.insert(
+                OutPoint::new(utxo.txid, utxo.vout),
+                (
+                    keychain,
+                    TxOut {
+                        value: utxo.amount.as_sat(),
+                        script_pubkey: utxo.script_pub_key,
+                    },
+                ),
+            );
+        }
+
+        // scan again once enough script_pubkeys are cached after the last one used
+        let required: Vec<_> = indexes
+            .iter()
+            .filter_map(|(keychain, index)| {
+                let required = index + 1 + stop_gap as u32;
+                match cached.get(keychain) {
+                    Some(count) if *count < required => Some((*keychain, required)),
+                    _ => None,
+                }
+            })
+            .collect();
+        if !required.is_empty() {
+            return Err(Error::MissingCachedScripts { required });
+        }
+
+        // the utxo set doesn't contain the whole transactions, download the ones we don't have
+        // from their block so that no txindex is needed
+        for (txid, height) in heights {
+            if db.get_tx(&txid, false)?.is_some() {
+                continue;
+            }
+
+            let block_hash = self.client.get_block_hash(height)?;
+            let tx = self.client.get_raw_transaction(&txid, Some(&block_hash))?;
+            let timestamp = self.client.get_block_header(&block_hash)?.time as u64;
+            let mut received = 0;
+            for output in &tx.output {
+                if db.is_mine(&output.script_pubkey)? {
+                    received += output.value;
+                }
+            }
+
+            debug!("saving tx: {}", txid);
+            db.set_tx(&TransactionDetails {
+                txid,
+                transaction: Some(tx),
+                received,
+                // the inputs of the transaction aren't known
+                sent: 0,
+                fee: None,
+                confirmation_time: ConfirmationTime::new(Some(height as u32), Some(timestamp)),
+                verified: true,
+                label: None,
+            })?;
+        }
+
+        for utxo in db.iter_utxos()? {
+            if !current_utxos.contains_key(&utxo.outpoint) {
+                debug!("removing utxo: {:?}", utxo);
+                db.del_utxo(&utxo.outpoint)?;
+            }
+        }
+        for (outpoint, (keychain, txout)) in current_utxos {
+            if db.get_utxo(&outpoint)?.is_some() {
+                continue;
+            }
+
+            let utxo = LocalUtxo {
+                outpoint,
+                txout,
+                keychain,
+                label: None,
+                is_frozen: false,
+                is_coinbase: db
+                    .get_raw_tx(&outpoint.txid)?
+                    .map(|tx| tx.is_coin_base())
+                    .unwrap_or(false),
+            };
+            debug!("adding utxo: {:?}", utxo);
+            db.set_utxo(&utxo)?;
+        }
+
+        for (keychain, index) in indexes {
+            if db.get_last_index(keychain)?.is_none_or(|last| last < index) {
+                db.set_last_index(keychain, index)?;
+            }
+        }
+
+        progress_update.update(100.0, None)?;
+
+        Ok(())
+    }
+
     /// Set the synced height in the core node by using a label of a fixed address so that
     /// another client with the same descriptor doesn't rescan the blockchain
     fn set_node_synced_height(&self, height: u32) -> Result<(), Error> {
@@ -110,55 +437,63 @@ impl Blockchain for RpcBlockchain {
         database: &mut D,
         progress_update: P,
     ) -> Result<(), Error> {
-        let mut scripts_pubkeys = database.iter_script_pubkeys(Some(KeychainKind::External))?;
-        scripts_pubkeys.extend(database.iter_script_pubkeys(Some(KeychainKind::Internal))?);
-        debug!(
-            "importing {} script_pubkeys (some maybe already imported)",
-            scripts_pubkeys.len()
-        );
-        let requests: Vec<_> = scripts_pubkeys
-            .iter()
-            .map(|s| ImportMultiRequest {
-                timestamp: ImportMultiRescanSince::Timestamp(0),
-                script_pubkey: Some(ImportMultiRequestScriptPubkey::Script(&s)),
-                watchonly: Some(true),
-                ..Default::default()
-            })
-            .collect();
-        let options = ImportMultiOptions {
-            rescan: Some(false),
-        };
-        // Note we use import_multi because as of bitcoin core 0.21.0 many descriptors are not supported
+        if self.scan_utxo_set {
+            return self.sync(stop_gap, database, progress_update);
+        }
+
+        // Note we fall back to import_multi because as of bitcoin core 0.21.0 many descriptors are not supported
         // https://bitcoindevkit.org/descriptors/#compatibility-matrix
-        //TODO maybe convenient using import_descriptor for compatible descriptor and import_multi as fallback
-        self.client.import_multi(&requests, Some(&options))?;
+        match &self.descriptors {
+            Some(descriptors) => self.import_descriptors(descriptors, database)?,
+            None => self.import_script_pubkeys(database)?,
+        }
 
         let current_height = self.get_height()?;
 
-        // min because block invalidate may cause height to go down
-        let node_synced = self.get_node_synced_height()?.min(current_height);
-
-        //TODO call rescan in chunks (updating node_synced_height) so that in case of
-        // interruption work can be partially recovered
-        debug!(
-            "rescan_blockchain from:{} to:{}",
-            node_synced, current_height
-        );
-        self.client
-            .rescan_blockchain(Some(node_synced as usize), Some(current_height as usize))?;
-        progress_update.update(1.0, None)?;
+        // the first rescan starts from the birthday of the wallet, if it's after `skip_blocks`
+        let birthday_height = get_birthday_height(&self.client, database, current_height)?;
+        let first_rescan = self.skip_blocks.unwrap_or(0).max(birthday_height);
 
-        self.set_node_synced_height(current_height)?;
+        // min because block invalidate may cause height to go down
+        let node_synced = self
+            .get_node_synced_height(first_rescan)?
+            .min(current_height);
+
+        // rescan in chunks, updating the node synced height after each one so that in case of
+        // interruption the next setup can resume from there
+        let total_blocks = current_height - node_synced + 1;
+        let mut start = node_synced;
+        loop {
+            let end = current_height.min(start.saturating_add(self.rescan_chunk_size - 1));
+            debug!("rescan_blockchain from:{} to:{}", start, end);
+            self.client
+                .rescan_blockchain(Some(start as usize), Some(end as usize))?;
+            self.set_node_synced_height(end)?;
+
+            progress_update.update(
+                (end - node_synced + 1) as f32 / total_blocks as f32 * 100.0,
+                Some(format!("Rescanned blocks up to {}", end)),
+            )?;
+
+            if end >= current_height {
+                break;
+            }
+            start = end + 1;
+        }
 
         self.sync(stop_gap, database, progress_update)
     }
 
     fn sync<D: BatchDatabase, P: 'static + Progress>(
         &self,
-        _stop_gap: Option<usize>,
+        stop_gap: Option<usize>,
         db: &mut D,
-        _progress_update: P,
+        progress_update: P,
     ) -> Result<(), Error> {
+        if let (true, Some(descriptors)) = (self.scan_utxo_set, &self.descriptors) {
+            return self.sync_utxo_set(descriptors, stop_gap.unwrap_or(20), db, &progress_update);
+        }
+
         let mut indexes = HashMap::new();
         for keykind in &[KeychainKind::External, KeychainKind::Internal] {
             indexes.insert(*keykind, db.get_last_index(*keykind)?.unwrap_or(0));
@@ -183,6 +518,25 @@ impl Blockchain for RpcBlockchain {
             .list_transactions(None, Some(1_000), None, Some(true))?;
         let mut list_txs_ids = HashSet::new();
 
+        // a descriptor wallet may find script_pubkeys that haven't been cached yet
+        if let Some(descriptors) = &self.descriptors {
+            let scripts = current_utxo
+                .iter()
+                .map(|u| u.script_pub_key.clone())
+                .chain(
+                    list_txs
+                        .iter()
+                        .filter(|t| t.detail.category != GetTransactionResultDetailCategory::Send)
+                        .filter_map(|t| t.detail.address.as_ref().map(Address::script_pubkey)),
+                )
+                .collect();
+            let required =
+                self.missing_cached_scripts(descriptors, db, &scripts, stop_gap.unwrap_or(20))?;
+            if !required.is_empty() {
+                return Err(Error::MissingCachedScripts { required });
+            }
+        }
+
         for tx_result in list_txs.iter().filter(|t| {
             // list_txs returns all conflicting tx we want to
             // filter out replaced tx => unconfirmed and not in the mempool
@@ -236,6 +590,7 @@ impl Blockchain for RpcBlockchain {
                     sent,
                     fee: tx_result.fee.map(|f| f.as_sat().abs() as u64),
                     verified: true,
+                    label: None,
                 };
                 debug!(
                     "saving tx: {} tx_result.fee:{:?} td.fees:{:?}",
@@ -265,6 +620,13 @@ impl Blockchain for RpcBlockchain {
                         value: u.amount.as_sat(),
                         script_pubkey: u.script_pub_key,
                     },
+                    label: None,
+                    is_frozen: false,
+                    // all the wallet's transactions have been saved above, along with the raw tx
+                    is_coinbase: db
+                        .get_raw_tx(&u.txid)?
+                        .map(|tx| tx.is_coin_base())
+                        .unwrap_or(false),
                 })
             })
             .collect::<Result<_, Error>>()?;
@@ -301,17 +663,59 @@ impl Blockchain for RpcBlockchain {
     }
 
     fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
-        let sat_per_kb = self
+        let estimate = self
             .client
-            .estimate_smart_fee(target as u16, None)?
-            .fee_rate
-            .ok_or(Error::FeeRateUnavailable)?
-            .as_sat() as f64;
+            .estimate_smart_fee(target as u16, self.fee_estimate_mode)?;
+
+        fee_rate_from_estimate(estimate)
+    }
+
+    fn estimate_fees(&self, targets: &[usize]) -> Result<Vec<FeeRate>, Error> {
+        if targets.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Send all the `estimatesmartfee` calls in a single JSON-RPC batch
+        let mode = self
+            .fee_estimate_mode
+            .map(serde_json::to_value)
+            .transpose()?;
+        let params = targets
+            .iter()
+            .map(|target| {
+                let mut params = vec![Value::from(*target as u16)];
+                params.extend(mode.clone());
+                params
+            })
+            .collect::<Vec<_>>();
+        let jsonrpc = self.client.get_jsonrpc_client();
+        let requests = params
+            .iter()
+            .map(|params| jsonrpc.build_request("estimatesmartfee", params))
+            .collect::<Vec<_>>();
+
+        jsonrpc
+            .send_batch(&requests)
+            .map_err(bitcoincore_rpc::Error::from)?
+            .into_iter()
+            .map(|response| {
+                let estimate = response
+                    .ok_or_else(|| Error::Generic("Missing `estimatesmartfee` response".into()))?
+                    .result::<EstimateSmartFeeResult>()
+                    .map_err(bitcoincore_rpc::Error::from)?;
 
-        Ok(FeeRate::from_sat_per_vb((sat_per_kb / 1000f64) as f32))
+                fee_rate_from_estimate(estimate)
+            })
+            .collect()
     }
 }
 
+fn fee_rate_from_estimate(estimate: EstimateSmartFeeResult) -> Result<FeeRate, Error> {
+    let sat_per_kb = estimate.fee_rate.ok_or(Error::FeeRateUnavailable)?.as_sat() as f64;
+
+    Ok(FeeRate::from_sat_per_vb((sat_per_kb / 1000f64) as f32))
+}
+
 impl ConfigurableBlockchain for RpcBlockchain {
     type Config = RpcConfig;
 
@@ -319,32 +723,35 @@ impl ConfigurableBlockchain for RpcBlockchain {
     /// if it's the first time it creates the wallet in the node and upon return is granted the wallet is loaded
     fn from_config(config: &Self::Config) -> Result<Self, Error> {
         let wallet_name = config.wallet_name.clone();
-        let wallet_url = format!("{}/wallet/{}", config.url, &wallet_name);
-        debug!("connecting to {} auth:{:?}", wallet_url, config.auth);
+        // no wallet is used to scan the utxo set
+        let url = match config.scan_utxo_set {
+            true => config.url.clone(),
+            false => format!("{}/wallet/{}", config.url, &wallet_name),
+        };
+        debug!("connecting to {} auth:{:?}", url, config.auth);
 
-        let client = Client::new(wallet_url, config.auth.clone())?;
-        let loaded_wallets = client.list_wallets()?;
-        if loaded_wallets.contains(&wallet_name) {
-            debug!("wallet already loaded {:?}", wallet_name);
-        } else {
-            let existing_wallets = list_wallet_dir(&client)?;
-            if existing_wallets.contains(&wallet_name) {
-                client.load_wallet(&wallet_name)?;
-                debug!("wallet loaded {:?}", wallet_name);
-            } else {
-                client.create_wallet(&wallet_name, Some(true), None, None, None)?;
-                debug!("wallet created {:?}", wallet_name);
+        let client = Client::new(url, config.auth.clone())?;
+        let rpc_version = client.version()?;
+
+        let secp = Secp256k1::new();
+        let descriptors = match &config.descriptors {
+            Some(descriptors) if rpc_version >= 210_000 || config.scan_utxo_set => {
+                node_descriptors(&client, descriptors, config.network, &secp)?
             }
-        }
+            _ => None,
+        };
 
-        let blockchain_info = client.get_blockchain_info()?;
-        let network = match blockchain_info.chain.as_str() {
-            "main" => Network::Bitcoin,
-            "test" => Network::Testnet,
-            "regtest" => Network::Regtest,
-            "signet" => Network::Signet,
-            _ => return Err(Error::Generic("Invalid network".to_string())),
+        let descriptors = if config.scan_utxo_set {
+            Some(descriptors.ok_or_else(|| {
+                Error::Generic(
+                    "Scanning the UTXO set requires descriptors that the node can parse".into(),
+                )
+            })?)
+        } else {
+            load_or_create_wallet(&client, &wallet_name, descriptors)?
         };
+
+        let network = get_node_network(&client)?;
         if network != config.network {
             return Err(Error::InvalidNetwork {
                 requested: config.network,
@@ -352,8 +759,11 @@ impl ConfigurableBlockchain for RpcBlockchain {
             });
         }
 
-        let mut capabilities: HashSet<_> = vec![Capability::FullHistory].into_iter().collect();
-        let rpc_version = client.version()?;
+        // the history isn't recovered when scanning the utxo set
+        let mut capabilities = HashSet::new();
+        if !config.scan_utxo_set {
+            capabilities.insert(Capability::FullHistory);
+        }
         if rpc_version >= 210_000 {
             let info: HashMap<String, Value> = client.call("getindexinfo", &[]).unwrap();
             if info.contains_key("txindex") {
@@ -373,6 +783,11 @@ impl ConfigurableBlockchain for RpcBlockchain {
             capabilities,
             _storage_address: storage_address,
             skip_blocks: config.skip_blocks,
+            rescan_chunk_size: RESCAN_CHUNK_SIZE,
+            descriptors,
+            scan_utxo_set: config.scan_utxo_set,
+            fee_estimate_mode: config.fee_estimate_mode,
+            secp,
         })
     }
 }
@@ -406,6 +821,139 @@ where
     Ok(wallet_name)
 }
 
+/// Return the network of the node
+pub(crate) fn get_node_network(client: &Client) -> Result<Network, Error> {
+    let blockchain_info = client.get_blockchain_info()?;
+    match blockchain_info.chain.as_str() {
+        "main" => Ok(Network::Bitcoin),
+        "test" => Ok(Network::Testnet),
+        "regtest" => Ok(Network::Regtest),
+        "signet" => Ok(Network::Signet),
+        _ => Err(Error::Generic("Invalid network".to_string())),
+    }
+}
+
+/// Return the height of the wallet birthday stored in `database`, or `0` if it's not set
+///
+/// A [`Birthday::Timestamp`] is resolved with the headers of the node and stored back as a
+/// [`Birthday::Height`].
+pub(crate) fn get_birthday_height<D: BatchDatabase>(
+    client: &Client,
+    database: &mut D,
+    current_height: u32,
+) -> Result<u32, Error> {
+    match database.get_birthday()? {
+        None => Ok(0),
+        Some(Birthday::Height(height)) => Ok(height),
+        Some(birthday) => {
+            let height = birthday.find_height(current_height, |height| -> Result<_, Error> {
+                let hash = client.get_block_hash(height as u64)?;
+                Ok(client.get_block_header(&hash)?.time as u64)
+            })?;
+            debug!("wallet birthday resolved to height {}", height);
+            database.set_birthday(&Birthday::Height(height))?;
+
+            Ok(height)
+        }
+    }
+}
+
+/// Parse the descriptors of the wallet and get their canonical form from the node, or `None` if
+/// the node can't parse some of them
+fn node_descriptors(
+    client: &Client,
+    descriptors: &RpcDescriptors,
+    network: Network,
+    secp: &SecpCtx,
+) -> Result<Option<Vec<NodeDescriptor>>, Error> {
+    let keychains = std::iter::once((KeychainKind::External, &descriptors.descriptor)).chain(
+        descriptors
+            .change_descriptor
+            .as_ref()
+            .map(|d| (KeychainKind::Internal, d)),
+    );
+
+    let mut result = vec![];
+    for (keychain, descriptor) in keychains {
+        let descriptor = descriptor.as_str().into_wallet_descriptor(secp, network)?.0;
+        let public = descriptor.to_string();
+        match client.get_descriptor_info(&public[..public.find('#').unwrap()]) {
+            Ok(info) => result.push(NodeDescriptor {
+                keychain,
+                descriptor,
+                canonical: info.descriptor,
+            }),
+            Err(e) => {
+                debug!("the node can't parse {}, using importmulti: {}", public, e);
+                return Ok(None);
+            }
+        }
+    }
+
+    Ok(Some(result))
+}
+
+/// Load the wallet in the node, creating it if it doesn't exist, and return the descriptors
+/// imported in it or `None` if it's a legacy wallet
+fn load_or_create_wallet(
+    client: &Client,
+    wallet_name: &str,
+    descriptors: Option<Vec<NodeDescriptor>>,
+) -> Result<Option<Vec<NodeDescriptor>>, Error> {
+    let loaded_wallets = client.list_wallets()?;
+    if loaded_wallets.iter().any(|w| w == wallet_name) {
+        debug!("wallet already loaded {:?}", wallet_name);
+    } else {
+        let existing_wallets = list_wallet_dir(client)?;
+        if existing_wallets.iter().any(|w| w == wallet_name) {
+            client.load_wallet(wallet_name)?;
+            debug!("wallet loaded {:?}", wallet_name);
+        } else if descriptors.is_some() {
+            create_descriptor_wallet(client, wallet_name)?;
+            debug!("descriptor wallet created {:?}", wallet_name);
+        } else {
+            client.create_wallet(wallet_name, Some(true), None, None, None)?;
+            debug!("wallet created {:?}", wallet_name);
+        }
+    }
+
+    match (is_descriptor_wallet(client)?, descriptors) {
+        (true, Some(descriptors)) => Ok(Some(descriptors)),
+        (true, None) => Err(Error::Generic(format!(
+            "{} is a descriptor wallet, its descriptors must be set in the config",
+            wallet_name
+        ))),
+        (false, _) => Ok(None),
+    }
+}
+
+/// Create a watch-only descriptor wallet
+//TODO use bitcoincore_rpc method when it supports the `descriptors` argument
+fn create_descriptor_wallet(client: &Client, wallet_name: &str) -> Result<(), Error> {
+    let _: Value = client.call(
+        "createwallet",
+        &[
+            wallet_name.into(),
+            true.into(),  // disable_private_keys
+            true.into(),  // blank
+            "".into(),    // passphrase
+            false.into(), // avoid_reuse
+            true.into(),  // descriptors
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Return whether the wallet loaded by the client is a descriptor wallet
+fn is_descriptor_wallet(client: &Client) -> Result<bool, Error> {
+    let info: Value = client.call("getwalletinfo", &[])?;
+    Ok(info
+        .get("descriptors")
+        .and_then(Value::as_bool)
+        .unwrap_or(false))
+}
+
 /// return the wallets available in default wallet directory
 //TODO use bitcoincore_rpc method when PR #179 lands
 fn list_wallet_dir(client: &Client) -> Result<Vec<String>, Error> {
@@ -446,6 +994,9 @@ crate::bdk_blockchain_tests! {
             network: Network::Regtest,
             wallet_name: format!("client-wallet-test-{:?}", std::time::SystemTime::now() ),
             skip_blocks: None,
+            descriptors: None,
+            scan_utxo_set: false,
+            fee_estimate_mode: None,
         };
         RpcBlockchain::from_config(&config).unwrap()
     }
@@ -454,17 +1005,19 @@ crate::bdk_blockchain_tests! {
 #[cfg(feature = "test-rpc")]
 #[cfg(test)]
 mod test {
-    use super::{RpcBlockchain, RpcConfig};
+    use super::{fee_rate_from_estimate, RpcBlockchain, RpcConfig, RpcDescriptors};
     use crate::bitcoin::consensus::deserialize;
     use crate::bitcoin::{Address, Amount, Network, Transaction};
     use crate::blockchain::rpc::wallet_name_from_descriptor;
-    use crate::blockchain::{noop_progress, Blockchain, Capability, ConfigurableBlockchain};
-    use crate::database::MemoryDatabase;
+    use crate::blockchain::{
+        noop_progress, progress, Blockchain, Capability, ConfigurableBlockchain,
+    };
+    use crate::database::{BatchOperations, Database, MemoryDatabase};
     use crate::wallet::AddressIndex;
-    use crate::Wallet;
+    use crate::{KeychainKind, Wallet};
     use bitcoin::secp256k1::Secp256k1;
     use bitcoin::Txid;
-    use bitcoincore_rpc::json::CreateRawTransactionInput;
+    use bitcoincore_rpc::json::{CreateRawTransactionInput, EstimateMode};
     use bitcoincore_rpc::RawTx;
     use bitcoincore_rpc::{Auth, RpcApi};
     use bitcoind::BitcoinD;
@@ -484,6 +1037,9 @@ mod test {
             network,
             wallet_name,
             skip_blocks: None,
+            descriptors: None,
+            scan_utxo_set: false,
+            fee_estimate_mode: None,
         };
         RpcBlockchain::from_config(&config)
     }
@@ -512,7 +1068,7 @@ mod test {
         assert_eq!(expected_address, address.to_string());
         send_to_address(&bitcoind, &address, 100_000);
         wallet.sync(noop_progress(), None).unwrap();
-        assert_eq!(wallet.get_balance().unwrap(), 100_000);
+        assert_eq!(wallet.get_balance().unwrap().get_total(), 100_000);
 
         let mut builder = wallet.build_tx();
         builder.add_recipient(node_address.script_pubkey(), 50_000);
@@ -523,7 +1079,7 @@ mod test {
         wallet.broadcast(tx).unwrap();
         wallet.sync(noop_progress(), None).unwrap();
         assert_eq!(
-            wallet.get_balance().unwrap(),
+            wallet.get_balance().unwrap().get_total(),
             100_000 - 50_000 - details.fee.unwrap_or(0)
         );
         drop(wallet);
@@ -536,6 +1092,9 @@ mod test {
             network: Network::Regtest,
             wallet_name: "another-name".to_string(),
             skip_blocks: Some(103),
+            descriptors: None,
+            scan_utxo_set: false,
+            fee_estimate_mode: None,
         };
         let blockchain_skip = RpcBlockchain::from_config(&config).unwrap();
         let db = MemoryDatabase::new();
@@ -544,7 +1103,123 @@ mod test {
         wallet_skip.sync(noop_progress(), None).unwrap();
         send_to_address(&bitcoind, &address, 100_000);
         wallet_skip.sync(noop_progress(), None).unwrap();
-        assert_eq!(wallet_skip.get_balance().unwrap(), 100_000);
+        assert_eq!(wallet_skip.get_balance().unwrap().get_total(), 100_000);
+    }
+
+    #[test]
+    fn test_rpc_descriptor_wallet() {
+        let bitcoind = create_bitcoind(vec![]);
+        let config = RpcConfig {
+            url: bitcoind.rpc_url(),
+            auth: Auth::CookieFile(bitcoind.config.cookie_file.clone()),
+            network: Network::Regtest,
+            wallet_name: "descriptor-wallet".to_string(),
+            skip_blocks: None,
+            descriptors: Some(RpcDescriptors {
+                descriptor: DESCRIPTOR_PRIV.to_string(),
+                change_descriptor: None,
+            }),
+            scan_utxo_set: false,
+            fee_estimate_mode: None,
+        };
+        let blockchain = RpcBlockchain::from_config(&config).unwrap();
+        assert!(blockchain.descriptors.is_some());
+        let db = MemoryDatabase::new();
+        let wallet = Wallet::new(DESCRIPTOR_PRIV, None, Network::Regtest, db, blockchain).unwrap();
+
+        generate(&bitcoind, 101);
+        wallet.sync(noop_progress(), None).unwrap();
+
+        // beyond the addresses cached by the wallet, but tracked by the node
+        let address = wallet.get_address(AddressIndex::Peek(150)).unwrap();
+        assert!(!wallet.is_mine(&address.script_pubkey()).unwrap());
+        send_to_address(&bitcoind, &address, 100_000);
+        wallet.sync(noop_progress(), None).unwrap();
+        assert!(wallet.is_mine(&address.script_pubkey()).unwrap());
+        assert_eq!(wallet.get_balance().unwrap().get_total(), 100_000);
+    }
+
+    #[test]
+    fn test_rpc_scan_utxo_set() {
+        let bitcoind = create_bitcoind(vec![]);
+        let config = RpcConfig {
+            url: bitcoind.rpc_url(),
+            auth: Auth::CookieFile(bitcoind.config.cookie_file.clone()),
+            network: Network::Regtest,
+            wallet_name: "unused".to_string(),
+            skip_blocks: None,
+            descriptors: Some(RpcDescriptors {
+                descriptor: DESCRIPTOR_PUB.to_string(),
+                change_descriptor: None,
+            }),
+            scan_utxo_set: true,
+            fee_estimate_mode: None,
+        };
+        let blockchain = RpcBlockchain::from_config(&config).unwrap();
+        assert!(blockchain.get_capabilities().is_empty());
+        let db = MemoryDatabase::new();
+        let wallet = Wallet::new(DESCRIPTOR_PRIV, None, Network::Regtest, db, blockchain).unwrap();
+
+        let node_address = generate(&bitcoind, 101);
+        let address = wallet.get_address(AddressIndex::Peek(95)).unwrap();
+        send_to_address(&bitcoind, &address, 100_000);
+        bitcoind
+            .client
+            .generate_to_address(1, &node_address)
+            .unwrap();
+
+        wallet.sync(noop_progress(), None).unwrap();
+        let balance = wallet.get_balance().unwrap();
+        assert_eq!(balance.confirmed, 100_000);
+        assert_eq!(wallet.list_transactions(false).unwrap().len(), 1);
+        // more addresses have been cached to keep the stop gap after the one used
+        let last_gap_address = wallet.get_address(AddressIndex::Peek(95 + 20)).unwrap();
+        assert!(wallet.is_mine(&last_gap_address.script_pubkey()).unwrap());
+    }
+
+    #[test]
+    fn test_rpc_rescan_in_chunks() {
+        let bitcoind = create_bitcoind(vec![]);
+        let address = generate(&bitcoind, 101);
+        let txid = send_to_address(&bitcoind, &address, 100_000);
+        generate(&bitcoind, 149);
+
+        let mut blockchain = create_rpc(&bitcoind, DESCRIPTOR_PUB, Network::Regtest).unwrap();
+        blockchain.rescan_chunk_size = 100;
+        let mut db = MemoryDatabase::new();
+        db.set_script_pubkey(&address.script_pubkey(), KeychainKind::External, 0)
+            .unwrap();
+
+        // the progress update after the first chunk fails, interrupting the rescan
+        let (sender, receiver) = progress();
+        drop(receiver);
+        assert!(blockchain.setup(None, &mut db, sender).is_err());
+        assert_eq!(blockchain.get_node_synced_height(0).unwrap(), 99);
+        assert!(db.get_tx(&txid, false).unwrap().is_none());
+
+        // the next setup resumes from the height stored in the node
+        let (sender, receiver) = progress();
+        blockchain.setup(None, &mut db, sender).unwrap();
+        assert_eq!(blockchain.get_node_synced_height(0).unwrap(), 250);
+        let updates: Vec<_> = receiver
+            .try_iter()
+            .filter(|(_, message)| message.as_ref().is_some_and(|m| m.starts_with("Rescanned")))
+            .collect();
+        let messages: Vec<_> = updates
+            .iter()
+            .map(|(_, message)| message.clone().unwrap())
+            .collect();
+        assert_eq!(
+            messages,
+            vec!["Rescanned blocks up to 198", "Rescanned blocks up to 250"]
+        );
+        let values: Vec<_> = updates.iter().map(|(value, _)| *value).collect();
+        assert!(values.iter().all(|v| (0.0..=100.0).contains(v)));
+        assert!(values.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(values.last(), Some(&100.0));
+
+        // the transaction confirmed in a block of the second chunk has been found
+        assert!(db.get_tx(&txid, false).unwrap().is_some());
     }
 
     #[test]
@@ -590,16 +1265,57 @@ mod test {
         assert_eq!(rpc.get_height().unwrap(), 115);
     }
 
+    #[test]
+    fn test_rpc_estimate_fee_mode() {
+        let bitcoind = create_bitcoind(vec![]);
+        let address = generate(&bitcoind, 100);
+        // create enough tx so that core give some fee estimation
+        for _ in 0..15 {
+            let _ = bitcoind.client.generate_to_address(1, &address).unwrap();
+            for _ in 0..2 {
+                send_to_address(&bitcoind, &address, 100_000);
+            }
+        }
+
+        for &mode in &[EstimateMode::Economical, EstimateMode::Conservative] {
+            let config = RpcConfig {
+                url: bitcoind.rpc_url(),
+                auth: Auth::CookieFile(bitcoind.config.cookie_file.clone()),
+                network: Network::Regtest,
+                wallet_name: format!("fee-estimate-mode-{:?}", mode),
+                skip_blocks: None,
+                descriptors: None,
+                scan_utxo_set: false,
+                fee_estimate_mode: Some(mode),
+            };
+            let rpc = RpcBlockchain::from_config(&config).unwrap();
+
+            let expected = [2, 6]
+                .iter()
+                .map(|target| {
+                    let estimate = bitcoind
+                        .client
+                        .estimate_smart_fee(*target, Some(mode))
+                        .unwrap();
+                    fee_rate_from_estimate(estimate).unwrap()
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(rpc.estimate_fee(2).unwrap(), expected[0]);
+            // the mode is also sent with the batched requests
+            assert_eq!(rpc.estimate_fees(&[2, 6]).unwrap(), expected);
+        }
+    }
+
     #[test]
     fn test_rpc_node_synced_height() {
         let bitcoind = create_bitcoind(vec![]);
         let rpc = create_rpc(&bitcoind, DESCRIPTOR_PUB, Network::Regtest).unwrap();
-        let synced_height = rpc.get_node_synced_height().unwrap();
+        let synced_height = rpc.get_node_synced_height(0).unwrap();
 
         assert_eq!(synced_height, 0);
         rpc.set_node_synced_height(1).unwrap();
 
-        let synced_height = rpc.get_node_synced_height().unwrap();
+        let synced_height = rpc.get_node_synced_height(0).unwrap();
         assert_eq!(synced_height, 1);
     }
 
@@ -0,0 +1,643 @@
+// Bitcoin Dev Kit
+//
+// Copyright (c) 2020-2021 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Rpc Blocks Blockchain
+//!
+//! Backend that downloads the blocks from Bitcoin Core RPC and looks for the wallet's transactions
+//! in them, without using a wallet in the node. It can be used with nodes running with
+//! `disablewallet=1`, and doesn't need a `txindex`.
+//!
+//! The hashes of the last blocks synced are stored in the database: at every sync they are
+//! compared with the best chain of the node to detect reorgs, and the transactions confirmed in
+//! the blocks disconnected are removed from the database before scanning the new ones.
+//!
+//! This is an **EXPERIMENTAL** feature, API and other major changes are expected.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # use bdk::blockchain::{RpcBlocksConfig, RpcBlocksBlockchain, ConfigurableBlockchain};
+//! let config = RpcBlocksConfig {
+//!     url: "127.0.0.1:18332".to_string(),
+//!     auth: bitcoincore_rpc::Auth::CookieFile("/home/user/.bitcoin/.cookie".into()),
+//!     network: bdk::bitcoin::Network::Testnet,
+//!     start_height: Some(2_000_000),
+//!     scan_mempool: true,
+//! };
+//! let blockchain = RpcBlocksBlockchain::from_config(&config);
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use bitcoincore_rpc::jsonrpc::serde_json::Value;
+use bitcoincore_rpc::{Auth, Client, RpcApi};
+use log::{debug, info};
+
+use crate::bitcoin::{Amount, BlockHash, Network, OutPoint, Transaction, Txid};
+use crate::blockchain::rpc::{get_birthday_height, get_node_network};
+use crate::blockchain::{Blockchain, Capability, ConfigurableBlockchain, Progress};
+use crate::database::{BatchDatabase, DatabaseUtils};
+use crate::{ConfirmationTime, Error, FeeRate, KeychainKind, LocalUtxo, TransactionDetails};
+
+/// Number of block hashes kept in the database to find the fork point after a reorg
+const REORG_DEPTH: u32 = 100;
+/// Number of blocks processed between two progress updates
+const PROGRESS_INTERVAL: u32 = 100;
+
+/// Blockchain backend that scans the blocks downloaded from a Bitcoin Core node, see the
+/// [module-level documentation](self)
+#[derive(Debug)]
+pub struct RpcBlocksBlockchain {
+    /// Rpc client to the node
+    client: Client,
+    /// Blockchain capabilities, cached here at startup
+    capabilities: HashSet<Capability>,
+    /// Height of the first block scanned at the first sync
+    start_height: u32,
+    /// Look for unconfirmed transactions in the mempool of the node
+    scan_mempool: bool,
+    /// First block scanned at the last sync with fewer script_pubkeys cached than required, and
+    /// the number of script_pubkeys cached for each keychain at that time
+    ///
+    /// This is only kept in memory: if the blockchain is dropped before the next sync, the
+    /// transactions sent to the new script_pubkeys in the blocks already scanned won't be found.
+    pending_rescan: Mutex<Option<(u32, HashMap<KeychainKind, u32>)>>,
+    /// Transactions in the mempool of the node that have already been checked and aren't
+    /// relevant for the wallet, along with the number of script_pubkeys cached for each keychain
+    /// when they were checked
+    mempool_checked: Mutex<(HashMap<KeychainKind, u32>, HashSet<Txid>)>,
+}
+
+/// RpcBlocksBlockchain configuration options
+#[derive(Debug)]
+pub struct RpcBlocksConfig {
+    /// The bitcoin node url
+    pub url: String,
+    /// The bitcoin node authentication mechanism
+    pub auth: Auth,
+    /// The network we are using (it will be checked the bitcoin node network matches this)
+    pub network: Network,
+    /// Height of the first block scanned at the first sync, if None the scan starts from the
+    /// genesis block
+    ///
+    /// The wallet [`Birthday`](crate::Birthday) is used instead if it's later.
+    pub start_height: Option<u32>,
+    /// Download the transactions in the mempool of the node to find the unconfirmed ones of the
+    /// wallet
+    ///
+    /// This can be slow when the mempool is large. Without it the transactions of the wallet are
+    /// only found once they are confirmed.
+    pub scan_mempool: bool,
+}
+
+impl RpcBlocksBlockchain {
+    /// Find the first block that has to be scanned, rolling back the transactions confirmed in
+    /// the blocks that are no longer in the best chain
+    fn find_start_height<D: BatchDatabase>(
+        &self,
+        database: &mut D,
+        tip_height: u32,
+    ) -> Result<u32, Error> {
+        let synced = database.iter_block_hashes()?;
+        let (first_synced, last_synced) = match (synced.first(), synced.last()) {
+            (Some((first, _)), Some((last, _))) => (*first, *last),
+            _ => {
+                let birthday_height = get_birthday_height(&self.client, database, tip_height)?;
+                return Ok(self.start_height.max(birthday_height));
+            }
+        };
+
+        let mut start_height = first_synced;
+        for (height, hash) in synced.iter().rev() {
+            if *height <= tip_height && self.client.get_block_hash(*height as u64)? == *hash {
+                start_height = height + 1;
+                break;
+            }
+        }
+
+        if start_height <= last_synced {
+            info!(
+                "Rolling back the transactions confirmed after height {}",
+                start_height
+            );
+            for height in start_height..=last_synced {
+                database.del_block_hash(height)?;
+            }
+            let dropped_txs = database
+                .iter_txs(true)?
+                .into_iter()
+                .filter(|details| match &details.confirmation_time {
+                    Some(c) => c.height >= start_height,
+                    None => false,
+                })
+                .collect::<Vec<_>>();
+            let mut updates = database.begin_batch();
+            database.drop_txs(&dropped_txs, &mut updates)?;
+            database.commit_batch(updates)?;
+        }
+
+        Ok(start_height)
+    }
+
+    /// Return the fee of the transactions in a block, computed by the node from its undo data
+    fn get_block_fees(&self, block_hash: &BlockHash) -> Result<HashMap<Txid, u64>, Error> {
+        let block: Value = self.client.call(
+            "getblock",
+            &[Value::String(block_hash.to_string()), Value::from(2)],
+        )?;
+
+        let mut fees = HashMap::new();
+        for tx in block["tx"].as_array().into_iter().flatten() {
+            let txid = tx["txid"].as_str().and_then(|txid| txid.parse().ok());
+            let fee = tx["fee"]
+                .as_f64()
+                .and_then(|fee| Amount::from_btc(fee).ok());
+            if let (Some(txid), Some(fee)) = (txid, fee) {
+                fees.insert(txid, fee.as_sat());
+            }
+        }
+
+        Ok(fees)
+    }
+
+    /// Look for the unconfirmed transactions of the wallet in the mempool of the node, and drop
+    /// the ones that are no longer there
+    ///
+    /// `cached` is the number of script_pubkeys cached for each keychain.
+    fn sync_mempool<D: BatchDatabase>(
+        &self,
+        database: &mut D,
+        cached: &HashMap<KeychainKind, u32>,
+        max_indexes: &mut HashMap<KeychainKind, u32>,
+        spent: &mut HashSet<OutPoint>,
+    ) -> Result<(), Error> {
+        let mempool: HashSet<Txid> = self.client.get_raw_mempool()?.into_iter().collect();
+        debug!("{} transactions in the mempool", mempool.len());
+
+        let dropped_txs: Vec<_> = database
+            .iter_txs(true)?
+            .into_iter()
+            .filter(|details| {
+                details.confirmation_time.is_none() && !mempool.contains(&details.txid)
+            })
+            .collect();
+        for outpoint in spent_by(&dropped_txs) {
+            spent.remove(&outpoint);
+        }
+        let mut updates = database.begin_batch();
+        database.drop_txs(&dropped_txs, &mut updates)?;
+        database.commit_batch(updates)?;
+
+        let mut checked = self.mempool_checked.lock().unwrap();
+        // the transactions checked before may pay to the script_pubkeys cached since then
+        if checked.0 != *cached {
+            *checked = (cached.clone(), HashSet::new());
+        }
+        checked.1.retain(|txid| mempool.contains(txid));
+
+        let mut new_txs = HashMap::new();
+        for txid in mempool {
+            if checked.1.contains(&txid) || database.get_tx(&txid, false)?.is_some() {
+                continue;
+            }
+            // it may have been mined or evicted in the meantime
+            if let Ok(tx) = self.client.get_raw_transaction(&txid, None) {
+                new_txs.insert(txid, tx);
+            }
+        }
+        debug!("{} new transactions in the mempool", new_txs.len());
+
+        // process the parents before their children, so that the inputs spending the outputs
+        // of the wallet are found
+        while !new_txs.is_empty() {
+            let ready: Vec<Txid> = new_txs
+                .iter()
+                .filter(|(_, tx)| {
+                    tx.input
+                        .iter()
+                        .all(|input| !new_txs.contains_key(&input.previous_output.txid))
+                })
+                .map(|(txid, _)| *txid)
+                .collect();
+            if ready.is_empty() {
+                break;
+            }
+
+            for txid in ready {
+                let tx = new_txs.remove(&txid).expect("the tx was just found");
+                match process_tx(database, &tx, None, max_indexes, spent)? {
+                    Some(mut details) => {
+                        if details.fee.is_none() {
+                            details.fee = self
+                                .client
+                                .get_mempool_entry(&txid)
+                                .ok()
+                                .map(|entry| entry.fees.base.as_sat());
+                        }
+                        debug!("saving unconfirmed tx: {}", txid);
+                        database.set_tx(&details)?;
+                    }
+                    None => {
+                        checked.1.insert(txid);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Blockchain for RpcBlocksBlockchain {
+    fn get_capabilities(&self) -> HashSet<Capability> {
+        self.capabilities.clone()
+    }
+
+    fn setup<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        database: &mut D,
+        progress_update: P,
+    ) -> Result<(), Error> {
+        let stop_gap = stop_gap.unwrap_or(20) as u32;
+        let tip_height = self.get_height()?;
+        let mut start_height = self.find_start_height(database, tip_height)?;
+
+        let mut cached = HashMap::new();
+        for keychain in &[KeychainKind::External, KeychainKind::Internal] {
+            let count = database.iter_script_pubkeys(Some(*keychain))?.len() as u32;
+            cached.insert(*keychain, count);
+        }
+        // scan again the blocks that were scanned with too few script_pubkeys cached, if the
+        // wallet has cached more of them since then
+        if let Some((height, previous)) = self.pending_rescan.lock().unwrap().take() {
+            if previous
+                .iter()
+                .any(|(keychain, count)| cached[keychain] > *count)
+            {
+                start_height = start_height.min(height);
+            }
+        }
+        let mut max_indexes = HashMap::new();
+        // outpoints spent by the transactions of the wallet, to avoid adding them back as UTXOs
+        let mut spent = spent_by(&database.iter_txs(true)?);
+        let mut missing_from = None;
+
+        debug!("scanning blocks from:{} to:{}", start_height, tip_height);
+        for height in start_height..=tip_height {
+            let block_hash = self.client.get_block_hash(height as u64)?;
+            let block = self.client.get_block(&block_hash)?;
+            let timestamp = block.header.time as u64;
+
+            let mut block_fees = None;
+            for tx in &block.txdata {
+                let confirmation_time = ConfirmationTime::new(Some(height), Some(timestamp));
+                let mut details = match process_tx(
+                    database,
+                    tx,
+                    confirmation_time,
+                    &mut max_indexes,
+                    &mut spent,
+                )? {
+                    Some(details) => details,
+                    None => continue,
+                };
+                if details.fee.is_none() && !tx.is_coin_base() {
+                    if block_fees.is_none() {
+                        block_fees = Some(self.get_block_fees(&block_hash)?);
+                    }
+                    details.fee = block_fees.as_ref().and_then(|f| f.get(&tx.txid()).cloned());
+                }
+
+                debug!("saving tx: {}", details.txid);
+                database.set_tx(&details)?;
+            }
+
+            // the next blocks should be scanned with `stop_gap` script_pubkeys cached after the
+            // last one used, otherwise they are scanned again once the wallet has cached them
+            if missing_from.is_none()
+                && max_indexes
+                    .iter()
+                    .any(|(keychain, index)| cached[keychain] < index + 1 + stop_gap)
+            {
+                missing_from = Some(height + 1);
+            }
+
+            database.set_block_hash(height, &block_hash)?;
+            if height >= REORG_DEPTH {
+                database.del_block_hash(height - REORG_DEPTH)?;
+            }
+
+            if height % PROGRESS_INTERVAL == 0 || height == tip_height {
+                let done = height - start_height + 1;
+                let total = tip_height - start_height + 1;
+                progress_update.update(
+                    done as f32 / total as f32 * 100.0,
+                    Some(format!("Scanned block {}", height)),
+                )?;
+            }
+        }
+
+        if self.scan_mempool {
+            self.sync_mempool(database, &cached, &mut max_indexes, &mut spent)?;
+        }
+
+        let required: Vec<_> = max_indexes
+            .iter()
+            .map(|(keychain, index)| (*keychain, index + 1 + stop_gap))
+            .filter(|(keychain, required)| cached[keychain] < *required)
+            .collect();
+
+        for (keychain, index) in max_indexes {
+            if database
+                .get_last_index(keychain)?
+                .is_none_or(|last| last < index)
+            {
+                database.set_last_index(keychain, index)?;
+            }
+        }
+
+        if !required.is_empty() {
+            if let Some(height) = missing_from {
+                *self.pending_rescan.lock().unwrap() = Some((height, cached));
+            }
+            return Err(Error::MissingCachedScripts { required });
+        }
+
+        Ok(())
+    }
+
+    fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error> {
+        Ok(Some(self.client.get_raw_transaction(txid, None)?))
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<(), Error> {
+        Ok(self.client.send_raw_transaction(tx).map(|_| ())?)
+    }
+
+    fn get_height(&self) -> Result<u32, Error> {
+        Ok(self.client.get_block_count().map(|height| height as u32)?)
+    }
+
+    fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
+        let sat_per_kb = self
+            .client
+            .estimate_smart_fee(target as u16, None)?
+            .fee_rate
+            .ok_or(Error::FeeRateUnavailable)?
+            .as_sat() as f64;
+
+        Ok(FeeRate::from_sat_per_vb((sat_per_kb / 1000f64) as f32))
+    }
+}
+
+impl ConfigurableBlockchain for RpcBlocksBlockchain {
+    type Config = RpcBlocksConfig;
+
+    fn from_config(config: &Self::Config) -> Result<Self, Error> {
+        debug!("connecting to {} auth:{:?}", config.url, config.auth);
+        let client = Client::new(config.url.clone(), config.auth.clone())?;
+
+        let network = get_node_network(&client)?;
+        if network != config.network {
+            return Err(Error::InvalidNetwork {
+                requested: config.network,
+                found: network,
+            });
+        }
+
+        let mut capabilities: HashSet<_> = vec![Capability::FullHistory].into_iter().collect();
+        if client.version()? >= 210_000 {
+            // the fees are computed by the node when the previous outputs aren't in the database,
+            // older versions don't return them with the blocks
+            capabilities.insert(Capability::AccurateFees);
+
+            let info: HashMap<String, Value> = client.call("getindexinfo", &[])?;
+            if info.contains_key("txindex") {
+                capabilities.insert(Capability::GetAnyTx);
+            }
+        }
+
+        Ok(RpcBlocksBlockchain {
+            client,
+            capabilities,
+            start_height: config.start_height.unwrap_or(0),
+            scan_mempool: config.scan_mempool,
+            pending_rescan: Mutex::new(None),
+            mempool_checked: Mutex::new((HashMap::new(), HashSet::new())),
+        })
+    }
+}
+
+/// Look for the inputs and outputs of the wallet in `tx`, updating the UTXOs in the database,
+/// and return the details of the transaction if it's relevant
+///
+/// The fee is only computed when all the previous outputs are in the database. Processing the
+/// same transaction more than once gives the same result.
+///
+/// `spent` contains the outpoints spent by the transactions of the wallet, and is updated with the
+/// inputs of `tx` if it's relevant.
+fn process_tx<D: BatchDatabase>(
+    database: &mut D,
+    tx: &Transaction,
+    confirmation_time: Option<ConfirmationTime>,
+    max_indexes: &mut HashMap<KeychainKind, u32>,
+    spent: &mut HashSet<OutPoint>,
+) -> Result<Option<TransactionDetails>, Error> {
+    let txid = tx.txid();
+
+    let mut received = 0;
+    let mut sent = 0;
+    let mut inputs_sum = Some(0);
+
+    for input in &tx.input {
+        if tx.is_coin_base() {
+            break;
+        }
+
+        match database.get_previous_output(&input.previous_output)? {
+            Some(previous_output) => {
+                inputs_sum = inputs_sum.map(|sum| sum + previous_output.value);
+                if database.is_mine(&previous_output.script_pubkey)? {
+                    debug!("{} spends {}", txid, input.previous_output);
+                    sent += previous_output.value;
+                    database.del_utxo(&input.previous_output)?;
+                }
+            }
+            None => inputs_sum = None,
+        }
+    }
+
+    for (vout, output) in tx.output.iter().enumerate() {
+        if let Some((keychain, index)) =
+            database.get_path_from_script_pubkey(&output.script_pubkey)?
+        {
+            debug!("{} output #{} is mine", txid, vout);
+            let outpoint = OutPoint::new(txid, vout as u32);
+            // it may have been spent by a transaction processed before, after a reorg
+            if database.get_utxo(&outpoint)?.is_none() && !spent.contains(&outpoint) {
+                database.set_utxo(&LocalUtxo {
+                    outpoint,
+                    txout: output.clone(),
+                    keychain,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase: tx.is_coin_base(),
+                })?;
+            }
+            received += output.value;
+
+            let max_index = max_indexes.entry(keychain).or_insert(index);
+            *max_index = std::cmp::max(*max_index, index);
+        }
+    }
+
+    if received == 0 && sent == 0 {
+        return Ok(None);
+    }
+    if !tx.is_coin_base() {
+        spent.extend(tx.input.iter().map(|input| input.previous_output));
+    }
+
+    let outputs_sum: u64 = tx.output.iter().map(|output| output.value).sum();
+    Ok(Some(TransactionDetails {
+        txid,
+        transaction: Some(tx.clone()),
+        received,
+        sent,
+        fee: match tx.is_coin_base() {
+            true => Some(0),
+            false => inputs_sum.map(|sum| sum.saturating_sub(outputs_sum)),
+        },
+        confirmation_time,
+        verified: true,
+        label: None,
+    }))
+}
+
+/// Return the outpoints spent by some transactions of the wallet
+fn spent_by(txs: &[TransactionDetails]) -> HashSet<OutPoint> {
+    txs.iter()
+        .filter_map(|details| details.transaction.as_ref())
+        .filter(|tx| !tx.is_coin_base())
+        .flat_map(|tx| tx.input.iter().map(|input| input.previous_output))
+        .collect()
+}
+
+#[cfg(feature = "test-rpc")]
+#[cfg(test)]
+mod test {
+    use super::{RpcBlocksBlockchain, RpcBlocksConfig};
+    use crate::bitcoin::{Amount, Network};
+    use crate::blockchain::{noop_progress, Blockchain, Capability, ConfigurableBlockchain};
+    use crate::database::MemoryDatabase;
+    use crate::wallet::AddressIndex;
+    use crate::Wallet;
+    use bitcoincore_rpc::{Auth, RpcApi};
+    use bitcoind::BitcoinD;
+
+    const DESCRIPTOR_PRIV: &str = "wpkh(tprv8ZgxMBicQKsPdZxBDUcvTSMEaLwCTzTc6gmw8KBKwa3BJzWzec4g6VUbQBHJcutDH6mMEmBeVyN27H1NF3Nu8isZ1Sts4SufWyfLE6Mf1MB/*)";
+
+    fn create_bitcoind(args: Vec<String>) -> BitcoinD {
+        let exe = std::env::var("BITCOIND_EXE").unwrap();
+        bitcoind::BitcoinD::with_args(exe, args, false, bitcoind::P2P::No).unwrap()
+    }
+
+    #[test]
+    fn test_rpc_blocks_sync_and_reorg() {
+        let bitcoind = create_bitcoind(vec![]);
+        let node_address = bitcoind.client.get_new_address(None, None).unwrap();
+        bitcoind
+            .client
+            .generate_to_address(101, &node_address)
+            .unwrap();
+
+        let config = RpcBlocksConfig {
+            url: bitcoind.rpc_url(),
+            auth: Auth::CookieFile(bitcoind.config.cookie_file.clone()),
+            network: Network::Regtest,
+            start_height: None,
+            scan_mempool: true,
+        };
+        let blockchain = RpcBlocksBlockchain::from_config(&config).unwrap();
+        assert!(blockchain
+            .get_capabilities()
+            .contains(&Capability::AccurateFees));
+        let db = MemoryDatabase::new();
+        let wallet = Wallet::new(DESCRIPTOR_PRIV, None, Network::Regtest, db, blockchain).unwrap();
+
+        let address = wallet.get_address(AddressIndex::New).unwrap();
+        let send = |amount| {
+            bitcoind
+                .client
+                .send_to_address(
+                    &address,
+                    Amount::from_sat(amount),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap()
+        };
+
+        send(50_000);
+        wallet.sync(noop_progress(), None).unwrap();
+        assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000);
+
+        bitcoind
+            .client
+            .generate_to_address(1, &node_address)
+            .unwrap();
+        let mined_txid = send(100_000);
+        let block_hash = bitcoind
+            .client
+            .generate_to_address(1, &node_address)
+            .unwrap()[0];
+        wallet.sync(noop_progress(), None).unwrap();
+        assert_eq!(wallet.get_balance().unwrap().get_total(), 150_000);
+        let details = wallet.list_transactions(false).unwrap();
+        assert!(details.iter().all(|d| d.confirmation_time.is_some()));
+        assert!(details.iter().all(|d| d.fee.is_some()));
+
+        let mut builder = wallet.build_tx();
+        builder.add_recipient(node_address.script_pubkey(), 20_000);
+        let (mut psbt, details) = builder.finish().unwrap();
+        assert!(wallet.sign(&mut psbt, Default::default()).unwrap());
+        wallet.broadcast(psbt.extract_tx()).unwrap();
+        bitcoind
+            .client
+            .generate_to_address(1, &node_address)
+            .unwrap();
+        wallet.sync(noop_progress(), None).unwrap();
+        assert_eq!(
+            wallet.get_balance().unwrap().get_total(),
+            150_000 - 20_000 - details.fee.unwrap()
+        );
+
+        // after the reorg the transactions are back in the mempool of the node
+        bitcoind.client.invalidate_block(&block_hash).unwrap();
+        wallet.sync(noop_progress(), None).unwrap();
+        let mined = wallet
+            .list_transactions(false)
+            .unwrap()
+            .into_iter()
+            .find(|d| d.txid == mined_txid)
+            .unwrap();
+        assert!(mined.confirmation_time.is_none());
+        assert_eq!(
+            wallet.get_balance().unwrap().get_total(),
+            150_000 - 20_000 - details.fee.unwrap()
+        );
+    }
+}
@@ -16,15 +16,32 @@ use log::{debug, error, info, trace};
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 
-use bitcoin::{BlockHeader, OutPoint, Script, Transaction, Txid};
+use bitcoin::hashes::sha256;
+use bitcoin::{BlockHash, BlockHeader, OutPoint, Script, Transaction, Txid};
 
 use super::*;
 use crate::database::{BatchDatabase, BatchOperations, DatabaseUtils};
 use crate::error::Error;
-use crate::types::{ConfirmationTime, KeychainKind, LocalUtxo, TransactionDetails};
+use crate::types::{
+    Birthday, BirthdaySearch, ConfirmationTime, KeychainKind, LocalUtxo, ScriptStatus,
+    TransactionDetails,
+};
 use crate::wallet::time::Instant;
 use crate::wallet::utils::ChunksIterator;
 
+// Range of the progress values reported during each phase of the sync
+const HISTORY_PROGRESS: (f32, f32) = (0.0, 40.0);
+const TXS_PROGRESS: (f32, f32) = (40.0, 60.0);
+const PREV_TXS_PROGRESS: (f32, f32) = (60.0, 80.0);
+const HEADERS_PROGRESS: (f32, f32) = (80.0, 95.0);
+
+fn phase_progress((start, end): (f32, f32), done: usize, total: usize) -> f32 {
+    match total {
+        0 => end,
+        _ => start + (end - start) * (done.min(total) as f32 / total as f32),
+    }
+}
+
 #[derive(Debug)]
 pub struct ElsGetHistoryRes {
     pub height: i32,
@@ -49,20 +66,87 @@ pub trait ElectrumLikeSync {
         heights: I,
     ) -> Result<Vec<BlockHeader>, Error>;
 
+    /// Return the height of the current tip of the chain
+    fn els_get_height(&self) -> Result<u32, Error>;
+
+    /// Return the status of the history of each script, or `None` if the backend doesn't
+    /// support it
+    ///
+    /// When available the status is used to skip downloading the history of the scripts that
+    /// haven't changed since the last sync.
+    fn els_batch_script_status<'s, I: IntoIterator<Item = &'s Script> + Clone>(
+        &self,
+        _scripts: I,
+    ) -> Result<Option<Vec<Option<sha256::Hash>>>, Error> {
+        Ok(None)
+    }
+
+    /// Return the unconfirmed transactions of each script, or `None` if the backend doesn't
+    /// support it
+    ///
+    /// When available it's used by [`electrum_like_refresh`](Self::electrum_like_refresh) to
+    /// look for new transactions without downloading the whole history of the scripts.
+    fn els_batch_script_get_mempool<'s, I: IntoIterator<Item = &'s Script> + Clone>(
+        &self,
+        _scripts: I,
+    ) -> Result<Option<Vec<Vec<ElsGetHistoryRes>>>, Error> {
+        Ok(None)
+    }
+
     // Provided methods down here...
 
+    /// Return the height and the hash of the current tip of the chain
+    fn els_get_tip(&self) -> Result<(u32, BlockHash), Error> {
+        let height = maybe_await!(self.els_get_height())?;
+        let header = maybe_await!(self.els_batch_block_header(vec![height]))?
+            .pop()
+            .ok_or_else(|| Error::Generic(format!("Missing header at height {}", height)))?;
+
+        Ok((height, header.block_hash()))
+    }
+
+    /// Return the height of the wallet birthday stored in `db`, or `0` if it's not set
+    ///
+    /// A [`Birthday::Timestamp`] is resolved to the first block mined around that time, which is
+    /// then stored in place of the timestamp so that the lookup only happens once.
+    fn els_birthday_height<D: BatchDatabase>(&self, db: &mut D) -> Result<u32, Error> {
+        let birthday = match db.get_birthday()? {
+            None => return Ok(0),
+            Some(Birthday::Height(height)) => return Ok(height),
+            Some(birthday) => birthday,
+        };
+
+        let mut search = BirthdaySearch::new(&birthday, maybe_await!(self.els_get_height())?);
+        while let Some(height) = search.next_height() {
+            let time = maybe_await!(self.els_batch_block_header(vec![height]))?
+                .first()
+                .map(|header| header.time as u64)
+                .ok_or_else(|| Error::Generic(format!("Missing header at height {}", height)))?;
+            search.update(time);
+        }
+        let height = search.height();
+
+        info!("wallet birthday resolved to height {}", height);
+        db.set_birthday(&Birthday::Height(height))?;
+
+        Ok(height)
+    }
+
     fn electrum_like_setup<D: BatchDatabase, P: Progress>(
         &self,
         stop_gap: Option<usize>,
         db: &mut D,
-        _progress_update: P,
+        progress_update: P,
     ) -> Result<(), Error> {
-        // TODO: progress
         let start = Instant::new();
         debug!("start setup");
 
         let stop_gap = stop_gap.unwrap_or(20);
         let chunk_size = stop_gap;
+        let birthday_height = maybe_await!(self.els_birthday_height(db))?;
+        // taken before downloading the history, so that the transactions confirmed in the
+        // meantime are picked up by the next sync
+        let tip = maybe_await!(self.els_get_tip())?;
 
         let mut history_txs_id = HashSet::new();
         let mut txid_height = HashMap::new();
@@ -71,14 +155,25 @@ pub trait ElectrumLikeSync {
         let mut wallet_chains = vec![KeychainKind::Internal, KeychainKind::External];
         // shuffling improve privacy, the server doesn't know my first request is from my internal or external addresses
         wallet_chains.shuffle(&mut thread_rng());
+        let wallet_scripts = wallet_chains
+            .iter()
+            .map(|keychain| db.iter_script_pubkeys(Some(*keychain)))
+            .collect::<Result<Vec<_>, _>>()?;
+        // we usually stop earlier than this, but it's the best upper bound we have
+        let total_scripts = wallet_scripts.iter().map(Vec::len).sum();
+        let mut scanned_scripts = 0;
+        let mut missing_scripts = vec![];
+        let mut statuses = vec![];
+
+        progress_update.update(0.0, Some("Downloading scripts history".into()))?;
+
         // download history of our internal and external script_pubkeys
-        for keychain in wallet_chains.iter() {
-            let script_iter = db.iter_script_pubkeys(Some(*keychain))?.into_iter();
+        for (keychain, scripts) in wallet_chains.iter().zip(wallet_scripts) {
+            let keychain_scripts = scripts.len();
 
-            for (i, chunk) in ChunksIterator::new(script_iter, stop_gap).enumerate() {
-                // TODO if i == last, should create another chunk of addresses in db
-                let call_result: Vec<Vec<ElsGetHistoryRes>> =
-                    maybe_await!(self.els_batch_script_get_history(chunk.iter()))?;
+            for (i, chunk) in ChunksIterator::new(scripts.into_iter(), stop_gap).enumerate() {
+                let call_result =
+                    maybe_await!(self.download_changed_history(&chunk, db, &mut statuses))?;
                 let max_index = call_result
                     .iter()
                     .enumerate()
@@ -89,6 +184,16 @@ pub trait ElectrumLikeSync {
                 }
                 let flattened: Vec<ElsGetHistoryRes> = call_result.into_iter().flatten().collect();
                 debug!("#{} of {:?} results:{}", i, keychain, flattened.len());
+
+                let scanned = scanned_scripts + (i + 1) * chunk_size;
+                progress_update.update(
+                    phase_progress(HISTORY_PROGRESS, scanned, total_scripts),
+                    Some(format!(
+                        "Downloaded history of {} scripts",
+                        scanned.min(total_scripts)
+                    )),
+                )?;
+
                 if flattened.is_empty() {
                     // Didn't find anything in the last `stop_gap` script_pubkeys, breaking
                     break;
@@ -98,6 +203,10 @@ pub trait ElectrumLikeSync {
                     // el.height = -1 means unconfirmed with unconfirmed parents
                     // el.height =  0 means unconfirmed with confirmed parents
                     // but we treat those tx the same
+                    if el.height > 0 && (el.height as u32) < birthday_height {
+                        // mined before the wallet was created, can't be ours
+                        continue;
+                    }
                     if el.height <= 0 {
                         txid_height.insert(el.tx_hash, None);
                     } else {
@@ -106,6 +215,19 @@ pub trait ElectrumLikeSync {
                     history_txs_id.insert(el.tx_hash);
                 }
             }
+
+            scanned_scripts += keychain_scripts;
+
+            // make sure that we have looked at `stop_gap` script_pubkeys after the last one used,
+            // unless no more of them can be derived
+            let required = max_indexes.get(keychain).map_or(0, |max| *max as usize + 1) + stop_gap;
+            if keychain_scripts > 0 && keychain_scripts < required {
+                debug!(
+                    "{:?} needs {} script_pubkeys, only {} cached",
+                    keychain, required, keychain_scripts
+                );
+                missing_scripts.push((*keychain, required as u32));
+            }
         }
 
         // saving max indexes
@@ -134,14 +256,21 @@ pub trait ElectrumLikeSync {
             &history_txs_id,
             &txs_raw_in_db,
             chunk_size,
-            db
+            db,
+            &progress_update,
         ))?;
         let new_timestamps = maybe_await!(self.download_needed_headers(
             &txid_height,
             &txs_details_in_db,
-            chunk_size
+            chunk_size,
+            &progress_update,
         ))?;
 
+        progress_update.update(
+            HEADERS_PROGRESS.1,
+            Some("Updating transactions and utxos".into()),
+        )?;
+
         let mut batch = db.begin_batch();
 
         // save any tx details not in db but in history_txs_id or with different height/timestamp
@@ -183,19 +312,282 @@ pub trait ElectrumLikeSync {
             }
         }
 
+        // only store the new statuses now that the history they refer to has been processed
+        for (script, status) in statuses {
+            match status {
+                Some(status) => batch.set_script_status(&script, &status)?,
+                None => {
+                    batch.del_script_status(&script)?;
+                }
+            }
+        }
+
         db.commit_batch(batch)?;
         info!("finish setup, elapsed {:?}ms", start.elapsed().as_millis());
 
+        db.set_synced_tip(tip.0, &tip.1)?;
+        progress_update.update(100.0, Some("Done".into()))?;
+
+        if !missing_scripts.is_empty() {
+            return Err(Error::MissingCachedScripts {
+                required: missing_scripts,
+            });
+        }
+
         Ok(())
     }
 
+    /// Only look for the new unconfirmed transactions of the wallet since the last sync
+    ///
+    /// When the tip of the chain hasn't changed since the last
+    /// [`electrum_like_setup`](Self::electrum_like_setup), only the unconfirmed transactions of
+    /// the recently used scripts are queried: the last `stop_gap` used and the next `stop_gap`
+    /// unused ones of each keychain, and the ones involved in the unconfirmed transactions stored
+    /// in the database. The new transactions are added to the database, without touching the
+    /// rest.
+    ///
+    /// A full sync runs instead when a new block has been found, when an unconfirmed transaction
+    /// of the database has left the mempool, or when the backend can't query the mempool.
+    fn electrum_like_refresh<D: BatchDatabase, P: Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        db: &mut D,
+        progress_update: P,
+    ) -> Result<(), Error> {
+        let tip = maybe_await!(self.els_get_tip())?;
+        if db.get_synced_tip()? != Some(tip) {
+            debug!("new block found, running a full sync");
+            return maybe_await!(self.electrum_like_setup(stop_gap, db, progress_update));
+        }
+
+        let stop_gap = stop_gap.unwrap_or(20);
+        progress_update.update(0.0, Some("Downloading unconfirmed transactions".into()))?;
+
+        let unconfirmed_in_db: Vec<TransactionDetails> = db
+            .iter_txs(true)?
+            .into_iter()
+            .filter(|details| details.confirmation_time.is_none())
+            .collect();
+        let scripts = refresh_scripts(db, stop_gap, &unconfirmed_in_db)?;
+        let mempool_txids = match maybe_await!(self.download_changed_mempool(&scripts, db))? {
+            Some(mempool_txids) => mempool_txids,
+            None => {
+                debug!("the backend can't query the mempool, running a full sync");
+                return maybe_await!(self.electrum_like_setup(Some(stop_gap), db, progress_update));
+            }
+        };
+
+        // the tip hasn't changed, so they have been replaced or evicted. Or a block has just been
+        // found, either way the full sync takes care of them
+        if unconfirmed_in_db
+            .iter()
+            .any(|details| !mempool_txids.contains(&details.txid))
+        {
+            debug!("unconfirmed transactions have left the mempool, running a full sync");
+            return maybe_await!(self.electrum_like_setup(Some(stop_gap), db, progress_update));
+        }
+
+        let known_txids: HashSet<Txid> =
+            db.iter_txs(false)?.into_iter().map(|tx| tx.txid).collect();
+        let new_txids: HashSet<Txid> = mempool_txids.difference(&known_txids).cloned().collect();
+        debug!("{} new unconfirmed transactions", new_txids.len());
+
+        if !new_txids.is_empty() {
+            let txs_raw_in_db: HashMap<Txid, Transaction> = db
+                .iter_raw_txs()?
+                .into_iter()
+                .map(|tx| (tx.txid(), tx))
+                .collect();
+            let utxos_deps = utxos_deps(db, &txs_raw_in_db)?;
+            maybe_await!(self.download_and_save_needed_raw_txs(
+                &new_txids,
+                &txs_raw_in_db,
+                stop_gap,
+                db,
+                &progress_update,
+            ))?;
+
+            let mut max_indexes = HashMap::new();
+            let mut batch = db.begin_batch();
+            for txid in new_txids.iter() {
+                save_transaction_details_and_utxos(txid, db, None, None, &mut batch, &utxos_deps)?;
+
+                let tx = db.get_raw_tx(txid)?.ok_or(Error::TransactionNotFound)?;
+                for input in tx.input.iter() {
+                    batch.del_utxo(&input.previous_output)?;
+                }
+                for output in tx.output.iter() {
+                    if let Some((keychain, index)) =
+                        db.get_path_from_script_pubkey(&output.script_pubkey)?
+                    {
+                        let max_index = max_indexes.entry(keychain).or_insert(index);
+                        *max_index = std::cmp::max(*max_index, index);
+                    }
+                }
+            }
+            db.commit_batch(batch)?;
+
+            let mut missing_scripts = vec![];
+            for (keychain, index) in max_indexes {
+                if db
+                    .get_last_index(keychain)?
+                    .is_some_and(|last| last >= index)
+                {
+                    continue;
+                }
+                db.set_last_index(keychain, index)?;
+
+                let cached = db.iter_script_pubkeys(Some(keychain))?.len();
+                let required = index as usize + 1 + stop_gap;
+                if cached < required {
+                    missing_scripts.push((keychain, required as u32));
+                }
+            }
+            if !missing_scripts.is_empty() {
+                return Err(Error::MissingCachedScripts {
+                    required: missing_scripts,
+                });
+            }
+        }
+
+        progress_update.update(100.0, Some("Done".into()))?;
+
+        Ok(())
+    }
+
+    /// download the unconfirmed transactions of `scripts`, or `None` if the backend can't query
+    /// the mempool
+    ///
+    /// When the backend reports the status of the scripts only the ones whose status doesn't match
+    /// the one stored in the database are queried, the unconfirmed transactions of the others are
+    /// taken from the history stored along with their status. The statuses aren't updated here,
+    /// since the rest of the history isn't processed: the next full sync will take care of them
+    fn download_changed_mempool<D: BatchDatabase>(
+        &self,
+        scripts: &[Script],
+        db: &D,
+    ) -> Result<Option<HashSet<Txid>>, Error> {
+        let statuses = match maybe_await!(self.els_batch_script_status(scripts.iter()))? {
+            Some(statuses) => statuses,
+            None => {
+                return Ok(
+                    maybe_await!(self.els_batch_script_get_mempool(scripts.iter()))?.map(
+                        |mempool| mempool.into_iter().flatten().map(|el| el.tx_hash).collect(),
+                    ),
+                )
+            }
+        };
+
+        let mut mempool_txids = HashSet::new();
+        let mut changed_scripts = vec![];
+        for (script, status) in scripts.iter().zip(statuses) {
+            match (status, db.get_script_status(script)?) {
+                (Some(status), Some(stored)) if stored.status == status => {
+                    mempool_txids.extend(
+                        stored
+                            .history
+                            .into_iter()
+                            .filter(|(_, height)| height.is_none())
+                            .map(|(txid, _)| txid),
+                    );
+                }
+                (None, None) => {}
+                _ => changed_scripts.push(script),
+            }
+        }
+        debug!(
+            "{} of {} scripts have changed",
+            changed_scripts.len(),
+            scripts.len()
+        );
+
+        if !changed_scripts.is_empty() {
+            match maybe_await!(self.els_batch_script_get_mempool(changed_scripts.into_iter()))? {
+                Some(mempool) => {
+                    mempool_txids.extend(mempool.into_iter().flatten().map(|el| el.tx_hash))
+                }
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some(mempool_txids))
+    }
+
+    /// download the history of `scripts`, skipping the ones whose status matches the one stored in
+    /// the database. The new statuses that should be stored are appended to `updated_statuses`
+    fn download_changed_history<D: BatchDatabase>(
+        &self,
+        scripts: &[Script],
+        db: &D,
+        updated_statuses: &mut Vec<(Script, Option<ScriptStatus>)>,
+    ) -> Result<Vec<Vec<ElsGetHistoryRes>>, Error> {
+        let statuses = match maybe_await!(self.els_batch_script_status(scripts.iter()))? {
+            Some(statuses) => statuses,
+            None => return maybe_await!(self.els_batch_script_get_history(scripts.iter())),
+        };
+
+        let mut history = Vec::with_capacity(scripts.len());
+        let mut to_download = vec![];
+        for (i, (script, status)) in scripts.iter().zip(statuses).enumerate() {
+            match (status, db.get_script_status(script)?) {
+                (Some(status), Some(stored)) if stored.status == status => {
+                    history.push(
+                        stored
+                            .history
+                            .into_iter()
+                            .map(|(tx_hash, height)| ElsGetHistoryRes {
+                                height: height.map(|h| h as i32).unwrap_or(0),
+                                tx_hash,
+                            })
+                            .collect(),
+                    );
+                }
+                (Some(status), _) => {
+                    history.push(vec![]);
+                    to_download.push((i, status));
+                }
+                // no history on the server
+                (None, stored) => {
+                    history.push(vec![]);
+                    if stored.is_some() {
+                        updated_statuses.push((script.clone(), None));
+                    }
+                }
+            }
+        }
+        debug!(
+            "{} of {} scripts have changed",
+            to_download.len(),
+            scripts.len()
+        );
+
+        if !to_download.is_empty() {
+            let changed_scripts = to_download.iter().map(|(i, _)| &scripts[*i]);
+            let downloaded = maybe_await!(self.els_batch_script_get_history(changed_scripts))?;
+            for ((i, status), script_history) in to_download.into_iter().zip(downloaded) {
+                let status = ScriptStatus {
+                    status,
+                    history: script_history
+                        .iter()
+                        .map(|el| (el.tx_hash, Some(el.height as u32).filter(|_| el.height > 0)))
+                        .collect(),
+                };
+                updated_statuses.push((scripts[i].clone(), Some(status)));
+                history[i] = script_history;
+            }
+        }
+
+        Ok(history)
+    }
+
     /// download txs identified by `history_txs_id` and theirs previous outputs if not already present in db
-    fn download_and_save_needed_raw_txs<D: BatchDatabase>(
+    fn download_and_save_needed_raw_txs<D: BatchDatabase, P: Progress>(
         &self,
         history_txs_id: &HashSet<Txid>,
         txs_raw_in_db: &HashMap<Txid, Transaction>,
         chunk_size: usize,
         db: &mut D,
+        progress_update: &P,
     ) -> Result<Vec<Transaction>, Error> {
         let mut txs_downloaded = vec![];
         let txids_raw_in_db: HashSet<Txid> = txs_raw_in_db.keys().cloned().collect();
@@ -206,6 +598,8 @@ pub trait ElectrumLikeSync {
                 txids_to_download,
                 chunk_size,
                 db,
+                progress_update,
+                TXS_PROGRESS,
             ))?);
             let mut prev_txids = HashSet::new();
             let mut txids_downloaded = HashSet::new();
@@ -225,6 +619,8 @@ pub trait ElectrumLikeSync {
                 prev_txs_to_download,
                 chunk_size,
                 db,
+                progress_update,
+                PREV_TXS_PROGRESS,
             ))?);
         }
 
@@ -232,11 +628,12 @@ pub trait ElectrumLikeSync {
     }
 
     /// download headers at heights in `txid_height` if tx details not already present, returns a map Txid -> timestamp
-    fn download_needed_headers(
+    fn download_needed_headers<P: Progress>(
         &self,
         txid_height: &HashMap<Txid, Option<u32>>,
         txs_details_in_db: &HashMap<Txid, TransactionDetails>,
         chunk_size: usize,
+        progress_update: &P,
     ) -> Result<HashMap<Txid, u64>, Error> {
         let mut txid_timestamp = HashMap::new();
         let txid_in_db_with_conf: HashSet<_> = txs_details_in_db
@@ -251,6 +648,7 @@ pub trait ElectrumLikeSync {
         let needed_heights: HashSet<u32> = needed_txid_height.values().cloned().collect();
         if !needed_heights.is_empty() {
             info!("{} headers to download for timestamp", needed_heights.len());
+            let total_headers = needed_heights.len();
             let mut height_timestamp: HashMap<u32, u64> = HashMap::new();
             for chunk in ChunksIterator::new(needed_heights.into_iter(), chunk_size) {
                 let call_result: Vec<BlockHeader> =
@@ -260,6 +658,14 @@ pub trait ElectrumLikeSync {
                         .into_iter()
                         .zip(call_result.iter().map(|h| h.time as u64)),
                 );
+                progress_update.update(
+                    phase_progress(HEADERS_PROGRESS, height_timestamp.len(), total_headers),
+                    Some(format!(
+                        "Downloaded {} of {} block headers",
+                        height_timestamp.len(),
+                        total_headers
+                    )),
+                )?;
             }
             for (txid, height) in needed_txid_height {
                 let timestamp = height_timestamp
@@ -272,12 +678,15 @@ pub trait ElectrumLikeSync {
         Ok(txid_timestamp)
     }
 
-    fn download_and_save_in_chunks<D: BatchDatabase>(
+    fn download_and_save_in_chunks<D: BatchDatabase, P: Progress>(
         &self,
         to_download: Vec<&Txid>,
         chunk_size: usize,
         db: &mut D,
+        progress_update: &P,
+        progress_range: (f32, f32),
     ) -> Result<Vec<Transaction>, Error> {
+        let total_txs = to_download.len();
         let mut txs_downloaded = vec![];
         for chunk in ChunksIterator::new(to_download.into_iter(), chunk_size) {
             let call_result: Vec<Transaction> =
@@ -288,12 +697,57 @@ pub trait ElectrumLikeSync {
             }
             db.commit_batch(batch)?;
             txs_downloaded.extend(call_result);
+
+            progress_update.update(
+                phase_progress(progress_range, txs_downloaded.len(), total_txs),
+                Some(format!(
+                    "Downloaded {} of {} transactions",
+                    txs_downloaded.len(),
+                    total_txs
+                )),
+            )?;
         }
 
         Ok(txs_downloaded)
     }
 }
 
+/// Return the scripts queried by [`ElectrumLikeSync::electrum_like_refresh`]
+fn refresh_scripts<D: BatchDatabase>(
+    db: &D,
+    stop_gap: usize,
+    unconfirmed_in_db: &[TransactionDetails],
+) -> Result<Vec<Script>, Error> {
+    let mut scripts = HashSet::new();
+    for keychain in &[KeychainKind::External, KeychainKind::Internal] {
+        let next = db.get_last_index(*keychain)?.map_or(0, |last| last + 1);
+        for index in next.saturating_sub(stop_gap as u32)..next + stop_gap as u32 {
+            match db.get_script_pubkey_from_path(*keychain, index)? {
+                Some(script) => scripts.insert(script),
+                None => break,
+            };
+        }
+    }
+
+    for tx in unconfirmed_in_db
+        .iter()
+        .filter_map(|details| details.transaction.as_ref())
+    {
+        let previous_outputs = tx
+            .input
+            .iter()
+            .map(|input| db.get_previous_output(&input.previous_output))
+            .collect::<Result<Vec<_>, _>>()?;
+        for txout in previous_outputs.iter().flatten().chain(tx.output.iter()) {
+            if db.is_mine(&txout.script_pubkey)? {
+                scripts.insert(txout.script_pubkey.clone());
+            }
+        }
+    }
+
+    Ok(scripts.into_iter().collect())
+}
+
 fn save_transaction_details_and_utxos<D: BatchDatabase>(
     txid: &Txid,
     db: &mut D,
@@ -349,6 +803,9 @@ fn save_transaction_details_and_utxos<D: BatchDatabase>(
                 outpoint: OutPoint::new(tx.txid(), i as u32),
                 txout: output.clone(),
                 keychain,
+                label: None,
+                is_frozen: false,
+                is_coinbase: tx.is_coin_base(),
             })?;
 
             incoming += output.value;
@@ -363,6 +820,7 @@ fn save_transaction_details_and_utxos<D: BatchDatabase>(
         confirmation_time: ConfirmationTime::new(height, timestamp),
         fee: Some(inputs_sum.saturating_sub(outputs_sum)), /* if the tx is a coinbase, fees would be negative */
         verified: height.is_some(),
+        label: None,
     };
     updates.set_tx(&tx_details)?;
 
@@ -387,3 +845,165 @@ fn utxos_deps<D: BatchDatabase>(
     }
     Ok(utxos_deps)
 }
+
+#[cfg(all(test, not(feature = "async-interface")))]
+mod test {
+    use std::cell::Cell;
+
+    use bitcoin::blockdata::script::Builder;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{TxIn, TxOut};
+
+    use super::*;
+    use crate::database::{Database, MemoryDatabase};
+
+    // Serves the history of a fixed set of scripts, counting the history requests
+    #[derive(Default)]
+    struct MockElectrum {
+        history: HashMap<Script, Vec<(Txid, i32)>>,
+        txs: HashMap<Txid, Transaction>,
+        history_requests: Cell<usize>,
+    }
+
+    impl MockElectrum {
+        fn add_tx(&mut self, tx: Transaction, height: i32) {
+            for output in &tx.output {
+                self.history
+                    .entry(output.script_pubkey.clone())
+                    .or_default()
+                    .push((tx.txid(), height));
+            }
+            self.txs.insert(tx.txid(), tx);
+        }
+    }
+
+    impl ElectrumLikeSync for MockElectrum {
+        fn els_batch_script_get_history<'s, I: IntoIterator<Item = &'s Script> + Clone>(
+            &self,
+            scripts: I,
+        ) -> Result<Vec<Vec<ElsGetHistoryRes>>, Error> {
+            self.history_requests.set(self.history_requests.get() + 1);
+            Ok(scripts
+                .into_iter()
+                .map(|script| {
+                    self.history
+                        .get(script)
+                        .into_iter()
+                        .flatten()
+                        .map(|(tx_hash, height)| ElsGetHistoryRes {
+                            height: *height,
+                            tx_hash: *tx_hash,
+                        })
+                        .collect()
+                })
+                .collect())
+        }
+
+        fn els_batch_transaction_get<'s, I: IntoIterator<Item = &'s Txid> + Clone>(
+            &self,
+            txids: I,
+        ) -> Result<Vec<Transaction>, Error> {
+            txids
+                .into_iter()
+                .map(|txid| {
+                    self.txs
+                        .get(txid)
+                        .cloned()
+                        .ok_or(Error::TransactionNotFound)
+                })
+                .collect()
+        }
+
+        fn els_batch_block_header<I: IntoIterator<Item = u32> + Clone>(
+            &self,
+            heights: I,
+        ) -> Result<Vec<BlockHeader>, Error> {
+            Ok(heights
+                .into_iter()
+                .map(|height| BlockHeader {
+                    version: 1,
+                    prev_blockhash: Default::default(),
+                    merkle_root: Default::default(),
+                    time: height,
+                    bits: 0,
+                    nonce: 0,
+                })
+                .collect())
+        }
+
+        fn els_get_height(&self) -> Result<u32, Error> {
+            Ok(10)
+        }
+
+        fn els_batch_script_status<'s, I: IntoIterator<Item = &'s Script> + Clone>(
+            &self,
+            scripts: I,
+        ) -> Result<Option<Vec<Option<sha256::Hash>>>, Error> {
+            Ok(Some(
+                scripts
+                    .into_iter()
+                    .map(|script| {
+                        self.history
+                            .get(script)
+                            .map(|history| sha256::Hash::hash(format!("{:?}", history).as_bytes()))
+                    })
+                    .collect(),
+            ))
+        }
+    }
+
+    fn receive(script: &Script, value: u64) -> Transaction {
+        Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Builder::new().push_int(value as i64).into_script(),
+                sequence: 0xFFFFFFFF,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: script.clone(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_sync_skips_unchanged_scripts() {
+        let mut db = MemoryDatabase::new();
+        let scripts: Vec<Script> = (0..25)
+            .map(|i| Builder::new().push_int(i).into_script())
+            .collect();
+        for (i, script) in scripts.iter().enumerate() {
+            db.set_script_pubkey(script, KeychainKind::External, i as u32)
+                .unwrap();
+        }
+
+        let mut electrum = MockElectrum::default();
+        electrum.add_tx(receive(&scripts[0], 50_000), 5);
+        electrum
+            .electrum_like_setup(None, &mut db, noop_progress())
+            .unwrap();
+        assert!(electrum.history_requests.get() > 0);
+        assert_eq!(db.iter_txs(false).unwrap().len(), 1);
+
+        // nothing has changed, the statuses match the ones stored
+        electrum.history_requests.set(0);
+        electrum
+            .electrum_like_setup(None, &mut db, noop_progress())
+            .unwrap();
+        assert_eq!(electrum.history_requests.get(), 0);
+        assert_eq!(db.iter_txs(false).unwrap().len(), 1);
+        assert_eq!(db.iter_utxos().unwrap().len(), 1);
+
+        // only the history of the script that received a new transaction is downloaded
+        electrum.add_tx(receive(&scripts[1], 30_000), 0);
+        electrum
+            .electrum_like_setup(None, &mut db, noop_progress())
+            .unwrap();
+        assert_eq!(electrum.history_requests.get(), 1);
+        assert_eq!(db.iter_txs(false).unwrap().len(), 2);
+        assert_eq!(db.iter_utxos().unwrap().len(), 2);
+    }
+}
@@ -132,6 +132,24 @@ impl BatchOperations for AnyDatabase {
     fn set_last_index(&mut self, keychain: KeychainKind, value: u32) -> Result<(), Error> {
         impl_inner_method!(AnyDatabase, self, set_last_index, keychain, value)
     }
+    fn set_label(&mut self, target: &LabelRef, label: &str) -> Result<(), Error> {
+        impl_inner_method!(AnyDatabase, self, set_label, target, label)
+    }
+    fn set_frozen_utxo(&mut self, outpoint: &OutPoint) -> Result<(), Error> {
+        impl_inner_method!(AnyDatabase, self, set_frozen_utxo, outpoint)
+    }
+    fn set_script_status(&mut self, script: &Script, status: &ScriptStatus) -> Result<(), Error> {
+        impl_inner_method!(AnyDatabase, self, set_script_status, script, status)
+    }
+    fn set_birthday(&mut self, birthday: &Birthday) -> Result<(), Error> {
+        impl_inner_method!(AnyDatabase, self, set_birthday, birthday)
+    }
+    fn set_block_hash(&mut self, height: u32, hash: &BlockHash) -> Result<(), Error> {
+        impl_inner_method!(AnyDatabase, self, set_block_hash, height, hash)
+    }
+    fn set_synced_tip(&mut self, height: u32, hash: &BlockHash) -> Result<(), Error> {
+        impl_inner_method!(AnyDatabase, self, set_synced_tip, height, hash)
+    }
 
     fn del_script_pubkey_from_path(
         &mut self,
@@ -168,6 +186,24 @@ impl BatchOperations for AnyDatabase {
     fn del_last_index(&mut self, keychain: KeychainKind) -> Result<Option<u32>, Error> {
         impl_inner_method!(AnyDatabase, self, del_last_index, keychain)
     }
+    fn del_label(&mut self, target: &LabelRef) -> Result<Option<String>, Error> {
+        impl_inner_method!(AnyDatabase, self, del_label, target)
+    }
+    fn del_frozen_utxo(&mut self, outpoint: &OutPoint) -> Result<bool, Error> {
+        impl_inner_method!(AnyDatabase, self, del_frozen_utxo, outpoint)
+    }
+    fn del_script_status(&mut self, script: &Script) -> Result<Option<ScriptStatus>, Error> {
+        impl_inner_method!(AnyDatabase, self, del_script_status, script)
+    }
+    fn del_birthday(&mut self) -> Result<Option<Birthday>, Error> {
+        impl_inner_method!(AnyDatabase, self, del_birthday)
+    }
+    fn del_block_hash(&mut self, height: u32) -> Result<Option<BlockHash>, Error> {
+        impl_inner_method!(AnyDatabase, self, del_block_hash, height)
+    }
+    fn del_synced_tip(&mut self) -> Result<Option<(u32, BlockHash)>, Error> {
+        impl_inner_method!(AnyDatabase, self, del_synced_tip)
+    }
 }
 
 impl Database for AnyDatabase {
@@ -197,6 +233,15 @@ impl Database for AnyDatabase {
     fn iter_txs(&self, include_raw: bool) -> Result<Vec<TransactionDetails>, Error> {
         impl_inner_method!(AnyDatabase, self, iter_txs, include_raw)
     }
+    fn iter_labels(&self) -> Result<Vec<(LabelRef, String)>, Error> {
+        impl_inner_method!(AnyDatabase, self, iter_labels)
+    }
+    fn iter_frozen_utxos(&self) -> Result<Vec<OutPoint>, Error> {
+        impl_inner_method!(AnyDatabase, self, iter_frozen_utxos)
+    }
+    fn iter_block_hashes(&self) -> Result<Vec<(u32, BlockHash)>, Error> {
+        impl_inner_method!(AnyDatabase, self, iter_block_hashes)
+    }
 
     fn get_script_pubkey_from_path(
         &self,
@@ -229,6 +274,25 @@ impl Database for AnyDatabase {
     fn get_last_index(&self, keychain: KeychainKind) -> Result<Option<u32>, Error> {
         impl_inner_method!(AnyDatabase, self, get_last_index, keychain)
     }
+    fn get_label(&self, target: &LabelRef) -> Result<Option<String>, Error> {
+        impl_inner_method!(AnyDatabase, self, get_label, target)
+    }
+    fn is_frozen_utxo(&self, outpoint: &OutPoint) -> Result<bool, Error> {
+        impl_inner_method!(AnyDatabase, self, is_frozen_utxo, outpoint)
+    }
+    fn get_script_status(&self, script: &Script) -> Result<Option<ScriptStatus>, Error> {
+        impl_inner_method!(AnyDatabase, self, get_script_status, script)
+    }
+    fn get_birthday(&self) -> Result<Option<Birthday>, Error> {
+        impl_inner_method!(AnyDatabase, self, get_birthday)
+    }
+
+    fn get_block_hash(&self, height: u32) -> Result<Option<BlockHash>, Error> {
+        impl_inner_method!(AnyDatabase, self, get_block_hash, height)
+    }
+    fn get_synced_tip(&self) -> Result<Option<(u32, BlockHash)>, Error> {
+        impl_inner_method!(AnyDatabase, self, get_synced_tip)
+    }
 
     fn increment_last_index(&mut self, keychain: KeychainKind) -> Result<u32, Error> {
         impl_inner_method!(AnyDatabase, self, increment_last_index, keychain)
@@ -256,6 +320,24 @@ impl BatchOperations for AnyBatch {
     fn set_last_index(&mut self, keychain: KeychainKind, value: u32) -> Result<(), Error> {
         impl_inner_method!(AnyBatch, self, set_last_index, keychain, value)
     }
+    fn set_label(&mut self, target: &LabelRef, label: &str) -> Result<(), Error> {
+        impl_inner_method!(AnyBatch, self, set_label, target, label)
+    }
+    fn set_frozen_utxo(&mut self, outpoint: &OutPoint) -> Result<(), Error> {
+        impl_inner_method!(AnyBatch, self, set_frozen_utxo, outpoint)
+    }
+    fn set_script_status(&mut self, script: &Script, status: &ScriptStatus) -> Result<(), Error> {
+        impl_inner_method!(AnyBatch, self, set_script_status, script, status)
+    }
+    fn set_birthday(&mut self, birthday: &Birthday) -> Result<(), Error> {
+        impl_inner_method!(AnyBatch, self, set_birthday, birthday)
+    }
+    fn set_block_hash(&mut self, height: u32, hash: &BlockHash) -> Result<(), Error> {
+        impl_inner_method!(AnyBatch, self, set_block_hash, height, hash)
+    }
+    fn set_synced_tip(&mut self, height: u32, hash: &BlockHash) -> Result<(), Error> {
+        impl_inner_method!(AnyBatch, self, set_synced_tip, height, hash)
+    }
 
     fn del_script_pubkey_from_path(
         &mut self,
@@ -286,6 +368,24 @@ impl BatchOperations for AnyBatch {
     fn del_last_index(&mut self, keychain: KeychainKind) -> Result<Option<u32>, Error> {
         impl_inner_method!(AnyBatch, self, del_last_index, keychain)
     }
+    fn del_label(&mut self, target: &LabelRef) -> Result<Option<String>, Error> {
+        impl_inner_method!(AnyBatch, self, del_label, target)
+    }
+    fn del_frozen_utxo(&mut self, outpoint: &OutPoint) -> Result<bool, Error> {
+        impl_inner_method!(AnyBatch, self, del_frozen_utxo, outpoint)
+    }
+    fn del_script_status(&mut self, script: &Script) -> Result<Option<ScriptStatus>, Error> {
+        impl_inner_method!(AnyBatch, self, del_script_status, script)
+    }
+    fn del_birthday(&mut self) -> Result<Option<Birthday>, Error> {
+        impl_inner_method!(AnyBatch, self, del_birthday)
+    }
+    fn del_block_hash(&mut self, height: u32) -> Result<Option<BlockHash>, Error> {
+        impl_inner_method!(AnyBatch, self, del_block_hash, height)
+    }
+    fn del_synced_tip(&mut self) -> Result<Option<(u32, BlockHash)>, Error> {
+        impl_inner_method!(AnyBatch, self, del_synced_tip)
+    }
 }
 
 impl BatchDatabase for AnyDatabase {
@@ -14,7 +14,7 @@ use std::convert::TryInto;
 use sled::{Batch, Tree};
 
 use bitcoin::consensus::encode::{deserialize, serialize};
-use bitcoin::hash_types::Txid;
+use bitcoin::hash_types::{BlockHash, Txid};
 use bitcoin::{OutPoint, Script, Transaction};
 
 use crate::database::memory::MapKey;
@@ -43,6 +43,7 @@ macro_rules! impl_batch_operations {
             let value = json!({
                 "t": utxo.txout,
                 "i": utxo.keychain,
+                "c": utxo.is_coinbase,
             });
             self.insert(key, serde_json::to_vec(&value)?)$($after_insert)*;
 
@@ -60,9 +61,10 @@ macro_rules! impl_batch_operations {
         fn set_tx(&mut self, transaction: &TransactionDetails) -> Result<(), Error> {
             let key = MapKey::Transaction(Some(&transaction.txid)).as_map_key();
 
-            // remove the raw tx from the serialized version
+            // remove the raw tx and the label from the serialized version
             let mut value = serde_json::to_value(transaction)?;
             value["transaction"] = serde_json::Value::Null;
+            value["label"] = serde_json::Value::Null;
             let value = serde_json::to_vec(&value)?;
 
             self.insert(key, value)$($after_insert)*;
@@ -82,6 +84,52 @@ macro_rules! impl_batch_operations {
             Ok(())
         }
 
+        fn set_label(&mut self, target: &LabelRef, label: &str) -> Result<(), Error> {
+            let key = MapKey::Label(Some(target)).as_map_key();
+            let value = json!({
+                "r": target,
+                "l": label,
+            });
+            self.insert(key, serde_json::to_vec(&value)?)$($after_insert)*;
+
+            Ok(())
+        }
+
+        fn set_frozen_utxo(&mut self, outpoint: &OutPoint) -> Result<(), Error> {
+            let key = MapKey::FrozenUtxo(Some(outpoint)).as_map_key();
+            self.insert(key, vec![])$($after_insert)*;
+
+            Ok(())
+        }
+
+        fn set_script_status(&mut self, script: &Script, status: &ScriptStatus) -> Result<(), Error> {
+            let key = MapKey::ScriptStatus(Some(script)).as_map_key();
+            self.insert(key, serde_json::to_vec(status)?)$($after_insert)*;
+
+            Ok(())
+        }
+
+        fn set_birthday(&mut self, birthday: &Birthday) -> Result<(), Error> {
+            let key = MapKey::Birthday.as_map_key();
+            self.insert(key, serde_json::to_vec(birthday)?)$($after_insert)*;
+
+            Ok(())
+        }
+
+        fn set_block_hash(&mut self, height: u32, hash: &BlockHash) -> Result<(), Error> {
+            let key = MapKey::BlockHash(Some(height)).as_map_key();
+            self.insert(key, serialize(hash))$($after_insert)*;
+
+            Ok(())
+        }
+
+        fn set_synced_tip(&mut self, height: u32, hash: &BlockHash) -> Result<(), Error> {
+            let key = MapKey::SyncedTip.as_map_key();
+            self.insert(key, serialize(&(height, *hash)))$($after_insert)*;
+
+            Ok(())
+        }
+
         fn del_script_pubkey_from_path(&mut self, keychain: KeychainKind, path: u32) -> Result<Option<Script>, Error> {
             let key = MapKey::Path((Some(keychain), Some(path))).as_map_key();
             let res = self.remove(key);
@@ -118,8 +166,9 @@ macro_rules! impl_batch_operations {
                     let mut val: serde_json::Value = serde_json::from_slice(&b)?;
                     let txout = serde_json::from_value(val["t"].take())?;
                     let keychain = serde_json::from_value(val["i"].take())?;
+                    let is_coinbase = val["c"].as_bool().unwrap_or(false);
 
-                    Ok(Some(LocalUtxo { outpoint: outpoint.clone(), txout, keychain }))
+                    Ok(Some(LocalUtxo { outpoint: outpoint.clone(), txout, keychain, label: None, is_frozen: false, is_coinbase }))
                 }
             }
         }
@@ -168,6 +217,68 @@ macro_rules! impl_batch_operations {
                 }
             }
         }
+
+        fn del_label(&mut self, target: &LabelRef) -> Result<Option<String>, Error> {
+            let key = MapKey::Label(Some(target)).as_map_key();
+            let res = self.remove(key);
+            let res = $process_delete!(res);
+
+            match res {
+                None => Ok(None),
+                Some(b) => {
+                    let mut val: serde_json::Value = serde_json::from_slice(&b)?;
+                    let label = serde_json::from_value(val["l"].take())?;
+
+                    Ok(Some(label))
+                }
+            }
+        }
+
+        fn del_frozen_utxo(&mut self, outpoint: &OutPoint) -> Result<bool, Error> {
+            let key = MapKey::FrozenUtxo(Some(outpoint)).as_map_key();
+            let res = self.remove(key);
+            let res = $process_delete!(res);
+
+            Ok(res.is_some())
+        }
+
+        fn del_script_status(&mut self, script: &Script) -> Result<Option<ScriptStatus>, Error> {
+            let key = MapKey::ScriptStatus(Some(script)).as_map_key();
+            let res = self.remove(key);
+            let res = $process_delete!(res);
+
+            match res {
+                None => Ok(None),
+                Some(b) => Ok(Some(serde_json::from_slice(&b)?)),
+            }
+        }
+
+        fn del_birthday(&mut self) -> Result<Option<Birthday>, Error> {
+            let key = MapKey::Birthday.as_map_key();
+            let res = self.remove(key);
+            let res = $process_delete!(res);
+
+            match res {
+                None => Ok(None),
+                Some(b) => Ok(Some(serde_json::from_slice(&b)?)),
+            }
+        }
+
+        fn del_block_hash(&mut self, height: u32) -> Result<Option<BlockHash>, Error> {
+            let key = MapKey::BlockHash(Some(height)).as_map_key();
+            let res = self.remove(key);
+            let res = $process_delete!(res);
+
+            Ok(res.map_or(Ok(None), |x| Some(deserialize(&x)).transpose())?)
+        }
+
+        fn del_synced_tip(&mut self) -> Result<Option<(u32, BlockHash)>, Error> {
+            let key = MapKey::SyncedTip.as_map_key();
+            let res = self.remove(key);
+            let res = $process_delete!(res);
+
+            Ok(res.map_or(Ok(None), |x| Some(deserialize(&x)).transpose())?)
+        }
     }
 }
 
@@ -231,11 +342,15 @@ impl Database for Tree {
                 let mut val: serde_json::Value = serde_json::from_slice(&v)?;
                 let txout = serde_json::from_value(val["t"].take())?;
                 let keychain = serde_json::from_value(val["i"].take())?;
+                let is_coinbase = val["c"].as_bool().unwrap_or(false);
 
                 Ok(LocalUtxo {
                     outpoint,
                     txout,
                     keychain,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase,
                 })
             })
             .collect()
@@ -267,6 +382,43 @@ impl Database for Tree {
             .collect()
     }
 
+    fn iter_labels(&self) -> Result<Vec<(LabelRef, String)>, Error> {
+        let key = MapKey::Label(None).as_map_key();
+        self.scan_prefix(key)
+            .map(|x| -> Result<_, Error> {
+                let (_, v) = x?;
+                let mut val: serde_json::Value = serde_json::from_slice(&v)?;
+                let target = serde_json::from_value(val["r"].take())?;
+                let label = serde_json::from_value(val["l"].take())?;
+
+                Ok((target, label))
+            })
+            .collect()
+    }
+
+    fn iter_frozen_utxos(&self) -> Result<Vec<OutPoint>, Error> {
+        let key = MapKey::FrozenUtxo(None).as_map_key();
+        self.scan_prefix(key)
+            .map(|x| -> Result<_, Error> {
+                let (k, _) = x?;
+                Ok(deserialize(&k[1..])?)
+            })
+            .collect()
+    }
+
+    fn iter_block_hashes(&self) -> Result<Vec<(u32, BlockHash)>, Error> {
+        let key = MapKey::BlockHash(None).as_map_key();
+        self.scan_prefix(key)
+            .map(|x| -> Result<_, Error> {
+                let (k, v) = x?;
+                let height: [u8; 4] = k[1..]
+                    .try_into()
+                    .map_err(|_| Error::InvalidU32Bytes(k[1..].to_vec()))?;
+                Ok((u32::from_be_bytes(height), deserialize(&v)?))
+            })
+            .collect()
+    }
+
     fn get_script_pubkey_from_path(
         &self,
         keychain: KeychainKind,
@@ -276,6 +428,11 @@ impl Database for Tree {
         Ok(self.get(key)?.map(|b| deserialize(&b)).transpose()?)
     }
 
+    fn get_synced_tip(&self) -> Result<Option<(u32, BlockHash)>, Error> {
+        let key = MapKey::SyncedTip.as_map_key();
+        Ok(self.get(key)?.map(|b| deserialize(&b)).transpose()?)
+    }
+
     fn get_path_from_script_pubkey(
         &self,
         script: &Script,
@@ -299,11 +456,15 @@ impl Database for Tree {
                 let mut val: serde_json::Value = serde_json::from_slice(&b)?;
                 let txout = serde_json::from_value(val["t"].take())?;
                 let keychain = serde_json::from_value(val["i"].take())?;
+                let is_coinbase = val["c"].as_bool().unwrap_or(false);
 
                 Ok(LocalUtxo {
                     outpoint: *outpoint,
                     txout,
                     keychain,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase,
                 })
             })
             .transpose()
@@ -342,6 +503,40 @@ impl Database for Tree {
             .transpose()
     }
 
+    fn get_label(&self, target: &LabelRef) -> Result<Option<String>, Error> {
+        let key = MapKey::Label(Some(target)).as_map_key();
+        self.get(key)?
+            .map(|b| -> Result<_, Error> {
+                let mut val: serde_json::Value = serde_json::from_slice(&b)?;
+                Ok(serde_json::from_value(val["l"].take())?)
+            })
+            .transpose()
+    }
+
+    fn is_frozen_utxo(&self, outpoint: &OutPoint) -> Result<bool, Error> {
+        let key = MapKey::FrozenUtxo(Some(outpoint)).as_map_key();
+        Ok(self.contains_key(key)?)
+    }
+
+    fn get_script_status(&self, script: &Script) -> Result<Option<ScriptStatus>, Error> {
+        let key = MapKey::ScriptStatus(Some(script)).as_map_key();
+        self.get(key)?
+            .map(|b| -> Result<_, Error> { Ok(serde_json::from_slice(&b)?) })
+            .transpose()
+    }
+
+    fn get_birthday(&self) -> Result<Option<Birthday>, Error> {
+        let key = MapKey::Birthday.as_map_key();
+        self.get(key)?
+            .map(|b| -> Result<_, Error> { Ok(serde_json::from_slice(&b)?) })
+            .transpose()
+    }
+
+    fn get_block_hash(&self, height: u32) -> Result<Option<BlockHash>, Error> {
+        let key = MapKey::BlockHash(Some(height)).as_map_key();
+        Ok(self.get(key)?.map(|b| deserialize(&b)).transpose()?)
+    }
+
     // inserts 0 if not present
     fn increment_last_index(&mut self, keychain: KeychainKind) -> Result<u32, Error> {
         let key = MapKey::LastIndex(keychain).as_map_key();
@@ -465,4 +660,34 @@ mod test {
     fn test_last_index() {
         crate::database::test::test_last_index(get_tree());
     }
+
+    #[test]
+    fn test_label() {
+        crate::database::test::test_label(get_tree());
+    }
+
+    #[test]
+    fn test_frozen_utxo() {
+        crate::database::test::test_frozen_utxo(get_tree());
+    }
+
+    #[test]
+    fn test_script_status() {
+        crate::database::test::test_script_status(get_tree());
+    }
+
+    #[test]
+    fn test_birthday() {
+        crate::database::test::test_birthday(get_tree());
+    }
+
+    #[test]
+    fn test_block_hash() {
+        crate::database::test::test_block_hash(get_tree());
+    }
+
+    #[test]
+    fn test_synced_tip() {
+        crate::database::test::test_synced_tip(get_tree());
+    }
 }
@@ -18,7 +18,7 @@ use std::collections::BTreeMap;
 use std::ops::Bound::{Excluded, Included};
 
 use bitcoin::consensus::encode::{deserialize, serialize};
-use bitcoin::hash_types::Txid;
+use bitcoin::hash_types::{BlockHash, Txid};
 use bitcoin::{OutPoint, Script, Transaction};
 
 use crate::database::{BatchDatabase, BatchOperations, ConfigurableDatabase, Database};
@@ -32,6 +32,12 @@ use crate::types::*;
 // transactions         t<txid> -> tx details
 // deriv indexes        c{i,e} -> u32
 // descriptor checksum  d{i,e} -> vec<u8>
+// labels               l{t,o,s,i,p,x}<txid/outpoint/script/input/pubkey/xpub> -> label
+// frozen utxos         f<outpoint> -> outpoint
+// script status        h<script> -> script status
+// birthday             b -> birthday
+// block hashes         k<height> -> block hash
+// synced tip           y -> (height, block hash)
 
 pub(crate) enum MapKey<'a> {
     Path((Option<KeychainKind>, Option<u32>)),
@@ -41,6 +47,12 @@ pub(crate) enum MapKey<'a> {
     Transaction(Option<&'a Txid>),
     LastIndex(KeychainKind),
     DescriptorChecksum(KeychainKind),
+    Label(Option<&'a LabelRef>),
+    FrozenUtxo(Option<&'a OutPoint>),
+    ScriptStatus(Option<&'a Script>),
+    Birthday,
+    BlockHash(Option<u32>),
+    SyncedTip,
 }
 
 impl MapKey<'_> {
@@ -59,6 +71,12 @@ impl MapKey<'_> {
             MapKey::Transaction(_) => b"t".to_vec(),
             MapKey::LastIndex(st) => [b"c", st.as_ref()].concat(),
             MapKey::DescriptorChecksum(st) => [b"d", st.as_ref()].concat(),
+            MapKey::Label(_) => b"l".to_vec(),
+            MapKey::FrozenUtxo(_) => b"f".to_vec(),
+            MapKey::ScriptStatus(_) => b"h".to_vec(),
+            MapKey::Birthday => b"b".to_vec(),
+            MapKey::BlockHash(_) => b"k".to_vec(),
+            MapKey::SyncedTip => b"y".to_vec(),
         }
     }
 
@@ -69,6 +87,26 @@ impl MapKey<'_> {
             MapKey::Utxo(Some(s)) => serialize(*s),
             MapKey::RawTx(Some(s)) => serialize(*s),
             MapKey::Transaction(Some(s)) => serialize(*s),
+            MapKey::FrozenUtxo(Some(s)) => serialize(*s),
+            MapKey::ScriptStatus(Some(s)) => serialize(*s),
+            // big endian, to keep them sorted by height
+            MapKey::BlockHash(Some(height)) => height.to_be_bytes().to_vec(),
+            MapKey::Label(Some(LabelRef::Transaction(txid))) => {
+                [b"t", &serialize(txid)[..]].concat()
+            }
+            MapKey::Label(Some(LabelRef::Output(outpoint))) => {
+                [b"o", &serialize(outpoint)[..]].concat()
+            }
+            MapKey::Label(Some(LabelRef::Script(script))) => {
+                [b"s", &serialize(script)[..]].concat()
+            }
+            MapKey::Label(Some(LabelRef::Input { txid, vin })) => {
+                [b"i", &serialize(txid)[..], &vin.to_be_bytes()[..]].concat()
+            }
+            MapKey::Label(Some(LabelRef::PublicKey(key))) => [b"p", &key.to_bytes()[..]].concat(),
+            MapKey::Label(Some(LabelRef::ExtendedPubKey(xpub))) => {
+                [b"x", &xpub.encode()[..]].concat()
+            }
             _ => vec![],
         }
     }
@@ -146,8 +184,10 @@ impl BatchOperations for MemoryDatabase {
 
     fn set_utxo(&mut self, utxo: &LocalUtxo) -> Result<(), Error> {
         let key = MapKey::Utxo(Some(&utxo.outpoint)).as_map_key();
-        self.map
-            .insert(key, Box::new((utxo.txout.clone(), utxo.keychain)));
+        self.map.insert(
+            key,
+            Box::new((utxo.txout.clone(), utxo.keychain, utxo.is_coinbase)),
+        );
 
         Ok(())
     }
@@ -165,9 +205,10 @@ impl BatchOperations for MemoryDatabase {
             self.set_raw_tx(tx)?;
         }
 
-        // remove the raw tx from the serialized version
+        // remove the raw tx and the label from the serialized version
         let mut transaction = transaction.clone();
         transaction.transaction = None;
+        transaction.label = None;
 
         self.map.insert(key, Box::new(transaction));
 
@@ -179,6 +220,43 @@ impl BatchOperations for MemoryDatabase {
 
         Ok(())
     }
+    fn set_label(&mut self, target: &LabelRef, label: &str) -> Result<(), Error> {
+        let key = MapKey::Label(Some(target)).as_map_key();
+        self.map
+            .insert(key, Box::new((target.clone(), label.to_string())));
+
+        Ok(())
+    }
+    fn set_frozen_utxo(&mut self, outpoint: &OutPoint) -> Result<(), Error> {
+        let key = MapKey::FrozenUtxo(Some(outpoint)).as_map_key();
+        self.map.insert(key, Box::new(*outpoint));
+
+        Ok(())
+    }
+    fn set_script_status(&mut self, script: &Script, status: &ScriptStatus) -> Result<(), Error> {
+        let key = MapKey::ScriptStatus(Some(script)).as_map_key();
+        self.map.insert(key, Box::new(status.clone()));
+
+        Ok(())
+    }
+    fn set_birthday(&mut self, birthday: &Birthday) -> Result<(), Error> {
+        let key = MapKey::Birthday.as_map_key();
+        self.map.insert(key, Box::new(*birthday));
+
+        Ok(())
+    }
+    fn set_block_hash(&mut self, height: u32, hash: &BlockHash) -> Result<(), Error> {
+        let key = MapKey::BlockHash(Some(height)).as_map_key();
+        self.map.insert(key, Box::new((height, *hash)));
+
+        Ok(())
+    }
+    fn set_synced_tip(&mut self, height: u32, hash: &BlockHash) -> Result<(), Error> {
+        let key = MapKey::SyncedTip.as_map_key();
+        self.map.insert(key, Box::new((height, *hash)));
+
+        Ok(())
+    }
 
     fn del_script_pubkey_from_path(
         &mut self,
@@ -218,11 +296,14 @@ impl BatchOperations for MemoryDatabase {
         match res {
             None => Ok(None),
             Some(b) => {
-                let (txout, keychain) = b.downcast_ref().cloned().unwrap();
+                let (txout, keychain, is_coinbase) = b.downcast_ref().cloned().unwrap();
                 Ok(Some(LocalUtxo {
                     outpoint: *outpoint,
                     txout,
                     keychain,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase,
                 }))
             }
         }
@@ -269,6 +350,54 @@ impl BatchOperations for MemoryDatabase {
             Some(b) => Ok(Some(*b.downcast_ref().unwrap())),
         }
     }
+    fn del_label(&mut self, target: &LabelRef) -> Result<Option<String>, Error> {
+        let key = MapKey::Label(Some(target)).as_map_key();
+        let res = self.map.remove(&key);
+        self.deleted_keys.push(key);
+
+        match res {
+            None => Ok(None),
+            Some(b) => {
+                let (_, label): (LabelRef, String) = b.downcast_ref().cloned().unwrap();
+                Ok(Some(label))
+            }
+        }
+    }
+    fn del_frozen_utxo(&mut self, outpoint: &OutPoint) -> Result<bool, Error> {
+        let key = MapKey::FrozenUtxo(Some(outpoint)).as_map_key();
+        let res = self.map.remove(&key);
+        self.deleted_keys.push(key);
+
+        Ok(res.is_some())
+    }
+    fn del_script_status(&mut self, script: &Script) -> Result<Option<ScriptStatus>, Error> {
+        let key = MapKey::ScriptStatus(Some(script)).as_map_key();
+        let res = self.map.remove(&key);
+        self.deleted_keys.push(key);
+
+        Ok(res.map(|x| x.downcast_ref().cloned().unwrap()))
+    }
+    fn del_birthday(&mut self) -> Result<Option<Birthday>, Error> {
+        let key = MapKey::Birthday.as_map_key();
+        let res = self.map.remove(&key);
+        self.deleted_keys.push(key);
+
+        Ok(res.map(|x| *x.downcast_ref().unwrap()))
+    }
+    fn del_block_hash(&mut self, height: u32) -> Result<Option<BlockHash>, Error> {
+        let key = MapKey::BlockHash(Some(height)).as_map_key();
+        let res = self.map.remove(&key);
+        self.deleted_keys.push(key);
+
+        Ok(res.map(|x| x.downcast_ref::<(u32, BlockHash)>().unwrap().1))
+    }
+    fn del_synced_tip(&mut self) -> Result<Option<(u32, BlockHash)>, Error> {
+        let key = MapKey::SyncedTip.as_map_key();
+        let res = self.map.remove(&key);
+        self.deleted_keys.push(key);
+
+        Ok(res.map(|x| *x.downcast_ref().unwrap()))
+    }
 }
 
 impl Database for MemoryDatabase {
@@ -309,11 +438,14 @@ impl Database for MemoryDatabase {
             .range::<Vec<u8>, _>((Included(&key), Excluded(&after(&key))))
             .map(|(k, v)| {
                 let outpoint = deserialize(&k[1..]).unwrap();
-                let (txout, keychain) = v.downcast_ref().cloned().unwrap();
+                let (txout, keychain, is_coinbase) = v.downcast_ref().cloned().unwrap();
                 Ok(LocalUtxo {
                     outpoint,
                     txout,
                     keychain,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase,
                 })
             })
             .collect()
@@ -343,6 +475,30 @@ impl Database for MemoryDatabase {
             .collect()
     }
 
+    fn iter_labels(&self) -> Result<Vec<(LabelRef, String)>, Error> {
+        let key = MapKey::Label(None).as_map_key();
+        self.map
+            .range::<Vec<u8>, _>((Included(&key), Excluded(&after(&key))))
+            .map(|(_, v)| Ok(v.downcast_ref().cloned().unwrap()))
+            .collect()
+    }
+
+    fn iter_frozen_utxos(&self) -> Result<Vec<OutPoint>, Error> {
+        let key = MapKey::FrozenUtxo(None).as_map_key();
+        self.map
+            .range::<Vec<u8>, _>((Included(&key), Excluded(&after(&key))))
+            .map(|(_, v)| Ok(*v.downcast_ref().unwrap()))
+            .collect()
+    }
+
+    fn iter_block_hashes(&self) -> Result<Vec<(u32, BlockHash)>, Error> {
+        let key = MapKey::BlockHash(None).as_map_key();
+        self.map
+            .range::<Vec<u8>, _>((Included(&key), Excluded(&after(&key))))
+            .map(|(_, v)| Ok(*v.downcast_ref().unwrap()))
+            .collect()
+    }
+
     fn get_script_pubkey_from_path(
         &self,
         keychain: KeychainKind,
@@ -372,11 +528,14 @@ impl Database for MemoryDatabase {
     fn get_utxo(&self, outpoint: &OutPoint) -> Result<Option<LocalUtxo>, Error> {
         let key = MapKey::Utxo(Some(outpoint)).as_map_key();
         Ok(self.map.get(&key).map(|b| {
-            let (txout, keychain) = b.downcast_ref().cloned().unwrap();
+            let (txout, keychain, is_coinbase) = b.downcast_ref().cloned().unwrap();
             LocalUtxo {
                 outpoint: *outpoint,
                 txout,
                 keychain,
+                label: None,
+                is_frozen: false,
+                is_coinbase,
             }
         }))
     }
@@ -406,6 +565,45 @@ impl Database for MemoryDatabase {
         Ok(self.map.get(&key).map(|b| *b.downcast_ref().unwrap()))
     }
 
+    fn get_label(&self, target: &LabelRef) -> Result<Option<String>, Error> {
+        let key = MapKey::Label(Some(target)).as_map_key();
+        Ok(self.map.get(&key).map(|b| {
+            let (_, label): (LabelRef, String) = b.downcast_ref().cloned().unwrap();
+            label
+        }))
+    }
+
+    fn is_frozen_utxo(&self, outpoint: &OutPoint) -> Result<bool, Error> {
+        let key = MapKey::FrozenUtxo(Some(outpoint)).as_map_key();
+        Ok(self.map.contains_key(&key))
+    }
+
+    fn get_script_status(&self, script: &Script) -> Result<Option<ScriptStatus>, Error> {
+        let key = MapKey::ScriptStatus(Some(script)).as_map_key();
+        Ok(self
+            .map
+            .get(&key)
+            .map(|b| b.downcast_ref().cloned().unwrap()))
+    }
+
+    fn get_birthday(&self) -> Result<Option<Birthday>, Error> {
+        let key = MapKey::Birthday.as_map_key();
+        Ok(self.map.get(&key).map(|b| *b.downcast_ref().unwrap()))
+    }
+
+    fn get_block_hash(&self, height: u32) -> Result<Option<BlockHash>, Error> {
+        let key = MapKey::BlockHash(Some(height)).as_map_key();
+        Ok(self
+            .map
+            .get(&key)
+            .map(|b| b.downcast_ref::<(u32, BlockHash)>().unwrap().1))
+    }
+
+    fn get_synced_tip(&self) -> Result<Option<(u32, BlockHash)>, Error> {
+        let key = MapKey::SyncedTip.as_map_key();
+        Ok(self.map.get(&key).map(|b| *b.downcast_ref().unwrap()))
+    }
+
     // inserts 0 if not present
     fn increment_last_index(&mut self, keychain: KeychainKind) -> Result<u32, Error> {
         let key = MapKey::LastIndex(keychain).as_map_key();
@@ -486,6 +684,7 @@ macro_rules! populate_test_db {
             sent: 0,
             confirmation_time,
             verified: current_height.is_some(),
+            label: None,
         };
 
         db.set_tx(&tx_details).unwrap();
@@ -497,6 +696,9 @@ macro_rules! populate_test_db {
                     vout: vout as u32,
                 },
                 keychain: KeychainKind::External,
+                label: None,
+                is_frozen: false,
+                is_coinbase: false,
             })
             .unwrap();
         }
@@ -582,4 +784,34 @@ mod test {
     fn test_last_index() {
         crate::database::test::test_last_index(get_tree());
     }
+
+    #[test]
+    fn test_label() {
+        crate::database::test::test_label(get_tree());
+    }
+
+    #[test]
+    fn test_frozen_utxo() {
+        crate::database::test::test_frozen_utxo(get_tree());
+    }
+
+    #[test]
+    fn test_script_status() {
+        crate::database::test::test_script_status(get_tree());
+    }
+
+    #[test]
+    fn test_birthday() {
+        crate::database::test::test_birthday(get_tree());
+    }
+
+    #[test]
+    fn test_block_hash() {
+        crate::database::test::test_block_hash(get_tree());
+    }
+
+    #[test]
+    fn test_synced_tip() {
+        crate::database::test::test_synced_tip(get_tree());
+    }
 }
@@ -24,7 +24,7 @@
 //!
 //! [`Wallet`]: crate::wallet::Wallet
 
-use bitcoin::hash_types::Txid;
+use bitcoin::hash_types::{BlockHash, Txid};
 use bitcoin::{OutPoint, Script, Transaction, TxOut};
 
 use crate::error::Error;
@@ -59,6 +59,18 @@ pub trait BatchOperations {
     fn set_tx(&mut self, transaction: &TransactionDetails) -> Result<(), Error>;
     /// Store the last derivation index for a given keychain.
     fn set_last_index(&mut self, keychain: KeychainKind, value: u32) -> Result<(), Error>;
+    /// Store the label of a transaction, an output or a script_pubkey
+    fn set_label(&mut self, target: &LabelRef, label: &str) -> Result<(), Error>;
+    /// Mark a UTXO as frozen, so that it's not selected automatically when building transactions
+    fn set_frozen_utxo(&mut self, outpoint: &OutPoint) -> Result<(), Error>;
+    /// Store the [`ScriptStatus`] of a script_pubkey
+    fn set_script_status(&mut self, script: &Script, status: &ScriptStatus) -> Result<(), Error>;
+    /// Store the [`Birthday`] of the wallet
+    fn set_birthday(&mut self, birthday: &Birthday) -> Result<(), Error>;
+    /// Store the hash of the block synced at `height`
+    fn set_block_hash(&mut self, height: u32, hash: &BlockHash) -> Result<(), Error>;
+    /// Store the height and the hash of the last block synced by an Electrum-like backend
+    fn set_synced_tip(&mut self, height: u32, hash: &BlockHash) -> Result<(), Error>;
 
     /// Delete a script_pubkey given the keychain and its child number.
     fn del_script_pubkey_from_path(
@@ -84,6 +96,18 @@ pub trait BatchOperations {
     ) -> Result<Option<TransactionDetails>, Error>;
     /// Delete the last derivation index for a keychain.
     fn del_last_index(&mut self, keychain: KeychainKind) -> Result<Option<u32>, Error>;
+    /// Delete the label of a transaction, an output or a script_pubkey
+    fn del_label(&mut self, target: &LabelRef) -> Result<Option<String>, Error>;
+    /// Remove the frozen flag from a UTXO, returning whether it was set
+    fn del_frozen_utxo(&mut self, outpoint: &OutPoint) -> Result<bool, Error>;
+    /// Delete the [`ScriptStatus`] of a script_pubkey
+    fn del_script_status(&mut self, script: &Script) -> Result<Option<ScriptStatus>, Error>;
+    /// Delete the [`Birthday`] of the wallet
+    fn del_birthday(&mut self) -> Result<Option<Birthday>, Error>;
+    /// Delete the hash of the block synced at `height`
+    fn del_block_hash(&mut self, height: u32) -> Result<Option<BlockHash>, Error>;
+    /// Delete the last block synced by an Electrum-like backend
+    fn del_synced_tip(&mut self) -> Result<Option<(u32, BlockHash)>, Error>;
 }
 
 /// Trait for reading data from a database
@@ -109,6 +133,12 @@ pub trait Database: BatchOperations {
     fn iter_raw_txs(&self) -> Result<Vec<Transaction>, Error>;
     /// Return the list of transactions metadata
     fn iter_txs(&self, include_raw: bool) -> Result<Vec<TransactionDetails>, Error>;
+    /// Return the list of labels, along with the object they are attached to
+    fn iter_labels(&self) -> Result<Vec<(LabelRef, String)>, Error>;
+    /// Return the list of frozen UTXOs
+    fn iter_frozen_utxos(&self) -> Result<Vec<OutPoint>, Error>;
+    /// Return the heights and hashes of the blocks synced, sorted by height
+    fn iter_block_hashes(&self) -> Result<Vec<(u32, BlockHash)>, Error>;
 
     /// Fetch a script_pubkey given the child number of a keychain.
     fn get_script_pubkey_from_path(
@@ -129,6 +159,18 @@ pub trait Database: BatchOperations {
     fn get_tx(&self, txid: &Txid, include_raw: bool) -> Result<Option<TransactionDetails>, Error>;
     /// Return the last defivation index for a keychain.
     fn get_last_index(&self, keychain: KeychainKind) -> Result<Option<u32>, Error>;
+    /// Fetch the label of a transaction, an output or a script_pubkey
+    fn get_label(&self, target: &LabelRef) -> Result<Option<String>, Error>;
+    /// Check whether a UTXO has been frozen
+    fn is_frozen_utxo(&self, outpoint: &OutPoint) -> Result<bool, Error>;
+    /// Fetch the [`ScriptStatus`] of a script_pubkey
+    fn get_script_status(&self, script: &Script) -> Result<Option<ScriptStatus>, Error>;
+    /// Fetch the [`Birthday`] of the wallet
+    fn get_birthday(&self) -> Result<Option<Birthday>, Error>;
+    /// Fetch the hash of the block synced at `height`
+    fn get_block_hash(&self, height: u32) -> Result<Option<BlockHash>, Error>;
+    /// Fetch the height and the hash of the last block synced by an Electrum-like backend
+    fn get_synced_tip(&self) -> Result<Option<(u32, BlockHash)>, Error>;
 
     /// Increment the last derivation index for a keychain and return it
     ///
@@ -185,6 +227,63 @@ pub(crate) trait DatabaseUtils: Database {
             })
             .transpose()
     }
+
+    /// Remove some transactions along with the UTXOs they created, and restore the UTXOs of the
+    /// wallet they spent, writing the changes to `updates`
+    ///
+    /// This is used by the backends that scan the blocks themselves to roll back the transactions
+    /// disconnected by a reorg or evicted from the mempool, which are added back if they are found
+    /// again.
+    #[cfg(any(feature = "rpc", feature = "compact_filters"))]
+    fn drop_txs(
+        &self,
+        dropped_txs: &[TransactionDetails],
+        updates: &mut dyn BatchOperations,
+    ) -> Result<(), Error> {
+        let dropped_txids: std::collections::HashSet<_> =
+            dropped_txs.iter().map(|details| details.txid).collect();
+
+        for details in dropped_txs {
+            updates.del_tx(&details.txid, false)?;
+
+            let tx = match &details.transaction {
+                Some(tx) => tx,
+                None => continue,
+            };
+            for vout in 0..tx.output.len() {
+                updates.del_utxo(&OutPoint::new(details.txid, vout as u32))?;
+            }
+            for input in &tx.input {
+                let previous_output = &input.previous_output;
+                if dropped_txids.contains(&previous_output.txid) {
+                    continue;
+                }
+
+                let prev_tx = match self.get_raw_tx(&previous_output.txid)? {
+                    Some(prev_tx) => prev_tx,
+                    None => continue,
+                };
+                let txout = match prev_tx.output.get(previous_output.vout as usize) {
+                    Some(txout) => txout,
+                    None => continue,
+                };
+                if let Some((keychain, _)) =
+                    self.get_path_from_script_pubkey(&txout.script_pubkey)?
+                {
+                    updates.set_utxo(&LocalUtxo {
+                        outpoint: *previous_output,
+                        txout: txout.clone(),
+                        keychain,
+                        label: None,
+                        is_frozen: false,
+                        is_coinbase: prev_tx.is_coin_base(),
+                    })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl<T: Database> DatabaseUtils for T {}
@@ -195,6 +294,7 @@ pub mod test {
 
     use bitcoin::consensus::encode::deserialize;
     use bitcoin::hashes::hex::*;
+    use bitcoin::hashes::{sha256, Hash};
     use bitcoin::*;
 
     use super::*;
@@ -289,6 +389,9 @@ pub mod test {
             txout,
             outpoint,
             keychain: KeychainKind::External,
+            label: None,
+            is_frozen: false,
+            is_coinbase: true,
         };
 
         tree.set_utxo(&utxo).unwrap();
@@ -322,6 +425,7 @@ pub mod test {
                 height: 1000,
             }),
             verified: true,
+            label: None,
         };
 
         tree.set_tx(&tx_details).unwrap();
@@ -369,5 +473,185 @@ pub mod test {
         );
     }
 
+    pub fn test_label<D: Database>(mut tree: D) {
+        let txid =
+            Txid::from_hex("5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456")
+                .unwrap();
+        let outpoint = OutPoint::new(txid, 1);
+        let script = Script::from(
+            Vec::<u8>::from_hex("76a91402306a7c23f3e8010de41e9e591348bb83f11daa88ac").unwrap(),
+        );
+
+        tree.set_label(&LabelRef::Transaction(txid), "invoice #42")
+            .unwrap();
+        tree.set_label(&LabelRef::Output(outpoint), "change")
+            .unwrap();
+        tree.set_label(&LabelRef::Script(script.clone()), "donations")
+            .unwrap();
+
+        assert_eq!(
+            tree.get_label(&LabelRef::Transaction(txid)).unwrap(),
+            Some("invoice #42".to_string())
+        );
+        assert_eq!(
+            tree.get_label(&LabelRef::Output(outpoint)).unwrap(),
+            Some("change".to_string())
+        );
+        assert_eq!(
+            tree.get_label(&LabelRef::Script(script.clone())).unwrap(),
+            Some("donations".to_string())
+        );
+        assert_eq!(
+            tree.get_label(&LabelRef::Output(OutPoint::new(txid, 0)))
+                .unwrap(),
+            None
+        );
+        assert_eq!(tree.iter_labels().unwrap().len(), 3);
+
+        // overwrite an existing label
+        tree.set_label(&LabelRef::Transaction(txid), "invoice #43")
+            .unwrap();
+        assert_eq!(
+            tree.get_label(&LabelRef::Transaction(txid)).unwrap(),
+            Some("invoice #43".to_string())
+        );
+        assert_eq!(tree.iter_labels().unwrap().len(), 3);
+
+        assert_eq!(
+            tree.del_label(&LabelRef::Script(script.clone())).unwrap(),
+            Some("donations".to_string())
+        );
+        assert_eq!(tree.get_label(&LabelRef::Script(script)).unwrap(), None);
+        assert_eq!(tree.iter_labels().unwrap().len(), 2);
+
+        let input = LabelRef::Input { txid, vin: 1 };
+        tree.set_label(&input, "spent").unwrap();
+        assert_eq!(tree.get_label(&input).unwrap(), Some("spent".to_string()));
+        // inputs and outputs with the same txid and index don't collide
+        assert_eq!(
+            tree.get_label(&LabelRef::Output(outpoint)).unwrap(),
+            Some("change".to_string())
+        );
+        assert!(tree
+            .iter_labels()
+            .unwrap()
+            .contains(&(input, "spent".to_string())));
+    }
+
+    pub fn test_frozen_utxo<D: Database>(mut tree: D) {
+        let outpoint = OutPoint::from_str(
+            "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456:0",
+        )
+        .unwrap();
+
+        assert!(!tree.is_frozen_utxo(&outpoint).unwrap());
+        tree.set_frozen_utxo(&outpoint).unwrap();
+        assert!(tree.is_frozen_utxo(&outpoint).unwrap());
+        assert_eq!(tree.iter_frozen_utxos().unwrap(), vec![outpoint]);
+
+        assert!(tree.del_frozen_utxo(&outpoint).unwrap());
+        assert!(!tree.is_frozen_utxo(&outpoint).unwrap());
+        assert!(tree.iter_frozen_utxos().unwrap().is_empty());
+    }
+
+    pub fn test_script_status<D: Database>(mut tree: D) {
+        let script = Script::from(
+            Vec::<u8>::from_hex("76a91402306a7c23f3e8010de41e9e591348bb83f11daa88ac").unwrap(),
+        );
+        let txid =
+            Txid::from_str("5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456")
+                .unwrap();
+        let status = ScriptStatus {
+            status: sha256::Hash::hash(b"status"),
+            history: vec![(txid, Some(1000)), (txid, None)],
+        };
+
+        assert_eq!(tree.get_script_status(&script).unwrap(), None);
+        tree.set_script_status(&script, &status).unwrap();
+        assert_eq!(
+            tree.get_script_status(&script).unwrap(),
+            Some(status.clone())
+        );
+
+        assert_eq!(tree.del_script_status(&script).unwrap(), Some(status));
+        assert_eq!(tree.get_script_status(&script).unwrap(), None);
+    }
+
+    pub fn test_birthday<D: Database>(mut tree: D) {
+        assert_eq!(tree.get_birthday().unwrap(), None);
+
+        tree.set_birthday(&Birthday::Timestamp(1_600_000_000))
+            .unwrap();
+        assert_eq!(
+            tree.get_birthday().unwrap(),
+            Some(Birthday::Timestamp(1_600_000_000))
+        );
+        tree.set_birthday(&Birthday::Height(650_000)).unwrap();
+        assert_eq!(
+            tree.get_birthday().unwrap(),
+            Some(Birthday::Height(650_000))
+        );
+
+        assert_eq!(
+            tree.del_birthday().unwrap(),
+            Some(Birthday::Height(650_000))
+        );
+        assert_eq!(tree.get_birthday().unwrap(), None);
+    }
+
+    pub fn test_block_hash<D: Database>(mut tree: D) {
+        let hash =
+            BlockHash::from_hex("000000000000000000051f1d1cbf5fcae0e2b9c7a4c7b1c8b9a4f2a44a9b4c9d")
+                .unwrap();
+        let other_hash =
+            BlockHash::from_hex("00000000000000000007b4fd1ff76cfb2f8d4bd4f9c47e7e2f6c4d1b1d8e1a1f")
+                .unwrap();
+
+        assert_eq!(tree.get_block_hash(700_000).unwrap(), None);
+        tree.set_block_hash(700_001, &other_hash).unwrap();
+        tree.set_block_hash(700_000, &hash).unwrap();
+        // 256 would be sorted before 255 with a little endian key
+        tree.set_block_hash(256, &hash).unwrap();
+        tree.set_block_hash(255, &hash).unwrap();
+        assert_eq!(tree.get_block_hash(700_000).unwrap(), Some(hash));
+        assert_eq!(
+            tree.iter_block_hashes().unwrap(),
+            vec![
+                (255, hash),
+                (256, hash),
+                (700_000, hash),
+                (700_001, other_hash)
+            ]
+        );
+
+        assert_eq!(tree.del_block_hash(700_001).unwrap(), Some(other_hash));
+        assert_eq!(tree.get_block_hash(700_001).unwrap(), None);
+        assert_eq!(tree.iter_block_hashes().unwrap().len(), 3);
+    }
+
+    pub fn test_synced_tip<D: Database>(mut tree: D) {
+        let hash =
+            BlockHash::from_hex("000000000000000000051f1d1cbf5fcae0e2b9c7a4c7b1c8b9a4f2a44a9b4c9d")
+                .unwrap();
+        let other_hash =
+            BlockHash::from_hex("00000000000000000007b4fd1ff76cfb2f8d4bd4f9c47e7e2f6c4d1b1d8e1a1f")
+                .unwrap();
+
+        assert_eq!(tree.get_synced_tip().unwrap(), None);
+        tree.set_synced_tip(700_000, &hash).unwrap();
+        tree.set_block_hash(650_000, &other_hash).unwrap();
+        tree.set_synced_tip(700_001, &other_hash).unwrap();
+        assert_eq!(tree.get_synced_tip().unwrap(), Some((700_001, other_hash)));
+        // the synced tip is independent from the block hashes
+        assert_eq!(
+            tree.iter_block_hashes().unwrap(),
+            vec![(650_000, other_hash)]
+        );
+
+        assert_eq!(tree.del_synced_tip().unwrap(), Some((700_001, other_hash)));
+        assert_eq!(tree.get_synced_tip().unwrap(), None);
+        assert_eq!(tree.get_block_hash(650_000).unwrap(), Some(other_hash));
+    }
+
     // TODO: more tests...
 }
@@ -100,6 +100,15 @@ pub enum Error {
     ProgressUpdateError,
     /// Requested outpoint doesn't exist in the tx (vout greater than available outputs)
     InvalidOutpoint(OutPoint),
+    /// The sync found activity too close to the last script_pubkey cached for some keychains
+    ///
+    /// The database has been updated with the history of the script_pubkeys already cached: after
+    /// caching more of them the sync should be run again. [`Wallet::sync`](crate::Wallet::sync)
+    /// takes care of this automatically.
+    MissingCachedScripts {
+        /// Minimum number of script_pubkeys that should be cached for each keychain
+        required: Vec<(crate::types::KeychainKind, u32)>,
+    },
 
     /// Error related to the parsing and usage of descriptors
     Descriptor(crate::descriptor::error::Error),
@@ -128,11 +137,10 @@ pub enum Error {
     //DifferentTransactions,
     //DifferentDescriptorStructure,
     //Uncapable(crate::blockchain::Capability),
-    //MissingCachedAddresses,
     #[cfg(feature = "electrum")]
     /// Electrum client error
     Electrum(electrum_client::Error),
-    #[cfg(feature = "esplora")]
+    #[cfg(any(feature = "esplora", feature = "esplora-blocking"))]
     /// Esplora client error
     Esplora(crate::blockchain::esplora::EsploraError),
     #[cfg(feature = "compact_filters")]
@@ -194,7 +202,7 @@ impl_error!(bitcoin::util::psbt::PsbtParseError, PsbtParse);
 
 #[cfg(feature = "electrum")]
 impl_error!(electrum_client::Error, Electrum);
-#[cfg(feature = "esplora")]
+#[cfg(any(feature = "esplora", feature = "esplora-blocking"))]
 impl_error!(crate::blockchain::esplora::EsploraError, Esplora);
 #[cfg(feature = "key-value-db")]
 impl_error!(sled::Error, Sled);
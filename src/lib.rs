@@ -66,7 +66,7 @@ fn main() -> Result<(), bdk::Error> {
 
     wallet.sync(noop_progress(), None)?;
 
-    println!("Descriptor balance: {} SAT", wallet.get_balance()?);
+    println!("Descriptor balance: {} SAT", wallet.get_balance()?.get_total());
 
     Ok(())
 }
@@ -196,6 +196,7 @@ fn main() -> Result<(), bdk::Error> {
 //! * `compact_filters`: [`compact_filters`](crate::blockchain::compact_filters) client protocol for interacting with the bitcoin P2P network
 //! * `electrum`: [`electrum`](crate::blockchain::electrum) client protocol for interacting with electrum servers
 //! * `esplora`: [`esplora`](crate::blockchain::esplora) client protocol for interacting with blockstream [electrs](https://github.com/Blockstream/electrs) servers
+//! * `esplora-blocking`: same as `esplora`, using a blocking HTTP client instead of the async one. The async client is used if `esplora` is enabled as well. It can't be enabled together with `async-interface` without `esplora`
 //! * `key-value-db`: key value [`database`](crate::database) based on [`sled`](crate::sled) for caching blockchain data
 
 pub extern crate bitcoin;
@@ -210,6 +211,15 @@ compile_error!(
     "Features async-interface and electrum are mutually exclusive and cannot be enabled together"
 );
 
+#[cfg(all(
+    feature = "async-interface",
+    feature = "esplora-blocking",
+    not(feature = "esplora")
+))]
+compile_error!(
+    "Feature esplora-blocking requires the feature esplora to be enabled together with async-interface"
+);
+
 #[cfg(feature = "keys-bip39")]
 extern crate bip39;
 
@@ -231,11 +241,17 @@ pub extern crate electrum_client;
 #[cfg(feature = "esplora")]
 pub extern crate reqwest;
 
+#[cfg(feature = "esplora-blocking")]
+pub extern crate ureq;
+
 #[cfg(feature = "key-value-db")]
 pub extern crate sled;
 
 #[allow(unused_imports)]
 #[cfg(test)]
+#[macro_use]
+extern crate lazy_static;
+
 #[allow(unused_imports)]
 #[cfg(test)]
 #[macro_use]
@@ -345,6 +345,7 @@ macro_rules! bdk_blockchain_tests {
             use $crate::blockchain::noop_progress;
             use $crate::database::MemoryDatabase;
             use $crate::types::KeychainKind;
+            use $crate::wallet::events::WalletEvent;
             use $crate::{Wallet, FeeRate};
             use $crate::testutils;
             use $crate::serial_test::serial;
@@ -389,7 +390,7 @@ macro_rules! bdk_blockchain_tests {
 
                 wallet.sync(noop_progress(), None).unwrap();
 
-                assert_eq!(wallet.get_balance().unwrap(), 50_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance");
                 assert_eq!(wallet.list_unspent().unwrap()[0].keychain, KeychainKind::External, "incorrect keychain kind");
 
                 let list_tx_item = &wallet.list_transactions(false).unwrap()[0];
@@ -399,6 +400,26 @@ macro_rules! bdk_blockchain_tests {
                 assert_eq!(list_tx_item.confirmation_time, None, "incorrect confirmation time");
             }
 
+            #[cfg(any(feature = "test-electrum", feature = "test-esplora"))]
+            #[test]
+            #[serial]
+            fn test_sync_progress() {
+                let (wallet, descriptors, mut test_client) = init_single_sig();
+
+                test_client.receive(testutils! {
+                    @tx ( (@external descriptors, 0) => 50_000 ) ( @confirmations 1 )
+                });
+
+                let (sender, receiver) = $crate::blockchain::progress();
+                wallet.sync(sender, None).unwrap();
+
+                let updates = receiver.try_iter().collect::<Vec<_>>();
+                assert!(updates.len() > 1, "missing progress updates");
+                assert!(updates.windows(2).all(|w| w[0].0 <= w[1].0), "progress is not monotonic");
+                assert!(updates.iter().all(|(_, message)| message.is_some()), "missing progress message");
+                assert_eq!(updates.last().unwrap().0, 100.0, "incorrect final progress");
+            }
+
             #[test]
             #[serial]
             fn test_sync_stop_gap_20() {
@@ -413,17 +434,65 @@ macro_rules! bdk_blockchain_tests {
 
                 wallet.sync(noop_progress(), None).unwrap();
 
-                assert_eq!(wallet.get_balance().unwrap(), 100_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 100_000, "incorrect balance");
                 assert_eq!(wallet.list_transactions(false).unwrap().len(), 2, "incorrect number of txs");
             }
 
+            #[cfg(any(feature = "test-electrum", feature = "test-esplora"))]
+            #[test]
+            #[serial]
+            fn test_sync_extend_cached_scripts() {
+                let (wallet, descriptors, mut test_client) = init_single_sig();
+
+                test_client.receive(testutils! {
+                    @tx ( (@external descriptors, 15) => 50_000 )
+                });
+                test_client.receive(testutils! {
+                    @tx ( (@external descriptors, 30) => 50_000 )
+                });
+                test_client.receive(testutils! {
+                    @tx ( (@external descriptors, 45) => 50_000 )
+                });
+
+                // only cache 20 addresses, the others will have to be derived during the sync
+                let (sender, receiver) = $crate::blockchain::progress();
+                wallet.sync(sender, Some(20)).unwrap();
+
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 150_000, "incorrect balance");
+                assert_eq!(wallet.list_transactions(false).unwrap().len(), 3, "incorrect number of txs");
+
+                let updates = receiver.try_iter().collect::<Vec<_>>();
+                assert!(updates.windows(2).all(|w| w[0].0 <= w[1].0), "progress is not monotonic across the retries");
+            }
+
+            #[cfg(any(feature = "test-electrum", feature = "test-esplora"))]
+            #[test]
+            #[serial]
+            fn test_sync_single_key_wallet() {
+                let descriptors = testutils! {
+                    @descriptors ( "wpkh(Alice)" ) ( @keys ( "Alice" => (@generate_wif) ) )
+                };
+                let mut test_client = TestClient::default();
+                let wallet = get_wallet_from_descriptors(&descriptors);
+
+                test_client.receive(testutils! {
+                    @tx ( (@external descriptors, 0) => 50_000 )
+                });
+
+                let (sender, receiver) = $crate::blockchain::progress();
+                wallet.sync(sender, None).unwrap();
+
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance");
+                assert_eq!(receiver.try_iter().last().unwrap().0, 100.0, "the sync didn't complete");
+            }
+
             #[test]
             #[serial]
             fn test_sync_before_and_after_receive() {
                 let (wallet, descriptors, mut test_client) = init_single_sig();
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 0);
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 0);
 
                 test_client.receive(testutils! {
                     @tx ( (@external descriptors, 0) => 50_000 )
@@ -431,10 +500,37 @@ macro_rules! bdk_blockchain_tests {
 
                 wallet.sync(noop_progress(), None).unwrap();
 
-                assert_eq!(wallet.get_balance().unwrap(), 50_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance");
                 assert_eq!(wallet.list_transactions(false).unwrap().len(), 1, "incorrect number of txs");
             }
 
+            #[test]
+            #[serial]
+            fn test_refresh_mempool_and_new_block() {
+                let (wallet, descriptors, mut test_client) = init_single_sig();
+                let node_addr = test_client.get_node_address(None);
+
+                wallet.sync(noop_progress(), None).unwrap();
+
+                let received_txid = test_client.receive(testutils! {
+                    @tx ( (@external descriptors, 0) => 50_000 )
+                });
+
+                wallet.refresh(noop_progress(), None).unwrap();
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance");
+                let details = wallet.list_transactions(false).unwrap();
+                assert_eq!(details.len(), 1, "incorrect number of txs");
+                assert_eq!(details[0].txid, received_txid);
+                assert!(details[0].confirmation_time.is_none());
+
+                test_client.generate(1, Some(node_addr));
+                wallet.refresh(noop_progress(), None).unwrap();
+
+                let details = wallet.list_transactions(false).unwrap();
+                assert_eq!(details.len(), 1, "incorrect number of txs");
+                assert!(details[0].confirmation_time.is_some(), "the new block should trigger a full sync");
+            }
+
             #[test]
             #[serial]
             fn test_sync_multiple_outputs_same_tx() {
@@ -446,7 +542,7 @@ macro_rules! bdk_blockchain_tests {
 
                 wallet.sync(noop_progress(), None).unwrap();
 
-                assert_eq!(wallet.get_balance().unwrap(), 105_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 105_000, "incorrect balance");
                 assert_eq!(wallet.list_transactions(false).unwrap().len(), 1, "incorrect number of txs");
                 assert_eq!(wallet.list_unspent().unwrap().len(), 3, "incorrect number of unspents");
 
@@ -471,7 +567,7 @@ macro_rules! bdk_blockchain_tests {
 
                 wallet.sync(noop_progress(), None).unwrap();
 
-                assert_eq!(wallet.get_balance().unwrap(), 75_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 75_000, "incorrect balance");
                 assert_eq!(wallet.list_transactions(false).unwrap().len(), 2, "incorrect number of txs");
                 assert_eq!(wallet.list_unspent().unwrap().len(), 2, "incorrect number of unspent");
             }
@@ -486,14 +582,14 @@ macro_rules! bdk_blockchain_tests {
                 });
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 50_000);
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000);
 
                 test_client.receive(testutils! {
                     @tx ( (@external descriptors, 0) => 25_000 )
                 });
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 75_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 75_000, "incorrect balance");
             }
 
             #[test]
@@ -507,7 +603,7 @@ macro_rules! bdk_blockchain_tests {
 
                 wallet.sync(noop_progress(), None).unwrap();
 
-                assert_eq!(wallet.get_balance().unwrap(), 50_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance");
                 assert_eq!(wallet.list_transactions(false).unwrap().len(), 1, "incorrect number of txs");
                 assert_eq!(wallet.list_unspent().unwrap().len(), 1, "incorrect unspent");
 
@@ -521,7 +617,7 @@ macro_rules! bdk_blockchain_tests {
 
                 wallet.sync(noop_progress(), None).unwrap();
 
-                assert_eq!(wallet.get_balance().unwrap(), 50_000, "incorrect balance after bump");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance after bump");
                 assert_eq!(wallet.list_transactions(false).unwrap().len(), 1, "incorrect number of txs after bump");
                 assert_eq!(wallet.list_unspent().unwrap().len(), 1, "incorrect unspent after bump");
 
@@ -532,6 +628,63 @@ macro_rules! bdk_blockchain_tests {
                 assert_eq!(list_tx_item.confirmation_time, None, "incorrect height after bump");
             }
 
+            #[test]
+            #[serial]
+            fn test_sync_with_listener_events() {
+                let (wallet, descriptors, mut test_client) = init_single_sig();
+                let (sender, receiver) = std::sync::mpsc::channel();
+
+                let txid = test_client.receive(testutils! {
+                    @tx ( (@external descriptors, 0) => 50_000 ) ( @replaceable true )
+                });
+
+                wallet.sync_with_listener(noop_progress(), None, sender.clone()).unwrap();
+
+                let events = receiver.try_recv().unwrap();
+                assert_eq!(events.len(), 2, "incorrect number of events");
+                assert!(matches!(&events[0], WalletEvent::NewTransaction(details) if details.txid == txid), "missing new tx event");
+                assert!(matches!(&events[1], WalletEvent::UtxoCreated(utxo) if utxo.outpoint.txid == txid), "missing new utxo event");
+
+                let new_txid = test_client.bump_fee(&txid);
+
+                wallet.sync_with_listener(noop_progress(), None, sender.clone()).unwrap();
+
+                let events = receiver.try_recv().unwrap();
+                assert!(events.iter().any(|e| matches!(e, WalletEvent::NewTransaction(details) if details.txid == new_txid)), "missing replacement tx event");
+                assert!(events.iter().any(|e| matches!(e, WalletEvent::TransactionRemoved { details, replaced_by } if details.txid == txid && *replaced_by == Some(new_txid))), "missing replaced tx event");
+
+                test_client.generate(1, None);
+
+                wallet.sync_with_listener(noop_progress(), None, sender).unwrap();
+
+                let events = receiver.try_recv().unwrap();
+                assert_eq!(events.len(), 1, "incorrect number of events after confirmation");
+                assert!(matches!(&events[0], WalletEvent::TransactionConfirmed(details) if details.txid == new_txid), "missing confirmed tx event");
+            }
+
+            #[test]
+            #[serial]
+            fn test_refresh_with_listener_events() {
+                let (wallet, descriptors, mut test_client) = init_single_sig();
+                let (sender, receiver) = std::sync::mpsc::channel();
+
+                wallet.sync(noop_progress(), None).unwrap();
+
+                let txid = test_client.receive(testutils! {
+                    @tx ( (@external descriptors, 0) => 50_000 )
+                });
+
+                wallet.refresh_with_listener(noop_progress(), None, sender.clone()).unwrap();
+
+                let events = receiver.try_recv().unwrap();
+                assert_eq!(events.len(), 2, "incorrect number of events");
+                assert!(matches!(&events[0], WalletEvent::NewTransaction(details) if details.txid == txid), "missing new tx event");
+                assert!(matches!(&events[1], WalletEvent::UtxoCreated(utxo) if utxo.outpoint.txid == txid), "missing new utxo event");
+
+                wallet.refresh_with_listener(noop_progress(), None, sender).unwrap();
+                assert!(receiver.try_recv().unwrap().is_empty(), "unexpected events");
+            }
+
             // FIXME: I would like this to be cfg_attr(not(feature = "test-esplora"), ignore) but it
             // doesn't work for some reason.
             #[cfg(not(feature = "esplora"))]
@@ -546,7 +699,7 @@ macro_rules! bdk_blockchain_tests {
 
                 wallet.sync(noop_progress(), None).unwrap();
 
-                assert_eq!(wallet.get_balance().unwrap(), 50_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance");
                 assert_eq!(wallet.list_transactions(false).unwrap().len(), 1, "incorrect number of txs");
                 assert_eq!(wallet.list_unspent().unwrap().len(), 1, "incorrect number of unspents");
 
@@ -559,7 +712,7 @@ macro_rules! bdk_blockchain_tests {
 
                 wallet.sync(noop_progress(), None).unwrap();
 
-                assert_eq!(wallet.get_balance().unwrap(), 50_000, "incorrect balance after invalidate");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance after invalidate");
 
                 let list_tx_item = &wallet.list_transactions(false).unwrap()[0];
                 assert_eq!(list_tx_item.txid, txid, "incorrect txid after invalidate");
@@ -578,7 +731,7 @@ macro_rules! bdk_blockchain_tests {
                 });
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 50_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance");
 
                 let mut builder = wallet.build_tx();
                 builder.add_recipient(node_addr.script_pubkey(), 25_000);
@@ -590,7 +743,7 @@ macro_rules! bdk_blockchain_tests {
                 wallet.broadcast(tx).unwrap();
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), details.received, "incorrect balance after send");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), details.received, "incorrect balance after send");
 
                 assert_eq!(wallet.list_transactions(false).unwrap().len(), 2, "incorrect number of txs");
                 assert_eq!(wallet.list_unspent().unwrap().len(), 1, "incorrect number of unspents");
@@ -608,7 +761,7 @@ macro_rules! bdk_blockchain_tests {
                 });
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 50_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance");
 
                 let tx_map = wallet.list_transactions(false).unwrap().into_iter().map(|tx| (tx.txid, tx)).collect::<std::collections::HashMap<_, _>>();
                 let details = tx_map.get(&received_txid).unwrap();
@@ -634,7 +787,7 @@ macro_rules! bdk_blockchain_tests {
                 });
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 50_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance");
 
                 let mut builder = wallet.build_tx();
                 builder.add_recipient(node_addr.script_pubkey(), 25_000);
@@ -645,7 +798,7 @@ macro_rules! bdk_blockchain_tests {
                 let sent_txid = wallet.broadcast(psbt.extract_tx()).unwrap();
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), details.received, "incorrect balance after receive");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), details.received, "incorrect balance after receive");
 
                 // empty wallet
                 let wallet = get_wallet_from_descriptors(&descriptors);
@@ -677,7 +830,7 @@ macro_rules! bdk_blockchain_tests {
                 });
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 50_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance");
 
                 let mut total_sent = 0;
                 for _ in 0..5 {
@@ -694,7 +847,7 @@ macro_rules! bdk_blockchain_tests {
                 }
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 50_000 - total_sent, "incorrect balance after chain");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000 - total_sent, "incorrect balance after chain");
 
                 // empty wallet
 
@@ -704,7 +857,7 @@ macro_rules! bdk_blockchain_tests {
                 test_client.generate(1, Some(node_addr));
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 50_000 - total_sent, "incorrect balance empty wallet");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000 - total_sent, "incorrect balance empty wallet");
 
             }
 
@@ -719,7 +872,7 @@ macro_rules! bdk_blockchain_tests {
                 });
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 50_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance");
 
                 let mut builder = wallet.build_tx();
                 builder.add_recipient(node_addr.script_pubkey().clone(), 5_000).enable_rbf();
@@ -728,8 +881,8 @@ macro_rules! bdk_blockchain_tests {
                 assert!(finalized, "Cannot finalize transaction");
                 wallet.broadcast(psbt.extract_tx()).unwrap();
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 50_000 - details.fee.unwrap_or(0) - 5_000, "incorrect balance from fees");
-                assert_eq!(wallet.get_balance().unwrap(), details.received, "incorrect balance from received");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000 - details.fee.unwrap_or(0) - 5_000, "incorrect balance from fees");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), details.received, "incorrect balance from received");
 
                 let mut builder = wallet.build_fee_bump(details.txid).unwrap();
                 builder.fee_rate(FeeRate::from_sat_per_vb(2.1));
@@ -738,8 +891,8 @@ macro_rules! bdk_blockchain_tests {
                 assert!(finalized, "Cannot finalize transaction");
                 wallet.broadcast(new_psbt.extract_tx()).unwrap();
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 50_000 - new_details.fee.unwrap_or(0) - 5_000, "incorrect balance from fees after bump");
-                assert_eq!(wallet.get_balance().unwrap(), new_details.received, "incorrect balance from received after bump");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000 - new_details.fee.unwrap_or(0) - 5_000, "incorrect balance from fees after bump");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), new_details.received, "incorrect balance from received after bump");
 
                 assert!(new_details.fee.unwrap_or(0) > details.fee.unwrap_or(0), "incorrect fees");
             }
@@ -755,7 +908,7 @@ macro_rules! bdk_blockchain_tests {
                 });
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 50_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 50_000, "incorrect balance");
 
                 let mut builder = wallet.build_tx();
                 builder.add_recipient(node_addr.script_pubkey().clone(), 49_000).enable_rbf();
@@ -764,8 +917,8 @@ macro_rules! bdk_blockchain_tests {
                 assert!(finalized, "Cannot finalize transaction");
                 wallet.broadcast(psbt.extract_tx()).unwrap();
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 1_000 - details.fee.unwrap_or(0), "incorrect balance after send");
-                assert_eq!(wallet.get_balance().unwrap(), details.received, "incorrect received after send");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 1_000 - details.fee.unwrap_or(0), "incorrect balance after send");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), details.received, "incorrect received after send");
 
                 let mut builder = wallet.build_fee_bump(details.txid).unwrap();
                 builder.fee_rate(FeeRate::from_sat_per_vb(5.0));
@@ -774,7 +927,7 @@ macro_rules! bdk_blockchain_tests {
                 assert!(finalized, "Cannot finalize transaction");
                 wallet.broadcast(new_psbt.extract_tx()).unwrap();
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 0, "incorrect balance after change removal");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 0, "incorrect balance after change removal");
                 assert_eq!(new_details.received, 0, "incorrect received after change removal");
 
                 assert!(new_details.fee.unwrap_or(0) > details.fee.unwrap_or(0), "incorrect fees");
@@ -791,7 +944,7 @@ macro_rules! bdk_blockchain_tests {
                 });
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 75_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 75_000, "incorrect balance");
 
                 let mut builder = wallet.build_tx();
                 builder.add_recipient(node_addr.script_pubkey().clone(), 49_000).enable_rbf();
@@ -800,7 +953,7 @@ macro_rules! bdk_blockchain_tests {
                 assert!(finalized, "Cannot finalize transaction");
                 wallet.broadcast(psbt.extract_tx()).unwrap();
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 26_000 - details.fee.unwrap_or(0), "incorrect balance after send");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 26_000 - details.fee.unwrap_or(0), "incorrect balance after send");
                 assert_eq!(details.received, 1_000 - details.fee.unwrap_or(0), "incorrect received after send");
 
                 let mut builder = wallet.build_fee_bump(details.txid).unwrap();
@@ -811,7 +964,7 @@ macro_rules! bdk_blockchain_tests {
                 wallet.broadcast(new_psbt.extract_tx()).unwrap();
                 wallet.sync(noop_progress(), None).unwrap();
                 assert_eq!(new_details.sent, 75_000, "incorrect sent");
-                assert_eq!(wallet.get_balance().unwrap(), new_details.received, "incorrect balance after add input");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), new_details.received, "incorrect balance after add input");
             }
 
             #[test]
@@ -825,7 +978,7 @@ macro_rules! bdk_blockchain_tests {
                 });
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 75_000, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 75_000, "incorrect balance");
 
                 let mut builder = wallet.build_tx();
                 builder.add_recipient(node_addr.script_pubkey().clone(), 49_000).enable_rbf();
@@ -834,7 +987,7 @@ macro_rules! bdk_blockchain_tests {
                 assert!(finalized, "Cannot finalize transaction");
                 wallet.broadcast(psbt.extract_tx()).unwrap();
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 26_000 - details.fee.unwrap_or(0), "incorrect balance after send");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 26_000 - details.fee.unwrap_or(0), "incorrect balance after send");
                 assert_eq!(details.received, 1_000 - details.fee.unwrap_or(0), "incorrect received after send");
 
                 let mut builder = wallet.build_fee_bump(details.txid).unwrap();
@@ -847,7 +1000,7 @@ macro_rules! bdk_blockchain_tests {
                 wallet.broadcast(new_psbt.extract_tx()).unwrap();
                 wallet.sync(noop_progress(), None).unwrap();
                 assert_eq!(new_details.sent, 75_000, "incorrect sent");
-                assert_eq!(wallet.get_balance().unwrap(), 0, "incorrect balance after add input");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 0, "incorrect balance after add input");
                 assert_eq!(new_details.received, 0, "incorrect received after add input");
             }
 
@@ -859,7 +1012,7 @@ macro_rules! bdk_blockchain_tests {
                 let wallet_addr = wallet.get_address($crate::wallet::AddressIndex::New).unwrap().address;
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert_eq!(wallet.get_balance().unwrap(), 0, "incorrect balance");
+                assert_eq!(wallet.get_balance().unwrap().get_total(), 0, "incorrect balance");
 
                 test_client.generate(1, Some(wallet_addr));
 
@@ -872,7 +1025,35 @@ macro_rules! bdk_blockchain_tests {
 
 
                 wallet.sync(noop_progress(), None).unwrap();
-                assert!(wallet.get_balance().unwrap() > 0, "incorrect balance after receiving coinbase");
+                assert!(wallet.get_balance().unwrap().get_total() > 0, "incorrect balance after receiving coinbase");
+            }
+
+            #[test]
+            #[serial]
+            fn test_estimate_fees_same_as_estimate_fee() {
+                use $crate::blockchain::Blockchain;
+                use $crate::testutils::blockchain_tests::RpcApi;
+
+                let (_, _, mut test_client) = init_single_sig();
+                let node_addr = test_client.get_node_address(None);
+
+                // create enough txs for core to give some fee estimation
+                for _ in 0..15 {
+                    for _ in 0..2 {
+                        test_client.send_to_address(&node_addr, $crate::bitcoin::Amount::from_sat(100_000), None, None, None, None, None, None).unwrap();
+                    }
+                    test_client.generate(1, Some(node_addr.clone()));
+                }
+
+                let blockchain = get_blockchain();
+                let targets = [1, 6, 144];
+                let estimates = blockchain.estimate_fees(&targets).unwrap();
+                let expected = targets
+                    .iter()
+                    .map(|target| blockchain.estimate_fee(*target).unwrap())
+                    .collect::<Vec<_>>();
+                assert_eq!(estimates, expected, "the estimates don't match the targets");
+                assert!(blockchain.estimate_fees(&[]).unwrap().is_empty());
             }
         }
     }
@@ -12,7 +12,9 @@
 use std::convert::AsRef;
 
 use bitcoin::blockdata::transaction::{OutPoint, Transaction, TxOut};
-use bitcoin::{hash_types::Txid, util::psbt};
+use bitcoin::hashes::sha256;
+use bitcoin::util::bip32::ExtendedPubKey;
+use bitcoin::{hash_types::Txid, util::psbt, PublicKey, Script};
 
 use serde::{Deserialize, Serialize};
 
@@ -88,6 +90,21 @@ pub struct LocalUtxo {
     pub txout: TxOut,
     /// Type of keychain
     pub keychain: KeychainKind,
+    /// Label attached to the outpoint, if any
+    ///
+    /// Labels are stored separately in the database, this field is only populated when the UTXO
+    /// is returned by a [`Wallet`](crate::Wallet) method.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Whether the UTXO has been frozen with [`Wallet::freeze_utxo`](crate::Wallet::freeze_utxo)
+    ///
+    /// Like the label, this flag is stored separately in the database and only populated when
+    /// the UTXO is returned by a [`Wallet`](crate::Wallet) method.
+    #[serde(default)]
+    pub is_frozen: bool,
+    /// Whether the output was created by a coinbase transaction
+    #[serde(default)]
+    pub is_coinbase: bool,
 }
 
 /// A [`Utxo`] with its `satisfaction_weight`.
@@ -174,6 +191,12 @@ pub struct TransactionDetails {
     /// The check is only perfomed when the `verify` feature is enabled.
     #[serde(default = "bool::default")] // default to `false` if not specified
     pub verified: bool,
+    /// Label attached to the transaction, if any
+    ///
+    /// Labels are stored separately in the database, this field is only populated when the
+    /// transaction is returned by a [`Wallet`](crate::Wallet) method.
+    #[serde(default)]
+    pub label: Option<String>,
 }
 
 /// Block height and timestamp of the block containing the confirmed transaction
@@ -195,6 +218,202 @@ impl ConfirmationTime {
     }
 }
 
+/// Reference to an object of the wallet that can be labeled
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LabelRef {
+    /// A transaction, identified by its [`Txid`]
+    Transaction(Txid),
+    /// A transaction output, identified by its [`OutPoint`]
+    Output(OutPoint),
+    /// A script_pubkey, usually one of the wallet's addresses
+    Script(Script),
+    /// A transaction input, identified by the [`Txid`] of the spending transaction and its index
+    Input {
+        /// Txid of the transaction that contains the input
+        txid: Txid,
+        /// Index of the input in the transaction
+        vin: u32,
+    },
+    /// A public key derived from one of the wallet's descriptors
+    PublicKey(PublicKey),
+    /// An extended public key that appears in one of the wallet's descriptors
+    ExtendedPubKey(ExtendedPubKey),
+}
+
+/// Status of the history of a script_pubkey, as reported by an Electrum server
+///
+/// Storing it lets the sync skip the script_pubkeys whose history hasn't changed since the last
+/// time it was downloaded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ScriptStatus {
+    /// Hash of the history, computed by the server as defined by the Electrum protocol
+    pub status: sha256::Hash,
+    /// Transactions in the history, along with their confirmation height
+    pub history: Vec<(Txid, Option<u32>)>,
+}
+
+/// Earliest point in the blockchain where the transactions of a wallet can be found
+///
+/// It's stored in the wallet's database and used by the blockchain backends to skip the blocks,
+/// filters or history that are older than the wallet.
+///
+/// The Electrum and Esplora APIs can't filter the history of a script_pubkey by height, so
+/// `ElectrumBlockchain` and `EsploraBlockchain` still download the whole history of every
+/// script_pubkey: only the transactions and the block headers older than the birthday are
+/// skipped.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Birthday {
+    /// Height of the first block that may contain a transaction of the wallet
+    Height(u32),
+    /// Unix timestamp of the creation of the wallet
+    ///
+    /// The backends look for the height of the first block mined around that time when they sync,
+    /// and store it in the database in place of the timestamp.
+    Timestamp(u64),
+}
+
+impl Birthday {
+    /// Return the height of the birthday, looking for the first block mined after the timestamp
+    /// in the chain that ends at `tip_height` if needed
+    ///
+    /// `get_time` is called with a block height and should return the timestamp of that block.
+    #[cfg(any(feature = "rpc", feature = "compact_filters"))]
+    pub(crate) fn find_height<F, E>(&self, tip_height: u32, mut get_time: F) -> Result<u32, E>
+    where
+        F: FnMut(u32) -> Result<u64, E>,
+    {
+        let mut search = BirthdaySearch::new(self, tip_height);
+        while let Some(height) = search.next_height() {
+            search.update(get_time(height)?);
+        }
+
+        Ok(search.height())
+    }
+}
+
+/// Only compile the items when at least one blockchain backend is enabled
+macro_rules! with_blockchain_backend {
+    ($($item:item)*) => {
+        $(
+            #[cfg(any(
+                feature = "electrum",
+                feature = "esplora",
+                feature = "esplora-blocking",
+                feature = "rpc",
+                feature = "compact_filters"
+            ))]
+            $item
+        )*
+    };
+}
+
+with_blockchain_backend! {
+    /// Binary search of the height of a [`Birthday`], in a chain that ends at a given tip
+    ///
+    /// The search is driven by the caller, which looks up the timestamp of the block at
+    /// [`BirthdaySearch::next_height`] and reports it with [`BirthdaySearch::update`], so that the
+    /// lookup can also be `async`. See [`Birthday::find_height`] for the blocking version.
+    #[derive(Debug)]
+    pub(crate) struct BirthdaySearch {
+        timestamp: u64,
+        low: u32,
+        high: u32,
+    }
+
+    impl BirthdaySearch {
+        /// Margin applied to [`Birthday::Timestamp`], since the timestamp of a block can be off by up
+        /// to two hours
+        pub(crate) const TIMESTAMP_WINDOW: u64 = 2 * 60 * 60;
+
+        pub(crate) fn new(birthday: &Birthday, tip_height: u32) -> Self {
+            match birthday {
+                Birthday::Height(height) => BirthdaySearch {
+                    timestamp: 0,
+                    low: *height,
+                    high: *height,
+                },
+                Birthday::Timestamp(timestamp) => BirthdaySearch {
+                    timestamp: timestamp.saturating_sub(Self::TIMESTAMP_WINDOW),
+                    low: 0,
+                    high: tip_height,
+                },
+            }
+        }
+
+        /// Return the height of the block whose timestamp is needed next, or `None` once the search
+        /// is over
+        pub(crate) fn next_height(&self) -> Option<u32> {
+            if self.low < self.high {
+                Some(self.low + (self.high - self.low) / 2)
+            } else {
+                None
+            }
+        }
+
+        /// Report the timestamp of the block at the height returned by
+        /// [`BirthdaySearch::next_height`]
+        pub(crate) fn update(&mut self, time: u64) {
+            let mid = match self.next_height() {
+                Some(mid) => mid,
+                None => return,
+            };
+
+            if time < self.timestamp {
+                self.low = mid + 1;
+            } else {
+                self.high = mid;
+            }
+        }
+
+        /// Return the height of the birthday, only meaningful once the search is over
+        pub(crate) fn height(&self) -> u32 {
+            self.low
+        }
+    }
+}
+
+/// Balance of a wallet, split in different categories depending on how safe it is to spend the
+/// funds
+///
+/// Each UTXO is only counted in one category: frozen UTXOs are only counted in
+/// [`Balance::frozen`], regardless of their confirmation status.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Balance {
+    /// Confirmed and spendable funds
+    pub confirmed: u64,
+    /// Unconfirmed outputs of transactions created by this wallet, usually change
+    pub trusted_pending: u64,
+    /// Unconfirmed outputs received from others
+    pub untrusted_pending: u64,
+    /// Coinbase outputs that haven't reached maturity yet
+    pub immature: u64,
+    /// Outputs frozen with [`Wallet::freeze_utxo`](crate::Wallet::freeze_utxo)
+    pub frozen: u64,
+}
+
+impl Balance {
+    /// Funds that can be safely spent by the wallet, meaning the confirmed balance plus the
+    /// trusted pending one
+    pub fn get_spendable(&self) -> u64 {
+        self.confirmed + self.trusted_pending
+    }
+
+    /// Sum of all the categories
+    pub fn get_total(&self) -> u64 {
+        self.confirmed + self.trusted_pending + self.untrusted_pending + self.immature + self.frozen
+    }
+}
+
+impl std::fmt::Display for Balance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{{ confirmed: {}, trusted_pending: {}, untrusted_pending: {}, immature: {}, frozen: {} }}",
+            self.confirmed, self.trusted_pending, self.untrusted_pending, self.immature, self.frozen
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -204,4 +423,43 @@ mod tests {
         const _MY_RATE: FeeRate = FeeRate::from_sat_per_vb(10.0);
         const _MIN_RELAY: FeeRate = FeeRate::default_min_relay_fee();
     }
+
+    with_blockchain_backend! {
+        #[test]
+        fn test_birthday_search() {
+            let mut search = BirthdaySearch::new(&Birthday::Height(42), 1000);
+            assert_eq!(search.next_height(), None);
+            assert_eq!(search.height(), 42);
+
+            // one block every 600 seconds, starting from 1_000_000
+            let timestamp = 1_000_000 + 123 * 600 + BirthdaySearch::TIMESTAMP_WINDOW;
+            search = BirthdaySearch::new(&Birthday::Timestamp(timestamp), 1000);
+            let mut lookups = 0;
+            while let Some(height) = search.next_height() {
+                search.update(1_000_000 + height as u64 * 600);
+                lookups += 1;
+            }
+            assert_eq!(search.height(), 123);
+            assert!(lookups <= 10);
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "rpc", feature = "compact_filters"))]
+    fn test_birthday_find_height() {
+        // one block every 600 seconds, starting from 1_000_000
+        let get_time = |height: u32| Ok::<_, ()>(1_000_000 + height as u64 * 600);
+
+        assert_eq!(Birthday::Height(42).find_height(1000, get_time), Ok(42));
+        assert_eq!(
+            Birthday::Timestamp(1_000_000 + 500 * 600 + BirthdaySearch::TIMESTAMP_WINDOW)
+                .find_height(1000, get_time),
+            Ok(500)
+        );
+        assert_eq!(Birthday::Timestamp(0).find_height(1000, get_time), Ok(0));
+        assert_eq!(
+            Birthday::Timestamp(u64::MAX).find_height(1000, get_time),
+            Ok(1000)
+        );
+    }
 }
@@ -563,6 +563,9 @@ mod test {
                         script_pubkey: Script::new(),
                     },
                     keychain: KeychainKind::External,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase: false,
                 }),
             },
             WeightedUtxo {
@@ -577,6 +580,9 @@ mod test {
                         script_pubkey: Script::new(),
                     },
                     keychain: KeychainKind::External,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase: false,
                 }),
             },
             WeightedUtxo {
@@ -591,6 +597,9 @@ mod test {
                         script_pubkey: Script::new(),
                     },
                     keychain: KeychainKind::Internal,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase: false,
                 }),
             },
         ]
@@ -611,6 +620,9 @@ mod test {
                         script_pubkey: Script::new(),
                     },
                     keychain: KeychainKind::External,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase: false,
                 }),
             });
         }
@@ -630,6 +642,9 @@ mod test {
                     script_pubkey: Script::new(),
                 },
                 keychain: KeychainKind::External,
+                label: None,
+                is_frozen: false,
+                is_coinbase: false,
             }),
         };
         vec![utxo; utxos_number]
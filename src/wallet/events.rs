@@ -0,0 +1,677 @@
+// Bitcoin Dev Kit
+// Written in 2021 by Alekos Filini <alekos.filini@gmail.com>
+//
+// Copyright (c) 2020-2021 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Wallet events
+//!
+//! This module defines the [`WalletEvent`]s that describe how the content of the wallet changed
+//! during a sync, and the [`SyncListener`] trait used to receive them from
+//! [`Wallet::sync_with_listener`](crate::Wallet::sync_with_listener) and
+//! [`Wallet::refresh_with_listener`](crate::Wallet::refresh_with_listener).
+//!
+//! The events are computed from the transactions and UTXOs written to the database by the
+//! blockchain backend during the sync, the rest of the wallet is never read.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # use bdk::blockchain::{noop_progress, ElectrumBlockchain};
+//! # use bdk::database::MemoryDatabase;
+//! # use bdk::electrum_client::Client;
+//! # use bdk::wallet::events::*;
+//! # use bdk::*;
+//! # use bitcoin::Network;
+//! let client = Client::new("ssl://electrum.blockstream.info:60002")?;
+//! let wallet = Wallet::new(
+//!     "wpkh(tpubD6NzVbkrYhZ4X2yy78HWrr1M9NT8dKeWfzNiQqDdMqqa9UmmGztGGz6TaLFGsLfdft5iu32gxq1T4eMNxExNNWzVCpf9Y6JZi5TnqoC9wJq/*)",
+//!     None,
+//!     Network::Testnet,
+//!     MemoryDatabase::default(),
+//!     ElectrumBlockchain::from(client),
+//! )?;
+//!
+//! let (sender, receiver) = std::sync::mpsc::channel();
+//! wallet.sync_with_listener(noop_progress(), None, sender)?;
+//!
+//! for event in receiver.try_recv().unwrap() {
+//!     if let WalletEvent::NewTransaction(details) = event {
+//!         println!("Received a new transaction: {}", details.txid);
+//!     }
+//! }
+//! # Ok::<(), bdk::Error>(())
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::Sender;
+
+use bitcoin::{BlockHash, OutPoint, Script, Transaction, Txid};
+
+use crate::database::{BatchDatabase, BatchOperations, Database};
+use crate::error::Error;
+use crate::types::{
+    Birthday, ConfirmationTime, KeychainKind, LabelRef, LocalUtxo, ScriptStatus, TransactionDetails,
+};
+
+/// Change to the content of the wallet detected during a sync
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalletEvent {
+    /// A transaction has been seen for the first time
+    NewTransaction(TransactionDetails),
+    /// A transaction that was previously unconfirmed has been confirmed
+    TransactionConfirmed(TransactionDetails),
+    /// A confirmed transaction has been moved to a different block or back to the mempool by a
+    /// reorg
+    TransactionReorged {
+        /// The transaction, with its new confirmation status
+        details: TransactionDetails,
+        /// Confirmation of the transaction before the reorg
+        previous_confirmation: ConfirmationTime,
+    },
+    /// A transaction has been removed from the wallet, usually because it was replaced or it was
+    /// dropped from the mempool
+    TransactionRemoved {
+        /// The transaction that has been removed
+        details: TransactionDetails,
+        /// Txid of the new transaction that spends at least one of the same inputs, if any
+        replaced_by: Option<Txid>,
+    },
+    /// A new UTXO has been created
+    UtxoCreated(LocalUtxo),
+    /// A UTXO is not available anymore, usually because it has been spent
+    UtxoSpent(LocalUtxo),
+}
+
+/// Trait for types that can receive the [`WalletEvent`]s generated by
+/// [`Wallet::sync_with_listener`](crate::Wallet::sync_with_listener) and
+/// [`Wallet::refresh_with_listener`](crate::Wallet::refresh_with_listener)
+pub trait SyncListener {
+    /// Process the list of events generated by a sync
+    ///
+    /// This method is called once at the end of every sync, even if the list of events is empty.
+    fn on_sync(&self, events: Vec<WalletEvent>) -> Result<(), Error>;
+}
+
+impl SyncListener for Sender<Vec<WalletEvent>> {
+    fn on_sync(&self, events: Vec<WalletEvent>) -> Result<(), Error> {
+        self.send(events)
+            .map_err(|_| Error::Generic("Unable to send the sync events".to_string()))
+    }
+}
+
+/// Snapshot of some transactions and UTXOs of a wallet, used to compute the events generated by a
+/// sync
+pub(crate) struct WalletSnapshot {
+    txs: BTreeMap<Txid, TransactionDetails>,
+    utxos: BTreeMap<OutPoint, LocalUtxo>,
+}
+
+impl WalletSnapshot {
+    pub(crate) fn new(txs: Vec<TransactionDetails>, utxos: Vec<LocalUtxo>) -> Self {
+        WalletSnapshot {
+            txs: txs.into_iter().map(|tx| (tx.txid, tx)).collect(),
+            utxos: utxos.into_iter().map(|u| (u.outpoint, u)).collect(),
+        }
+    }
+
+    /// Compute the list of events that turn `self` into `after`
+    ///
+    /// Transaction events come first, followed by the UTXO ones. Within each category the events
+    /// are sorted by txid.
+    pub(crate) fn diff(self, after: &WalletSnapshot) -> Vec<WalletEvent> {
+        let mut events = Vec::new();
+
+        // outpoints spent by the transactions that are new in `after`, used to detect replacements
+        let new_spends: HashMap<OutPoint, Txid> = after
+            .txs
+            .values()
+            .filter(|tx| !self.txs.contains_key(&tx.txid))
+            .filter_map(|tx| tx.transaction.as_ref())
+            .flat_map(|tx| {
+                let txid = tx.txid();
+                tx.input.iter().map(move |i| (i.previous_output, txid))
+            })
+            .collect();
+
+        for (txid, details) in &after.txs {
+            match self.txs.get(txid) {
+                None => events.push(WalletEvent::NewTransaction(details.clone())),
+                Some(prev) if prev.confirmation_time == details.confirmation_time => {}
+                Some(prev) => match &prev.confirmation_time {
+                    None => events.push(WalletEvent::TransactionConfirmed(details.clone())),
+                    Some(previous_confirmation) => events.push(WalletEvent::TransactionReorged {
+                        details: details.clone(),
+                        previous_confirmation: previous_confirmation.clone(),
+                    }),
+                },
+            }
+        }
+
+        for (txid, details) in self.txs {
+            if after.txs.contains_key(&txid) {
+                continue;
+            }
+
+            let replaced_by = details.transaction.as_ref().and_then(|tx| {
+                tx.input
+                    .iter()
+                    .find_map(|i| new_spends.get(&i.previous_output).cloned())
+            });
+            events.push(WalletEvent::TransactionRemoved {
+                details,
+                replaced_by,
+            });
+        }
+
+        for (outpoint, utxo) in &after.utxos {
+            if !self.utxos.contains_key(outpoint) {
+                events.push(WalletEvent::UtxoCreated(utxo.clone()));
+            }
+        }
+        for (outpoint, utxo) in self.utxos {
+            if !after.utxos.contains_key(&outpoint) {
+                events.push(WalletEvent::UtxoSpent(utxo));
+            }
+        }
+
+        events
+    }
+}
+
+/// State of the transactions and UTXOs written during a sync, before they were first written
+#[derive(Debug, Default)]
+pub(crate) struct SyncRecord {
+    txs: BTreeMap<Txid, Option<TransactionDetails>>,
+    utxos: BTreeMap<OutPoint, Option<LocalUtxo>>,
+}
+
+impl SyncRecord {
+    /// Compute the events generated by the writes recorded so far, comparing the previous state
+    /// of the transactions and UTXOs with the current one in `database`
+    pub(crate) fn into_events<D: Database>(self, database: &D) -> Result<Vec<WalletEvent>, Error> {
+        let mut txs_before = vec![];
+        let mut txs_after = vec![];
+        for (txid, before) in self.txs {
+            txs_before.extend(before);
+            txs_after.extend(database.get_tx(&txid, true)?);
+        }
+
+        let mut utxos_before = vec![];
+        let mut utxos_after = vec![];
+        for (outpoint, before) in self.utxos {
+            utxos_before.extend(before);
+            utxos_after.extend(database.get_utxo(&outpoint)?);
+        }
+
+        let with_labels = |txs: Vec<TransactionDetails>, utxos: Vec<LocalUtxo>| {
+            let txs = txs
+                .into_iter()
+                .map(|mut details| {
+                    details.label = database.get_label(&LabelRef::Transaction(details.txid))?;
+                    Ok(details)
+                })
+                .collect::<Result<_, Error>>()?;
+            let utxos = utxos
+                .into_iter()
+                .map(|mut utxo| {
+                    utxo.label = database.get_label(&LabelRef::Output(utxo.outpoint))?;
+                    utxo.is_frozen = database.is_frozen_utxo(&utxo.outpoint)?;
+                    Ok(utxo)
+                })
+                .collect::<Result<_, Error>>()?;
+            Ok::<_, Error>(WalletSnapshot::new(txs, utxos))
+        };
+        let before = with_labels(txs_before, utxos_before)?;
+        let after = with_labels(txs_after, utxos_after)?;
+
+        Ok(before.diff(&after))
+    }
+}
+
+/// [`BatchDatabase`] that forwards everything to `inner`, optionally recording in a
+/// [`SyncRecord`] the previous state of the transactions and UTXOs that are written
+pub(crate) struct RecordingDatabase<'a, D> {
+    inner: &'a mut D,
+    record: Option<&'a mut SyncRecord>,
+}
+
+impl<'a, D: Database> RecordingDatabase<'a, D> {
+    pub(crate) fn new(inner: &'a mut D, record: Option<&'a mut SyncRecord>) -> Self {
+        RecordingDatabase { inner, record }
+    }
+
+    fn record_tx(&mut self, txid: &Txid) -> Result<(), Error> {
+        if let Some(record) = self.record.as_mut() {
+            if !record.txs.contains_key(txid) {
+                record.txs.insert(*txid, self.inner.get_tx(txid, true)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record_utxo(&mut self, outpoint: &OutPoint) -> Result<(), Error> {
+        if let Some(record) = self.record.as_mut() {
+            if !record.utxos.contains_key(outpoint) {
+                record
+                    .utxos
+                    .insert(*outpoint, self.inner.get_utxo(outpoint)?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Batch of a [`RecordingDatabase`], which keeps track of the keys written until it's committed
+pub(crate) struct RecordingBatch<B> {
+    inner: B,
+    txids: Vec<Txid>,
+    outpoints: Vec<OutPoint>,
+}
+
+impl<B> RecordingBatch<B> {
+    fn record_tx(&mut self, txid: &Txid) -> Result<(), Error> {
+        self.txids.push(*txid);
+        Ok(())
+    }
+
+    fn record_utxo(&mut self, outpoint: &OutPoint) -> Result<(), Error> {
+        self.outpoints.push(*outpoint);
+        Ok(())
+    }
+}
+
+macro_rules! impl_recording_batch_operations {
+    () => {
+        fn set_script_pubkey(
+            &mut self,
+            script: &Script,
+            keychain: KeychainKind,
+            child: u32,
+        ) -> Result<(), Error> {
+            self.inner.set_script_pubkey(script, keychain, child)
+        }
+        fn set_utxo(&mut self, utxo: &LocalUtxo) -> Result<(), Error> {
+            self.record_utxo(&utxo.outpoint)?;
+            self.inner.set_utxo(utxo)
+        }
+        fn set_raw_tx(&mut self, transaction: &Transaction) -> Result<(), Error> {
+            self.inner.set_raw_tx(transaction)
+        }
+        fn set_tx(&mut self, transaction: &TransactionDetails) -> Result<(), Error> {
+            self.record_tx(&transaction.txid)?;
+            self.inner.set_tx(transaction)
+        }
+        fn set_last_index(&mut self, keychain: KeychainKind, value: u32) -> Result<(), Error> {
+            self.inner.set_last_index(keychain, value)
+        }
+        fn set_label(&mut self, target: &LabelRef, label: &str) -> Result<(), Error> {
+            self.inner.set_label(target, label)
+        }
+        fn set_frozen_utxo(&mut self, outpoint: &OutPoint) -> Result<(), Error> {
+            self.inner.set_frozen_utxo(outpoint)
+        }
+        fn set_script_status(
+            &mut self,
+            script: &Script,
+            status: &ScriptStatus,
+        ) -> Result<(), Error> {
+            self.inner.set_script_status(script, status)
+        }
+        fn set_birthday(&mut self, birthday: &Birthday) -> Result<(), Error> {
+            self.inner.set_birthday(birthday)
+        }
+        fn set_block_hash(&mut self, height: u32, hash: &BlockHash) -> Result<(), Error> {
+            self.inner.set_block_hash(height, hash)
+        }
+        fn set_synced_tip(&mut self, height: u32, hash: &BlockHash) -> Result<(), Error> {
+            self.inner.set_synced_tip(height, hash)
+        }
+
+        fn del_script_pubkey_from_path(
+            &mut self,
+            keychain: KeychainKind,
+            child: u32,
+        ) -> Result<Option<Script>, Error> {
+            self.inner.del_script_pubkey_from_path(keychain, child)
+        }
+        fn del_path_from_script_pubkey(
+            &mut self,
+            script: &Script,
+        ) -> Result<Option<(KeychainKind, u32)>, Error> {
+            self.inner.del_path_from_script_pubkey(script)
+        }
+        fn del_utxo(&mut self, outpoint: &OutPoint) -> Result<Option<LocalUtxo>, Error> {
+            self.record_utxo(outpoint)?;
+            self.inner.del_utxo(outpoint)
+        }
+        fn del_raw_tx(&mut self, txid: &Txid) -> Result<Option<Transaction>, Error> {
+            self.inner.del_raw_tx(txid)
+        }
+        fn del_tx(
+            &mut self,
+            txid: &Txid,
+            include_raw: bool,
+        ) -> Result<Option<TransactionDetails>, Error> {
+            self.record_tx(txid)?;
+            self.inner.del_tx(txid, include_raw)
+        }
+        fn del_last_index(&mut self, keychain: KeychainKind) -> Result<Option<u32>, Error> {
+            self.inner.del_last_index(keychain)
+        }
+        fn del_label(&mut self, target: &LabelRef) -> Result<Option<String>, Error> {
+            self.inner.del_label(target)
+        }
+        fn del_frozen_utxo(&mut self, outpoint: &OutPoint) -> Result<bool, Error> {
+            self.inner.del_frozen_utxo(outpoint)
+        }
+        fn del_script_status(&mut self, script: &Script) -> Result<Option<ScriptStatus>, Error> {
+            self.inner.del_script_status(script)
+        }
+        fn del_birthday(&mut self) -> Result<Option<Birthday>, Error> {
+            self.inner.del_birthday()
+        }
+        fn del_block_hash(&mut self, height: u32) -> Result<Option<BlockHash>, Error> {
+            self.inner.del_block_hash(height)
+        }
+        fn del_synced_tip(&mut self) -> Result<Option<(u32, BlockHash)>, Error> {
+            self.inner.del_synced_tip()
+        }
+    };
+}
+
+impl<'a, D: Database> BatchOperations for RecordingDatabase<'a, D> {
+    impl_recording_batch_operations!();
+}
+
+impl<B: BatchOperations> BatchOperations for RecordingBatch<B> {
+    impl_recording_batch_operations!();
+}
+
+impl<'a, D: Database> Database for RecordingDatabase<'a, D> {
+    fn check_descriptor_checksum<Bytes: AsRef<[u8]>>(
+        &mut self,
+        keychain: KeychainKind,
+        bytes: Bytes,
+    ) -> Result<(), Error> {
+        self.inner.check_descriptor_checksum(keychain, bytes)
+    }
+
+    fn iter_script_pubkeys(&self, keychain: Option<KeychainKind>) -> Result<Vec<Script>, Error> {
+        self.inner.iter_script_pubkeys(keychain)
+    }
+    fn iter_utxos(&self) -> Result<Vec<LocalUtxo>, Error> {
+        self.inner.iter_utxos()
+    }
+    fn iter_raw_txs(&self) -> Result<Vec<Transaction>, Error> {
+        self.inner.iter_raw_txs()
+    }
+    fn iter_txs(&self, include_raw: bool) -> Result<Vec<TransactionDetails>, Error> {
+        self.inner.iter_txs(include_raw)
+    }
+    fn iter_labels(&self) -> Result<Vec<(LabelRef, String)>, Error> {
+        self.inner.iter_labels()
+    }
+    fn iter_frozen_utxos(&self) -> Result<Vec<OutPoint>, Error> {
+        self.inner.iter_frozen_utxos()
+    }
+    fn iter_block_hashes(&self) -> Result<Vec<(u32, BlockHash)>, Error> {
+        self.inner.iter_block_hashes()
+    }
+
+    fn get_script_pubkey_from_path(
+        &self,
+        keychain: KeychainKind,
+        child: u32,
+    ) -> Result<Option<Script>, Error> {
+        self.inner.get_script_pubkey_from_path(keychain, child)
+    }
+    fn get_path_from_script_pubkey(
+        &self,
+        script: &Script,
+    ) -> Result<Option<(KeychainKind, u32)>, Error> {
+        self.inner.get_path_from_script_pubkey(script)
+    }
+    fn get_utxo(&self, outpoint: &OutPoint) -> Result<Option<LocalUtxo>, Error> {
+        self.inner.get_utxo(outpoint)
+    }
+    fn get_raw_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error> {
+        self.inner.get_raw_tx(txid)
+    }
+    fn get_tx(&self, txid: &Txid, include_raw: bool) -> Result<Option<TransactionDetails>, Error> {
+        self.inner.get_tx(txid, include_raw)
+    }
+    fn get_last_index(&self, keychain: KeychainKind) -> Result<Option<u32>, Error> {
+        self.inner.get_last_index(keychain)
+    }
+    fn get_label(&self, target: &LabelRef) -> Result<Option<String>, Error> {
+        self.inner.get_label(target)
+    }
+    fn is_frozen_utxo(&self, outpoint: &OutPoint) -> Result<bool, Error> {
+        self.inner.is_frozen_utxo(outpoint)
+    }
+    fn get_script_status(&self, script: &Script) -> Result<Option<ScriptStatus>, Error> {
+        self.inner.get_script_status(script)
+    }
+    fn get_birthday(&self) -> Result<Option<Birthday>, Error> {
+        self.inner.get_birthday()
+    }
+    fn get_block_hash(&self, height: u32) -> Result<Option<BlockHash>, Error> {
+        self.inner.get_block_hash(height)
+    }
+    fn get_synced_tip(&self) -> Result<Option<(u32, BlockHash)>, Error> {
+        self.inner.get_synced_tip()
+    }
+
+    fn increment_last_index(&mut self, keychain: KeychainKind) -> Result<u32, Error> {
+        self.inner.increment_last_index(keychain)
+    }
+}
+
+impl<'a, D: BatchDatabase> BatchDatabase for RecordingDatabase<'a, D> {
+    type Batch = RecordingBatch<D::Batch>;
+
+    fn begin_batch(&self) -> Self::Batch {
+        RecordingBatch {
+            inner: self.inner.begin_batch(),
+            txids: vec![],
+            outpoints: vec![],
+        }
+    }
+
+    fn commit_batch(&mut self, batch: Self::Batch) -> Result<(), Error> {
+        // the previous state has to be read before the batch is applied
+        for txid in &batch.txids {
+            self.record_tx(txid)?;
+        }
+        for outpoint in &batch.outpoints {
+            self.record_utxo(outpoint)?;
+        }
+
+        self.inner.commit_batch(batch.inner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::{Transaction, TxIn, TxOut};
+
+    use super::*;
+    use crate::database::MemoryDatabase;
+
+    fn tx(prev: OutPoint, value: u64) -> Transaction {
+        Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: prev,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: Default::default(),
+            }],
+        }
+    }
+
+    fn details(tx: &Transaction, height: Option<u32>) -> TransactionDetails {
+        TransactionDetails {
+            transaction: Some(tx.clone()),
+            txid: tx.txid(),
+            received: tx.output[0].value,
+            sent: 0,
+            fee: None,
+            confirmation_time: height.map(|height| ConfirmationTime {
+                height,
+                timestamp: 0,
+            }),
+            verified: true,
+            label: None,
+        }
+    }
+
+    fn utxo(tx: &Transaction) -> LocalUtxo {
+        LocalUtxo {
+            outpoint: OutPoint::new(tx.txid(), 0),
+            txout: tx.output[0].clone(),
+            keychain: KeychainKind::External,
+            label: None,
+            is_frozen: false,
+            is_coinbase: false,
+        }
+    }
+
+    #[test]
+    fn test_diff_empty() {
+        let snapshot = WalletSnapshot::new(vec![], vec![]);
+        assert!(snapshot
+            .diff(&WalletSnapshot::new(vec![], vec![]))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_diff_new_and_confirmed() {
+        let tx_a = tx(OutPoint::new(Txid::default(), 0), 10_000);
+        let tx_b = tx(OutPoint::new(Txid::default(), 1), 20_000);
+
+        let before = WalletSnapshot::new(vec![details(&tx_a, None)], vec![utxo(&tx_a)]);
+        let after = WalletSnapshot::new(
+            vec![details(&tx_a, Some(100)), details(&tx_b, None)],
+            vec![utxo(&tx_a), utxo(&tx_b)],
+        );
+
+        let events = before.diff(&after);
+        assert_eq!(events.len(), 3);
+        assert!(events.contains(&WalletEvent::TransactionConfirmed(details(
+            &tx_a,
+            Some(100)
+        ))));
+        assert!(events.contains(&WalletEvent::NewTransaction(details(&tx_b, None))));
+        assert_eq!(events[2], WalletEvent::UtxoCreated(utxo(&tx_b)));
+    }
+
+    #[test]
+    fn test_diff_reorg() {
+        let tx_a = tx(OutPoint::new(Txid::default(), 0), 10_000);
+
+        let before = WalletSnapshot::new(vec![details(&tx_a, Some(100))], vec![utxo(&tx_a)]);
+        let after = WalletSnapshot::new(vec![details(&tx_a, None)], vec![utxo(&tx_a)]);
+
+        assert_eq!(
+            before.diff(&after),
+            vec![WalletEvent::TransactionReorged {
+                details: details(&tx_a, None),
+                previous_confirmation: ConfirmationTime {
+                    height: 100,
+                    timestamp: 0
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_replaced() {
+        let prev = OutPoint::new(Txid::default(), 0);
+        let original = tx(prev, 10_000);
+        let replacement = tx(prev, 9_000);
+        let dropped = tx(OutPoint::new(Txid::default(), 1), 5_000);
+
+        let before = WalletSnapshot::new(
+            vec![details(&original, None), details(&dropped, None)],
+            vec![utxo(&original), utxo(&dropped)],
+        );
+        let after =
+            WalletSnapshot::new(vec![details(&replacement, None)], vec![utxo(&replacement)]);
+
+        let events = before.diff(&after);
+        assert_eq!(events.len(), 6);
+        assert!(events.contains(&WalletEvent::TransactionRemoved {
+            details: details(&original, None),
+            replaced_by: Some(replacement.txid()),
+        }));
+        assert!(events.contains(&WalletEvent::TransactionRemoved {
+            details: details(&dropped, None),
+            replaced_by: None,
+        }));
+        assert!(events.contains(&WalletEvent::UtxoSpent(utxo(&original))));
+        assert!(events.contains(&WalletEvent::UtxoSpent(utxo(&dropped))));
+    }
+
+    #[test]
+    fn test_recording_database() {
+        let tx_a = tx(OutPoint::new(Txid::default(), 0), 10_000);
+        let tx_b = tx(OutPoint::new(tx_a.txid(), 0), 9_000);
+        let tx_c = tx(OutPoint::new(Txid::default(), 1), 5_000);
+
+        let mut database = MemoryDatabase::new();
+        database.set_tx(&details(&tx_a, None)).unwrap();
+        database.set_utxo(&utxo(&tx_a)).unwrap();
+        database.set_tx(&details(&tx_c, Some(100))).unwrap();
+        database.set_utxo(&utxo(&tx_c)).unwrap();
+
+        let mut record = SyncRecord::default();
+        {
+            let mut recording = RecordingDatabase::new(&mut database, Some(&mut record));
+            let mut batch = recording.begin_batch();
+            batch.set_tx(&details(&tx_a, Some(101))).unwrap();
+            batch.set_tx(&details(&tx_b, None)).unwrap();
+            batch.set_utxo(&utxo(&tx_b)).unwrap();
+            batch.del_utxo(&utxo(&tx_a).outpoint).unwrap();
+            recording.commit_batch(batch).unwrap();
+
+            // written twice, the state before the first write is kept
+            recording.set_tx(&details(&tx_b, Some(101))).unwrap();
+        }
+        assert_eq!(record.txs.len(), 2, "tx_c shouldn't be recorded");
+
+        let events = record.into_events(&database).unwrap();
+        assert_eq!(events.len(), 4);
+        assert!(events.contains(&WalletEvent::NewTransaction(details(&tx_b, Some(101)))));
+        assert!(events.contains(&WalletEvent::TransactionConfirmed(details(
+            &tx_a,
+            Some(101)
+        ))));
+        assert_eq!(events[2], WalletEvent::UtxoCreated(utxo(&tx_b)));
+        assert_eq!(events[3], WalletEvent::UtxoSpent(utxo(&tx_a)));
+    }
+
+    #[test]
+    fn test_recording_database_disabled() {
+        let tx_a = tx(OutPoint::new(Txid::default(), 0), 10_000);
+
+        let mut database = MemoryDatabase::new();
+        let mut recording = RecordingDatabase::new(&mut database, None);
+        let mut batch = recording.begin_batch();
+        batch.set_tx(&details(&tx_a, None)).unwrap();
+        recording.commit_batch(batch).unwrap();
+
+        assert!(database.get_tx(&tx_a.txid(), false).unwrap().is_some());
+    }
+}
@@ -30,12 +30,8 @@
 //! }"#;
 //!
 //! let import = WalletExport::from_str(import)?;
-//! let wallet = Wallet::new_offline(
-//!     &import.descriptor(),
-//!     import.change_descriptor().as_ref(),
-//!     Network::Testnet,
-//!     MemoryDatabase::default(),
-//! )?;
+//! let wallet = import.import_wallet(Network::Testnet, MemoryDatabase::default())?;
+//! assert_eq!(wallet.get_birthday()?, Some(Birthday::Height(1782088)));
 //! # Ok::<_, bdk::Error>(())
 //! ```
 //!
@@ -66,7 +62,11 @@ use serde::{Deserialize, Serialize};
 use miniscript::descriptor::{ShInner, WshInner};
 use miniscript::{Descriptor, DescriptorPublicKey, ScriptContext, Terminal};
 
+use bitcoin::Network;
+
 use crate::database::BatchDatabase;
+use crate::error::Error;
+use crate::types::Birthday;
 use crate::wallet::Wallet;
 
 /// Structure that contains the export of a wallet
@@ -185,6 +185,39 @@ impl WalletExport {
         }
     }
 
+    /// Create an offline [`Wallet`] from the export
+    ///
+    /// The [`birthday`](WalletExport::birthday) of the export is stored in the `database`, so
+    /// that the blockchain backends don't look for its transactions in older blocks.
+    pub fn import_wallet<D: BatchDatabase>(
+        &self,
+        network: Network,
+        database: D,
+    ) -> Result<Wallet<(), D>, Error> {
+        let wallet = Wallet::new_offline(
+            &self.descriptor(),
+            self.change_descriptor().as_ref(),
+            network,
+            database,
+        )?;
+        if let Some(birthday) = self.birthday() {
+            wallet.set_birthday(birthday)?;
+        }
+
+        Ok(wallet)
+    }
+
+    /// Return the [`Birthday`] of the exported wallet, or `None` if the `blockheight` field is `0`
+    ///
+    /// [`WalletExport::import_wallet`] sets it automatically, while for "online" wallets it should
+    /// be set with [`Wallet::set_birthday`] after creating them.
+    pub fn birthday(&self) -> Option<Birthday> {
+        match self.blockheight {
+            0 => None,
+            height => Some(Birthday::Height(height)),
+        }
+    }
+
     /// Return the external descriptor
     pub fn descriptor(&self) -> String {
         self.descriptor.clone()
@@ -231,6 +264,7 @@ mod test {
                 height: 5000,
             }),
             verified: true,
+            label: None,
         })
         .unwrap();
 
@@ -347,5 +381,26 @@ mod test {
         assert_eq!(export.change_descriptor(), Some(change_descriptor.into()));
         assert_eq!(export.blockheight, 5000);
         assert_eq!(export.label, "Test Label");
+        assert_eq!(export.birthday(), Some(Birthday::Height(5000)));
+    }
+
+    #[test]
+    fn test_import_wallet() {
+        let import_str = "{\"descriptor\":\"wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44\'/0\'/0\'/0/*)\",\"blockheight\":5000,\"label\":\"Test Label\"}";
+        let export = WalletExport::from_str(import_str).unwrap();
+
+        let wallet = export
+            .import_wallet(Network::Bitcoin, MemoryDatabase::default())
+            .unwrap();
+        assert_eq!(wallet.get_birthday().unwrap(), Some(Birthday::Height(5000)));
+
+        let export = WalletExport {
+            blockheight: 0,
+            ..export
+        };
+        let wallet = export
+            .import_wallet(Network::Bitcoin, MemoryDatabase::default())
+            .unwrap();
+        assert_eq!(wallet.get_birthday().unwrap(), None);
     }
 }
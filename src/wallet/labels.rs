@@ -0,0 +1,543 @@
+// Bitcoin Dev Kit
+// Written in 2021 by Alekos Filini <alekos.filini@gmail.com>
+//
+// Copyright (c) 2020-2021 Bitcoin Dev Kit Developers
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Wallet labels export
+//!
+//! This module implements the label export format defined in [BIP-329](https://github.com/bitcoin/bips/blob/master/bip-0329.mediawiki),
+//! which stores one JSON record per line.
+//!
+//! ## Examples
+//!
+//! ### Export the labels of a `Wallet`
+//!
+//! ```
+//! # use bitcoin::*;
+//! # use bdk::database::*;
+//! # use bdk::wallet::labels::*;
+//! # use bdk::wallet::AddressIndex;
+//! # use bdk::*;
+//! let wallet = Wallet::new_offline(
+//!     "wpkh([c258d2e4/84h/1h/0h]tpubDD3ynpHgJQW8VvWRzQ5WFDCrs4jqVFGHB3vLC3r49XHJSqP8bHKdK4AriuUKLccK68zfzowx7YhmDN8SiSkgCDENUFx9qVw65YyqM78vyVe/0/*)",
+//!     None,
+//!     Network::Testnet,
+//!     MemoryDatabase::default()
+//! )?;
+//! let address = wallet.get_address(AddressIndex::New)?;
+//! wallet.set_label(&LabelRef::Script(address.script_pubkey()), "donations")?;
+//!
+//! let export = LabelsExport::export_wallet(&wallet)?;
+//! println!("Exported: {}", export.to_string());
+//! # Ok::<_, bdk::Error>(())
+//! ```
+//!
+//! ### Import labels into a `Wallet`
+//!
+//! ```
+//! # use std::str::FromStr;
+//! # use bitcoin::*;
+//! # use bdk::database::*;
+//! # use bdk::wallet::labels::*;
+//! # use bdk::wallet::AddressIndex;
+//! # use bdk::*;
+//! let wallet = Wallet::new_offline(
+//!     "wpkh([c258d2e4/84h/1h/0h]tpubDD3ynpHgJQW8VvWRzQ5WFDCrs4jqVFGHB3vLC3r49XHJSqP8bHKdK4AriuUKLccK68zfzowx7YhmDN8SiSkgCDENUFx9qVw65YyqM78vyVe/0/*)",
+//!     None,
+//!     Network::Testnet,
+//!     MemoryDatabase::default()
+//! )?;
+//! # wallet.get_address(AddressIndex::New)?;
+//! let import = r#"{"type":"addr","ref":"tb1qlvuuza7al8k6shl67qv00g8va3eepy70a42nxd","label":"donations"}"#;
+//!
+//! let import = LabelsExport::from_str(import)?;
+//! let result = import.import_into(&wallet)?;
+//! println!("Imported {} labels, {} unmatched", result.imported, result.unmatched.len());
+//! # assert_eq!(result.imported, 1);
+//! # Ok::<_, bdk::Error>(())
+//! ```
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use bitcoin::util::bip32::ExtendedPubKey;
+use bitcoin::{Address, OutPoint, PublicKey, Txid};
+
+use miniscript::{DescriptorPublicKey, ForEachKey, ToPublicKey};
+
+use crate::database::{BatchDatabase, BatchOperations, DatabaseUtils};
+use crate::descriptor::derived::AsDerived;
+use crate::error::Error;
+use crate::types::{KeychainKind, LabelRef};
+use crate::wallet::Wallet;
+
+/// Type of the object referenced by a [`LabelRecord`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelType {
+    /// A transaction, referenced by its txid
+    Tx,
+    /// An address
+    Addr,
+    /// A public key, in hex
+    Pubkey,
+    /// A transaction input, referenced as `<txid>:<vin>`
+    Input,
+    /// A transaction output, referenced as `<txid>:<vout>`
+    Output,
+    /// An extended public key
+    Xpub,
+}
+
+/// A single record of a BIP-329 export
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabelRecord {
+    /// Type of the object referenced
+    #[serde(rename = "type")]
+    pub label_type: LabelType,
+    /// Reference to the object, encoded as required by its type
+    #[serde(rename = "ref")]
+    pub reference: String,
+    /// The label, if present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Optional key origin of the object
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+}
+
+/// Reason why a [`LabelRecord`] could not be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnmatchedReason {
+    /// The `ref` field could not be parsed for the record's type
+    InvalidReference,
+    /// The referenced object does not belong to the wallet
+    NotInWallet,
+    /// The record doesn't contain a label
+    MissingLabel,
+}
+
+/// Outcome of [`LabelsExport::import_into`]
+#[derive(Debug, Default)]
+pub struct LabelsImport {
+    /// Number of labels stored in the wallet's database
+    pub imported: usize,
+    /// Records that could not be imported, along with the reason
+    pub unmatched: Vec<(LabelRecord, UnmatchedReason)>,
+}
+
+/// Structure that contains the labels of a wallet in the BIP-329 format
+///
+/// For a usage example see [this module](crate::wallet::labels)'s documentation.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LabelsExport {
+    /// List of records
+    pub records: Vec<LabelRecord>,
+}
+
+impl fmt::Display for LabelsExport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, record) in self.records.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "{}",
+                serde_json::to_string(record).map_err(|_| fmt::Error)?
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for LabelsExport {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let records = s
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+
+        Ok(LabelsExport { records })
+    }
+}
+
+fn parse_index_ref(s: &str) -> Option<(Txid, u32)> {
+    let mut parts = s.splitn(2, ':');
+    let txid = Txid::from_str(parts.next()?).ok()?;
+    let index = parts.next()?.parse().ok()?;
+
+    Some((txid, index))
+}
+
+impl LabelsExport {
+    /// Export the labels stored in the database of a wallet
+    ///
+    /// Labels attached to scripts that can't be represented as an address on the wallet's
+    /// network are not included in the export.
+    pub fn export_wallet<B, D: BatchDatabase>(wallet: &Wallet<B, D>) -> Result<Self, Error> {
+        let records = wallet
+            .list_labels()?
+            .into_iter()
+            .filter_map(|(target, label)| {
+                let (label_type, reference) = match target {
+                    LabelRef::Transaction(txid) => (LabelType::Tx, txid.to_string()),
+                    LabelRef::Output(outpoint) => (LabelType::Output, outpoint.to_string()),
+                    LabelRef::Script(script) => (
+                        LabelType::Addr,
+                        Address::from_script(&script, wallet.network)?.to_string(),
+                    ),
+                    LabelRef::Input { txid, vin } => {
+                        (LabelType::Input, format!("{}:{}", txid, vin))
+                    }
+                    LabelRef::PublicKey(key) => (LabelType::Pubkey, key.to_string()),
+                    LabelRef::ExtendedPubKey(xpub) => (LabelType::Xpub, xpub.to_string()),
+                };
+
+                Some(LabelRecord {
+                    label_type,
+                    reference,
+                    label: Some(label),
+                    origin: None,
+                })
+            })
+            .collect();
+
+        Ok(LabelsExport { records })
+    }
+
+    /// Import the labels into the database of a wallet
+    ///
+    /// Only records that reference an object of the wallet are imported, replacing any label
+    /// already present. All the others are returned in [`LabelsImport::unmatched`].
+    pub fn import_into<B, D: BatchDatabase>(
+        &self,
+        wallet: &Wallet<B, D>,
+    ) -> Result<LabelsImport, Error> {
+        let mut result = LabelsImport::default();
+        let mut pubkeys = None;
+        let mut xpubs = None;
+
+        let mut batch = wallet.database.borrow().begin_batch();
+        for record in &self.records {
+            let label = match &record.label {
+                Some(label) => label,
+                None => {
+                    result
+                        .unmatched
+                        .push((record.clone(), UnmatchedReason::MissingLabel));
+                    continue;
+                }
+            };
+
+            let target = match Self::match_record(wallet, record, &mut pubkeys, &mut xpubs)? {
+                Ok(target) => target,
+                Err(reason) => {
+                    result.unmatched.push((record.clone(), reason));
+                    continue;
+                }
+            };
+
+            batch.set_label(&target, label)?;
+            result.imported += 1;
+        }
+        wallet.database.borrow_mut().commit_batch(batch)?;
+
+        Ok(result)
+    }
+
+    fn match_record<B, D: BatchDatabase>(
+        wallet: &Wallet<B, D>,
+        record: &LabelRecord,
+        pubkeys: &mut Option<HashSet<PublicKey>>,
+        xpubs: &mut Option<HashSet<ExtendedPubKey>>,
+    ) -> Result<Result<LabelRef, UnmatchedReason>, Error> {
+        let database = wallet.database.borrow();
+        let reference = record.reference.as_str();
+
+        macro_rules! parse_or_unmatched {
+            ($parsed:expr) => {
+                match $parsed {
+                    Some(val) => val,
+                    None => return Ok(Err(UnmatchedReason::InvalidReference)),
+                }
+            };
+        }
+
+        let (target, is_mine) = match record.label_type {
+            LabelType::Tx => {
+                let txid = parse_or_unmatched!(Txid::from_str(reference).ok());
+                let is_mine = database.get_tx(&txid, false)?.is_some();
+
+                (LabelRef::Transaction(txid), is_mine)
+            }
+            LabelType::Addr => {
+                let address = parse_or_unmatched!(Address::from_str(reference).ok());
+                let script = address.script_pubkey();
+                let is_mine = database.is_mine(&script)?;
+
+                (LabelRef::Script(script), is_mine)
+            }
+            LabelType::Output => {
+                let outpoint = parse_or_unmatched!(OutPoint::from_str(reference).ok());
+                let is_mine = match database.get_utxo(&outpoint)? {
+                    Some(_) => true,
+                    None => match database.get_raw_tx(&outpoint.txid)? {
+                        Some(tx) => match tx.output.get(outpoint.vout as usize) {
+                            Some(txout) => database.is_mine(&txout.script_pubkey)?,
+                            None => false,
+                        },
+                        None => false,
+                    },
+                };
+
+                (LabelRef::Output(outpoint), is_mine)
+            }
+            LabelType::Input => {
+                let (txid, vin) = parse_or_unmatched!(parse_index_ref(reference));
+                let is_mine = database
+                    .get_raw_tx(&txid)?
+                    .map(|tx| (vin as usize) < tx.input.len())
+                    .unwrap_or(false);
+
+                (LabelRef::Input { txid, vin }, is_mine)
+            }
+            LabelType::Pubkey => {
+                let key = parse_or_unmatched!(PublicKey::from_str(reference).ok());
+                if pubkeys.is_none() {
+                    *pubkeys = Some(Self::wallet_pubkeys(wallet)?);
+                }
+                let is_mine = pubkeys.as_ref().unwrap().contains(&key);
+
+                (LabelRef::PublicKey(key), is_mine)
+            }
+            LabelType::Xpub => {
+                let xpub = parse_or_unmatched!(ExtendedPubKey::from_str(reference).ok());
+                if xpubs.is_none() {
+                    *xpubs = Some(Self::wallet_xpubs(wallet)?);
+                }
+                let is_mine = xpubs.as_ref().unwrap().contains(&xpub);
+
+                (LabelRef::ExtendedPubKey(xpub), is_mine)
+            }
+        };
+
+        match is_mine {
+            true => Ok(Ok(target)),
+            false => Ok(Err(UnmatchedReason::NotInWallet)),
+        }
+    }
+
+    fn keychains<B, D: BatchDatabase>(wallet: &Wallet<B, D>) -> Vec<KeychainKind> {
+        match wallet.change_descriptor {
+            Some(_) => vec![KeychainKind::External, KeychainKind::Internal],
+            None => vec![KeychainKind::External],
+        }
+    }
+
+    // Public keys derived at the indexes for which the wallet has cached a script_pubkey
+    fn wallet_pubkeys<B, D: BatchDatabase>(
+        wallet: &Wallet<B, D>,
+    ) -> Result<HashSet<PublicKey>, Error> {
+        let mut pubkeys = HashSet::new();
+        for keychain in Self::keychains(wallet) {
+            let descriptor = wallet.get_descriptor_for_keychain(keychain);
+            let count = match descriptor.is_deriveable() {
+                true => wallet
+                    .database
+                    .borrow()
+                    .iter_script_pubkeys(Some(keychain))?
+                    .len() as u32,
+                false => 1,
+            };
+
+            for index in 0..count {
+                let derived = descriptor.as_derived(index, &wallet.secp);
+                derived.for_each_key(|key| {
+                    pubkeys.insert(key.as_key().to_public_key());
+                    true
+                });
+            }
+        }
+
+        Ok(pubkeys)
+    }
+
+    fn wallet_xpubs<B, D: BatchDatabase>(
+        wallet: &Wallet<B, D>,
+    ) -> Result<HashSet<ExtendedPubKey>, Error> {
+        let mut xpubs = HashSet::new();
+        for keychain in Self::keychains(wallet) {
+            wallet
+                .get_descriptor_for_keychain(keychain)
+                .for_each_key(|key| {
+                    if let DescriptorPublicKey::XPub(xpub) = key.as_key() {
+                        xpubs.insert(xpub.xkey);
+                    }
+                    true
+                });
+        }
+
+        Ok(xpubs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::util::bip32::{ChildNumber, ExtendedPubKey};
+    use bitcoin::{Address, Network, OutPoint, Script, Transaction, TxIn};
+
+    use super::*;
+    use crate::types::LabelRef;
+    use crate::wallet::test::{get_funded_wallet, get_test_single_sig_csv, get_test_wpkh};
+    use crate::wallet::AddressIndex::New;
+
+    #[test]
+    fn test_labels_roundtrip() {
+        let (wallet, _, txid) = get_funded_wallet(get_test_wpkh());
+        let address = wallet.get_address(New).unwrap();
+
+        wallet
+            .set_label(&LabelRef::Transaction(txid), "funding")
+            .unwrap();
+        wallet
+            .set_label(&LabelRef::Output(OutPoint { txid, vout: 0 }), "coin")
+            .unwrap();
+        wallet
+            .set_label(&LabelRef::Script(address.script_pubkey()), "addr")
+            .unwrap();
+
+        let export = LabelsExport::export_wallet(&wallet).unwrap();
+        assert_eq!(export.records.len(), 3);
+
+        let parsed = LabelsExport::from_str(&export.to_string()).unwrap();
+        assert_eq!(parsed, export);
+
+        let (other, _, _) = get_funded_wallet(get_test_wpkh());
+        // make sure the address is cached in the other wallet too
+        other.get_address(New).unwrap();
+        let result = parsed.import_into(&other).unwrap();
+        assert_eq!(result.imported, 3);
+        assert!(result.unmatched.is_empty());
+        assert_eq!(
+            other
+                .get_label(&LabelRef::Script(address.script_pubkey()))
+                .unwrap(),
+            Some("addr".to_string())
+        );
+
+        let (unrelated, _, _) = get_funded_wallet(get_test_single_sig_csv());
+        let result = parsed.import_into(&unrelated).unwrap();
+        assert_eq!(result.imported, 0);
+        assert!(result
+            .unmatched
+            .iter()
+            .all(|(_, reason)| *reason == UnmatchedReason::NotInWallet));
+        assert!(unrelated.list_labels().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_import_labels() {
+        let xpub = ExtendedPubKey::from_str("tpubEBr4i6yk5nf5DAaJpsi9N2pPYBeJ7fZ5Z9rmN4977iYLCGco1VyjB9tvvuvYtfZzjD5A8igzgw3HeWeeKFmanHYqksqZXYXGsw5zjnj7KM9").unwrap();
+        let (wallet, _, txid) = get_funded_wallet(&format!("wpkh({}/*)", xpub));
+        let pubkey = xpub
+            .derive_pub(&wallet.secp, &[ChildNumber::from_normal_idx(0).unwrap()])
+            .unwrap()
+            .public_key;
+        let foreign_address = Address::p2wsh(&Script::new(), Network::Regtest);
+
+        let spending_tx = Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid, vout: 0 },
+                ..Default::default()
+            }],
+            output: vec![],
+        };
+        wallet
+            .database
+            .borrow_mut()
+            .set_raw_tx(&spending_tx)
+            .unwrap();
+
+        let import = format!(
+            r#"
+            {{"type":"tx","ref":"{txid}","label":"funding"}}
+            {{"type":"input","ref":"{spending_txid}:0","label":"spent"}}
+            {{"type":"output","ref":"{txid}:0","label":"coin","origin":"wpkh([d34db33f/84'/1'/0'])"}}
+            {{"type":"xpub","ref":"{xpub}","label":"account"}}
+            {{"type":"pubkey","ref":"{pubkey}","label":"key"}}
+            {{"type":"addr","ref":"{foreign}","label":"not ours"}}
+            {{"type":"output","ref":"{txid}:1","label":"not ours"}}
+            {{"type":"tx","ref":"invalid","label":"invalid"}}
+            {{"type":"tx","ref":"{txid}"}}
+            "#,
+            txid = txid,
+            spending_txid = spending_tx.txid(),
+            xpub = xpub,
+            pubkey = pubkey,
+            foreign = foreign_address,
+        );
+
+        let result = LabelsExport::from_str(&import)
+            .unwrap()
+            .import_into(&wallet)
+            .unwrap();
+        assert_eq!(result.imported, 5);
+        assert_eq!(
+            result
+                .unmatched
+                .iter()
+                .map(|(_, reason)| *reason)
+                .collect::<Vec<_>>(),
+            vec![
+                UnmatchedReason::NotInWallet,
+                UnmatchedReason::NotInWallet,
+                UnmatchedReason::InvalidReference,
+                UnmatchedReason::MissingLabel
+            ]
+        );
+
+        assert_eq!(
+            wallet.get_label(&LabelRef::Transaction(txid)).unwrap(),
+            Some("funding".to_string())
+        );
+        assert_eq!(
+            wallet
+                .get_label(&LabelRef::Input {
+                    txid: spending_tx.txid(),
+                    vin: 0
+                })
+                .unwrap(),
+            Some("spent".to_string())
+        );
+        assert_eq!(
+            wallet.get_label(&LabelRef::ExtendedPubKey(xpub)).unwrap(),
+            Some("account".to_string())
+        );
+        assert_eq!(
+            wallet.get_label(&LabelRef::PublicKey(pubkey)).unwrap(),
+            Some("key".to_string())
+        );
+
+        let export = LabelsExport::export_wallet(&wallet).unwrap();
+        assert_eq!(export.records.len(), 5);
+    }
+}
@@ -13,12 +13,12 @@
 //!
 //! This module defines the [`Wallet`] structure.
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 use std::ops::{Deref, DerefMut};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use bitcoin::secp256k1::Secp256k1;
 
@@ -37,7 +37,9 @@ use log::{debug, error, info, trace};
 
 pub mod address_validator;
 pub mod coin_selection;
+pub mod events;
 pub mod export;
+pub mod labels;
 pub mod signer;
 pub mod time;
 pub mod tx_builder;
@@ -50,10 +52,16 @@ pub use utils::IsDust;
 
 use address_validator::AddressValidator;
 use coin_selection::DefaultCoinSelectionAlgorithm;
+use events::{RecordingDatabase, SyncListener, SyncRecord};
 use signer::{SignOptions, Signer, SignerOrdering, SignersContainer};
 use tx_builder::{BumpFee, CreateTx, FeePolicy, TxBuilder, TxParams};
-use utils::{check_nlocktime, check_nsequence_rbf, After, Older, SecpCtx, DUST_LIMIT_SATOSHI};
+use utils::{
+    check_nlocktime, check_nsequence_rbf, After, Older, SecpCtx, COINBASE_MATURITY,
+    DUST_LIMIT_SATOSHI,
+};
 
+#[cfg(feature = "electrum")]
+use crate::blockchain::electrum::{ElectrumBlockchain, ElectrumNotification};
 use crate::blockchain::{Blockchain, Progress};
 use crate::database::{BatchDatabase, BatchOperations, DatabaseUtils};
 use crate::descriptor::derived::AsDerived;
@@ -92,7 +100,7 @@ pub struct Wallet<B, D> {
 
     network: Network,
 
-    current_height: Option<u32>,
+    current_height: Cell<Option<u32>>,
 
     client: B,
     database: RefCell<D>,
@@ -161,7 +169,7 @@ where
             change_signers,
             address_validators: Vec::new(),
             network,
-            current_height,
+            current_height: Cell::new(current_height),
             client,
             database: RefCell::new(database),
             secp,
@@ -318,13 +326,30 @@ where
     /// Note that this methods only operate on the internal database, which first needs to be
     /// [`Wallet::sync`] manually.
     pub fn list_unspent(&self) -> Result<Vec<LocalUtxo>, Error> {
-        self.database.borrow().iter_utxos()
+        let database = self.database.borrow();
+        database
+            .iter_utxos()?
+            .into_iter()
+            .map(|mut utxo| {
+                utxo.label = database.get_label(&LabelRef::Output(utxo.outpoint))?;
+                utxo.is_frozen = database.is_frozen_utxo(&utxo.outpoint)?;
+                Ok(utxo)
+            })
+            .collect()
     }
 
     /// Returns the `UTXO` owned by this wallet corresponding to `outpoint` if it exists in the
     /// wallet's database.
     pub fn get_utxo(&self, outpoint: OutPoint) -> Result<Option<LocalUtxo>, Error> {
-        self.database.borrow().get_utxo(&outpoint)
+        let database = self.database.borrow();
+        database
+            .get_utxo(&outpoint)?
+            .map(|mut utxo| {
+                utxo.label = database.get_label(&LabelRef::Output(outpoint))?;
+                utxo.is_frozen = database.is_frozen_utxo(&outpoint)?;
+                Ok(utxo)
+            })
+            .transpose()
     }
 
     /// Return the list of transactions made and received by the wallet
@@ -335,18 +360,111 @@ where
     /// Note that this methods only operate on the internal database, which first needs to be
     /// [`Wallet::sync`] manually.
     pub fn list_transactions(&self, include_raw: bool) -> Result<Vec<TransactionDetails>, Error> {
-        self.database.borrow().iter_txs(include_raw)
+        let database = self.database.borrow();
+        database
+            .iter_txs(include_raw)?
+            .into_iter()
+            .map(|mut details| {
+                details.label = database.get_label(&LabelRef::Transaction(details.txid))?;
+                Ok(details)
+            })
+            .collect()
     }
 
-    /// Return the balance, meaning the sum of this wallet's unspent outputs' values
+    /// Return the balance, split in the categories defined in [`Balance`]
+    ///
+    /// Coinbase outputs are considered immature if they have less than 100 confirmations or if the
+    /// current height of the blockchain is not known, which is the case for offline wallets.
     ///
     /// Note that this methods only operate on the internal database, which first needs to be
     /// [`Wallet::sync`] manually.
-    pub fn get_balance(&self) -> Result<u64, Error> {
-        Ok(self
-            .list_unspent()?
-            .iter()
-            .fold(0, |sum, i| sum + i.txout.value))
+    pub fn get_balance(&self) -> Result<Balance, Error> {
+        let mut balance = Balance::default();
+
+        for utxo in self.list_unspent()? {
+            let value = utxo.txout.value;
+            if utxo.is_frozen {
+                balance.frozen += value;
+                continue;
+            }
+
+            let (confirmation_time, sent) =
+                match self.database.borrow().get_tx(&utxo.outpoint.txid, false)? {
+                    Some(details) => (details.confirmation_time, details.sent),
+                    None => (None, 0),
+                };
+
+            match confirmation_time {
+                _ if utxo.is_coinbase && !self.is_coinbase_mature(confirmation_time.as_ref()) => {
+                    balance.immature += value
+                }
+                Some(_) => balance.confirmed += value,
+                None if sent > 0 => balance.trusted_pending += value,
+                None => balance.untrusted_pending += value,
+            }
+        }
+
+        Ok(balance)
+    }
+
+    /// Attach a label to a transaction, an output or a script_pubkey, replacing the previous one
+    /// if present
+    ///
+    /// Labels are stored in the wallet's database and returned by [`Wallet::list_transactions`]
+    /// and [`Wallet::list_unspent`] alongside the object they refer to.
+    pub fn set_label(&self, target: &LabelRef, label: &str) -> Result<(), Error> {
+        self.database.borrow_mut().set_label(target, label)
+    }
+
+    /// Return the label attached to a transaction, an output or a script_pubkey
+    pub fn get_label(&self, target: &LabelRef) -> Result<Option<String>, Error> {
+        self.database.borrow().get_label(target)
+    }
+
+    /// Remove the label attached to a transaction, an output or a script_pubkey, returning it
+    pub fn del_label(&self, target: &LabelRef) -> Result<Option<String>, Error> {
+        self.database.borrow_mut().del_label(target)
+    }
+
+    /// Return the list of labels stored in the wallet's database, along with the object they are
+    /// attached to
+    pub fn list_labels(&self) -> Result<Vec<(LabelRef, String)>, Error> {
+        self.database.borrow().iter_labels()
+    }
+
+    /// Freeze a UTXO owned by this wallet
+    ///
+    /// Frozen UTXOs are persisted in the database and never selected automatically when building a
+    /// transaction, they can only be spent by adding them explicitly with
+    /// [`TxBuilder::add_utxo`].
+    pub fn freeze_utxo(&self, outpoint: OutPoint) -> Result<(), Error> {
+        let mut database = self.database.borrow_mut();
+        if database.get_utxo(&outpoint)?.is_none() {
+            return Err(Error::UnknownUtxo);
+        }
+
+        database.set_frozen_utxo(&outpoint)
+    }
+
+    /// Unfreeze a UTXO previously frozen with [`Wallet::freeze_utxo`]
+    ///
+    /// Returns `false` if the UTXO wasn't frozen.
+    pub fn unfreeze_utxo(&self, outpoint: OutPoint) -> Result<bool, Error> {
+        self.database.borrow_mut().del_frozen_utxo(&outpoint)
+    }
+
+    /// Set the birthday of the wallet, the earliest point in the blockchain where its transactions
+    /// can be found
+    ///
+    /// The birthday is stored in the database, and the blockchain backends don't look for the
+    /// wallet's transactions before it when syncing.
+    pub fn set_birthday(&self, birthday: Birthday) -> Result<(), Error> {
+        self.database.borrow_mut().set_birthday(&birthday)
+    }
+
+    /// Return the birthday of the wallet, if set
+    pub fn get_birthday(&self) -> Result<Option<Birthday>, Error> {
+        self.database.borrow().get_birthday()
     }
 
     /// Add an external signer
@@ -714,6 +832,7 @@ where
             sent,
             fee: Some(fee_amount),
             verified: true,
+            label: None,
         };
 
         Ok((psbt, transaction_details))
@@ -819,6 +938,9 @@ where
                     outpoint: txin.previous_output,
                     txout,
                     keychain,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase: false,
                 };
 
                 Ok(WeightedUtxo {
@@ -997,7 +1119,7 @@ where
                 .borrow()
                 .get_tx(&input.previous_output.txid, false)?
                 .map(|tx| tx.confirmation_time.map(|c| c.height).unwrap_or(u32::MAX));
-            let current_height = sign_options.assume_height.or(self.current_height);
+            let current_height = sign_options.assume_height.or(self.current_height.get());
 
             debug!(
                 "Input #{} - {}, using `create_height` = {:?}, `current_height` = {:?}",
@@ -1187,10 +1309,41 @@ where
         Ok(())
     }
 
+    // Cache script_pubkeys until there are at least `required` of them for each keychain. Returns
+    // whether any new script_pubkey has been stored.
+    fn ensure_addresses_cached(&self, required: &[(KeychainKind, u32)]) -> Result<bool, Error> {
+        let mut extended = false;
+        for (keychain, required) in required {
+            let descriptor = match (keychain, &self.change_descriptor) {
+                (KeychainKind::Internal, Some(change_descriptor)) => change_descriptor,
+                (KeychainKind::Internal, None) => continue,
+                (KeychainKind::External, _) => &self.descriptor,
+            };
+            if !descriptor.is_deriveable() {
+                continue;
+            }
+
+            let cached = self
+                .database
+                .borrow()
+                .iter_script_pubkeys(Some(*keychain))?
+                .len() as u32;
+            if cached < *required {
+                debug!("caching more {:?} addresses", keychain);
+                let count = std::cmp::max(required - cached, CACHE_ADDR_BATCH_SIZE);
+                self.cache_addresses(*keychain, cached, count)?;
+                extended = true;
+            }
+        }
+
+        Ok(extended)
+    }
+
     fn get_available_utxos(&self) -> Result<Vec<(LocalUtxo, usize)>, Error> {
         Ok(self
             .list_unspent()?
             .into_iter()
+            .filter(|utxo| !utxo.is_frozen)
             .map(|utxo| {
                 let keychain = utxo.keychain;
                 (
@@ -1203,6 +1356,17 @@ where
             .collect())
     }
 
+    // Whether a coinbase output confirmed at `confirmation_time` can be spent in the next block.
+    // If the current height is not known the output is always considered immature.
+    fn is_coinbase_mature(&self, confirmation_time: Option<&ConfirmationTime>) -> bool {
+        match (confirmation_time, self.current_height.get()) {
+            (Some(time), Some(current_height)) => {
+                current_height.saturating_sub(time.height) + 1 >= COINBASE_MATURITY
+            }
+            _ => false,
+        }
+    }
+
     /// Given the options returns the list of utxos that must be used to form the
     /// transaction and any further that may be used if needed.
     #[allow(clippy::type_complexity)]
@@ -1250,11 +1414,28 @@ where
             false => vec![true; may_spend.len()],
         };
 
+        let satisfies_maturity = {
+            let database = self.database.borrow();
+            may_spend
+                .iter()
+                .map(|u| match u.0.is_coinbase {
+                    false => Ok(true),
+                    true => {
+                        let confirmation_time = database
+                            .get_tx(&u.0.outpoint.txid, false)?
+                            .and_then(|tx| tx.confirmation_time);
+                        Ok(self.is_coinbase_mature(confirmation_time.as_ref()))
+                    }
+                })
+                .collect::<Result<Vec<_>, Error>>()?
+        };
+
         let mut i = 0;
         may_spend.retain(|u| {
             let retain = change_policy.is_satisfied_by(&u.0)
                 && !unspendable.contains(&u.0.outpoint)
-                && satisfies_confirmed[i];
+                && satisfies_confirmed[i]
+                && satisfies_maturity[i];
             i += 1;
             retain
         });
@@ -1483,6 +1664,38 @@ where
         &self,
         progress_update: P,
         max_address_param: Option<u32>,
+    ) -> Result<(), Error> {
+        maybe_await!(self._sync(progress_update, max_address_param, false, None))
+    }
+
+    /// Update the internal database with the changes since the last sync, doing as little work
+    /// as possible
+    ///
+    /// This works like [`Wallet::sync`], but it's meant to be called frequently, for example to
+    /// spot incoming payments: with the Electrum and Esplora backends, only the new unconfirmed
+    /// transactions of the recently used addresses are downloaded until a new block is found. See
+    /// [`Blockchain::refresh`] for the details.
+    ///
+    /// With Electrum, the status of the recently used addresses is compared with the one stored
+    /// at the last sync, and only the addresses whose status has changed are queried. The server
+    /// doesn't let us ask for the unconfirmed transactions alone, so the whole history of those
+    /// addresses is downloaded and the confirmed transactions are discarded.
+    #[maybe_async]
+    pub fn refresh<P: 'static + Progress>(
+        &self,
+        progress_update: P,
+        max_address_param: Option<u32>,
+    ) -> Result<(), Error> {
+        maybe_await!(self._sync(progress_update, max_address_param, true, None))
+    }
+
+    #[maybe_async]
+    fn _sync<P: 'static + Progress>(
+        &self,
+        progress_update: P,
+        max_address_param: Option<u32>,
+        mut refresh: bool,
+        listener: Option<&dyn SyncListener>,
     ) -> Result<(), Error> {
         debug!("Begin sync...");
 
@@ -1525,20 +1738,61 @@ where
         debug!("run_setup: {}", run_setup);
         // TODO: what if i generate an address first and cache some addresses?
         // TODO: we should sync if generating an address triggers a new batch to be stored
-        if run_setup {
-            maybe_await!(self.client.setup(
-                None,
-                self.database.borrow_mut().deref_mut(),
-                progress_update,
-            ))?;
-        } else {
-            maybe_await!(self.client.sync(
-                None,
-                self.database.borrow_mut().deref_mut(),
-                progress_update,
-            ))?;
+        // the sync is run again after caching more addresses, the progress shouldn't restart
+        let progress_update = MonotonicProgress::new(progress_update);
+        // the writes of the backend are only recorded when somebody is listening for the events
+        let mut record = listener.map(|_| SyncRecord::default());
+        loop {
+            let result = {
+                let mut database = self.database.borrow_mut();
+                let mut database = RecordingDatabase::new(database.deref_mut(), record.as_mut());
+                if run_setup {
+                    maybe_await!(self
+                        .client
+                        .setup(None, &mut database, progress_update.clone(),))
+                } else if refresh {
+                    maybe_await!(self
+                        .client
+                        .refresh(None, &mut database, progress_update.clone(),))
+                } else {
+                    maybe_await!(self
+                        .client
+                        .sync(None, &mut database, progress_update.clone(),))
+                }
+            };
+
+            match result {
+                Err(Error::MissingCachedScripts { required }) => {
+                    // a keychain with a fixed descriptor can't derive more script_pubkeys, so it's
+                    // always fully synced
+                    let required = required
+                        .into_iter()
+                        .filter(|(keychain, _)| {
+                            self._get_descriptor_for_keychain(*keychain)
+                                .0
+                                .is_deriveable()
+                        })
+                        .collect::<Vec<_>>();
+                    if required.is_empty() {
+                        break;
+                    }
+
+                    // the backend asked for addresses that are already cached, running the sync
+                    // again wouldn't change anything
+                    if !self.ensure_addresses_cached(&required)? {
+                        return Err(Error::MissingCachedScripts { required });
+                    }
+                    // the new addresses have never been synced, so a refresh isn't enough
+                    run_setup = false;
+                    refresh = false;
+                }
+                result => break result?,
+            }
         }
 
+        self.current_height
+            .set(Some(maybe_await!(self.client.get_height())?));
+
         #[cfg(feature = "verify")]
         {
             debug!("Verifying transactions...");
@@ -1556,9 +1810,42 @@ where
             }
         }
 
+        if let (Some(listener), Some(record)) = (listener, record) {
+            listener.on_sync(record.into_events(self.database.borrow().deref())?)?;
+        }
+
         Ok(())
     }
 
+    /// Sync the internal database with the blockchain and report what changed to `listener`
+    ///
+    /// This works like [`Wallet::sync`], but the transactions and UTXOs written to the database
+    /// during the sync are compared with their previous state, and the differences are passed to
+    /// [`SyncListener::on_sync`] as a list of [`WalletEvent`](events::WalletEvent)s.
+    #[maybe_async]
+    pub fn sync_with_listener<P: 'static + Progress, L: SyncListener>(
+        &self,
+        progress_update: P,
+        max_address_param: Option<u32>,
+        listener: L,
+    ) -> Result<(), Error> {
+        maybe_await!(self._sync(progress_update, max_address_param, false, Some(&listener)))
+    }
+
+    /// Refresh the internal database and report what changed to `listener`
+    ///
+    /// This works like [`Wallet::refresh`], and the events are generated like in
+    /// [`Wallet::sync_with_listener`].
+    #[maybe_async]
+    pub fn refresh_with_listener<P: 'static + Progress, L: SyncListener>(
+        &self,
+        progress_update: P,
+        max_address_param: Option<u32>,
+        listener: L,
+    ) -> Result<(), Error> {
+        maybe_await!(self._sync(progress_update, max_address_param, true, Some(&listener)))
+    }
+
     /// Return a reference to the internal blockchain client
     pub fn client(&self) -> &B {
         &self.client
@@ -1578,6 +1865,60 @@ where
     }
 }
 
+#[cfg(feature = "electrum")]
+#[cfg_attr(docsrs, doc(cfg(feature = "electrum")))]
+impl<D> Wallet<ElectrumBlockchain, D>
+where
+    D: BatchDatabase,
+{
+    /// Wait for the Electrum server to notify a new block or some activity on the wallet's
+    /// script_pubkeys, and then sync the database
+    ///
+    /// All the script_pubkeys cached in the database are subscribed before waiting. If no
+    /// notification is received before `timeout` expires the database is not updated and an
+    /// empty list is returned.
+    ///
+    /// For a usage example see the [`blockchain::electrum`](crate::blockchain::electrum) module.
+    pub fn sync_on_notification<P: 'static + Progress>(
+        &self,
+        progress_update: P,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Vec<ElectrumNotification>, Error> {
+        self.client.subscribe(self.database.borrow().deref())?;
+
+        let notifications = self.client.wait_for_notifications(timeout)?;
+        if !notifications.is_empty() {
+            debug!("Received {} notifications", notifications.len());
+            self.sync(progress_update, None)?;
+        }
+
+        Ok(notifications)
+    }
+}
+
+/// [`Progress`] that never reports a value lower than the previous ones
+struct MonotonicProgress<P>(Arc<Mutex<(P, f32)>>);
+
+impl<P> MonotonicProgress<P> {
+    fn new(inner: P) -> Self {
+        MonotonicProgress(Arc::new(Mutex::new((inner, 0.0))))
+    }
+}
+
+impl<P> Clone for MonotonicProgress<P> {
+    fn clone(&self) -> Self {
+        MonotonicProgress(Arc::clone(&self.0))
+    }
+}
+
+impl<P: Progress> Progress for MonotonicProgress<P> {
+    fn update(&self, progress: f32, message: Option<String>) -> Result<(), Error> {
+        let mut inner = self.0.lock().unwrap();
+        inner.1 = inner.1.max(progress);
+        inner.0.update(inner.1, message)
+    }
+}
+
 /// Trait implemented by types that can be used to measure weight units.
 pub trait Vbytes {
     /// Convert weight units to virtual bytes.
@@ -1594,7 +1935,7 @@ impl Vbytes for usize {
 pub(crate) mod test {
     use std::str::FromStr;
 
-    use bitcoin::{util::psbt, Network};
+    use bitcoin::{util::psbt, Network, TxIn};
 
     use crate::database::memory::MemoryDatabase;
     use crate::database::Database;
@@ -2404,6 +2745,241 @@ pub(crate) mod test {
         assert_eq!(details.sent, 75_000, "total should be sum of both inputs");
     }
 
+    #[test]
+    fn test_create_tx_skip_frozen_utxo() {
+        let (wallet, descriptors, txid) = get_funded_wallet(get_test_wpkh());
+        let small_output_txid = crate::populate_test_db!(
+            wallet.database.borrow_mut(),
+            testutils! (@tx ( (@external descriptors, 0) => 25_000 ) (@confirmations 1)),
+            Some(100),
+        );
+        wallet.freeze_utxo(OutPoint { txid, vout: 0 }).unwrap();
+
+        let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX").unwrap();
+        let mut builder = wallet.build_tx();
+        builder
+            .set_single_recipient(addr.script_pubkey())
+            .drain_wallet();
+        let (psbt, details) = builder.finish().unwrap();
+
+        assert_eq!(psbt.global.unsigned_tx.input.len(), 1);
+        assert_eq!(
+            psbt.global.unsigned_tx.input[0].previous_output,
+            OutPoint {
+                txid: small_output_txid,
+                vout: 0
+            }
+        );
+        assert_eq!(details.sent, 25_000);
+    }
+
+    #[test]
+    fn test_create_tx_add_frozen_utxo() {
+        let (wallet, descriptors, _) = get_funded_wallet(get_test_wpkh());
+        let small_output_txid = crate::populate_test_db!(
+            wallet.database.borrow_mut(),
+            testutils! (@tx ( (@external descriptors, 0) => 25_000 ) (@confirmations 1)),
+            Some(100),
+        );
+        let small_output = OutPoint {
+            txid: small_output_txid,
+            vout: 0,
+        };
+        wallet.freeze_utxo(small_output).unwrap();
+
+        let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX").unwrap();
+        let mut builder = wallet.build_tx();
+        builder
+            .add_recipient(addr.script_pubkey(), 30_000)
+            .add_utxo(small_output)
+            .unwrap();
+        let (psbt, details) = builder.finish().unwrap();
+
+        assert_eq!(
+            psbt.global.unsigned_tx.input.len(),
+            2,
+            "the frozen utxo should be spent since it was added manually"
+        );
+        assert_eq!(details.sent, 75_000);
+    }
+
+    #[test]
+    fn test_freeze_utxo() {
+        let (wallet, _, txid) = get_funded_wallet(get_test_wpkh());
+        let outpoint = OutPoint { txid, vout: 0 };
+
+        assert!(matches!(
+            wallet.freeze_utxo(OutPoint { txid, vout: 1 }),
+            Err(Error::UnknownUtxo)
+        ));
+
+        wallet.freeze_utxo(outpoint).unwrap();
+        assert!(wallet.list_unspent().unwrap()[0].is_frozen);
+        assert!(wallet.get_utxo(outpoint).unwrap().unwrap().is_frozen);
+
+        assert!(wallet.unfreeze_utxo(outpoint).unwrap());
+        assert!(!wallet.unfreeze_utxo(outpoint).unwrap());
+        assert!(!wallet.get_utxo(outpoint).unwrap().unwrap().is_frozen);
+    }
+
+    #[test]
+    fn test_create_tx_skip_immature_coinbase() {
+        let (wallet, _, _) = get_funded_wallet(get_test_wpkh());
+        let coinbase = Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: 25_000,
+                script_pubkey: wallet.get_address(New).unwrap().script_pubkey(),
+            }],
+        };
+        {
+            let mut database = wallet.database.borrow_mut();
+            database
+                .set_tx(&TransactionDetails {
+                    transaction: Some(coinbase.clone()),
+                    txid: coinbase.txid(),
+                    received: 25_000,
+                    sent: 0,
+                    fee: None,
+                    confirmation_time: Some(ConfirmationTime {
+                        height: 50,
+                        timestamp: 0,
+                    }),
+                    verified: true,
+                    label: None,
+                })
+                .unwrap();
+            database
+                .set_utxo(&LocalUtxo {
+                    outpoint: OutPoint::new(coinbase.txid(), 0),
+                    txout: coinbase.output[0].clone(),
+                    keychain: KeychainKind::External,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase: true,
+                })
+                .unwrap();
+        }
+
+        let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX").unwrap();
+        let drain_inputs = || {
+            let mut builder = wallet.build_tx();
+            builder
+                .set_single_recipient(addr.script_pubkey())
+                .drain_wallet();
+            let (psbt, _) = builder.finish().unwrap();
+            psbt.global.unsigned_tx.input.len()
+        };
+
+        // 99 confirmations, the coinbase can't be spent in the next block
+        wallet.current_height.set(Some(148));
+        assert_eq!(drain_inputs(), 1);
+
+        wallet.current_height.set(Some(149));
+        assert_eq!(drain_inputs(), 2);
+    }
+
+    #[test]
+    fn test_get_balance() {
+        let (wallet, descriptors, _) = get_funded_wallet(get_test_wpkh());
+        crate::populate_test_db!(
+            wallet.database.borrow_mut(),
+            testutils! (@tx ( (@external descriptors, 0) => 25_000 ) ),
+            None,
+        );
+        let frozen_txid = crate::populate_test_db!(
+            wallet.database.borrow_mut(),
+            testutils! (@tx ( (@external descriptors, 0) => 5_000 ) (@confirmations 1)),
+            Some(100),
+        );
+        wallet
+            .freeze_utxo(OutPoint {
+                txid: frozen_txid,
+                vout: 0,
+            })
+            .unwrap();
+
+        let script_pubkey = wallet.get_address(New).unwrap().script_pubkey();
+        let mut insert_tx = |input: TxIn, value: u64, height: Option<u32>, sent: u64| {
+            let tx = Transaction {
+                version: 1,
+                lock_time: 0,
+                input: vec![input],
+                output: vec![TxOut {
+                    value,
+                    script_pubkey: script_pubkey.clone(),
+                }],
+            };
+            let mut database = wallet.database.borrow_mut();
+            database
+                .set_tx(&TransactionDetails {
+                    transaction: Some(tx.clone()),
+                    txid: tx.txid(),
+                    received: value,
+                    sent,
+                    fee: None,
+                    confirmation_time: height.map(|height| ConfirmationTime {
+                        height,
+                        timestamp: 0,
+                    }),
+                    verified: true,
+                    label: None,
+                })
+                .unwrap();
+            database
+                .set_utxo(&LocalUtxo {
+                    outpoint: OutPoint::new(tx.txid(), 0),
+                    txout: tx.output[0].clone(),
+                    keychain: KeychainKind::External,
+                    label: None,
+                    is_frozen: false,
+                    is_coinbase: tx.is_coin_base(),
+                })
+                .unwrap();
+        };
+        // unconfirmed change
+        insert_tx(
+            TxIn {
+                previous_output: OutPoint::new(Txid::default(), 1),
+                ..Default::default()
+            },
+            10_000,
+            None,
+            20_000,
+        );
+        // coinbase outputs, one mature and one immature
+        insert_tx(TxIn::default(), 1_000, Some(1), 0);
+        insert_tx(
+            TxIn {
+                sequence: 1,
+                ..Default::default()
+            },
+            2_000,
+            Some(50),
+            0,
+        );
+
+        wallet.current_height.set(Some(100));
+        assert_eq!(
+            wallet.get_balance().unwrap(),
+            Balance {
+                confirmed: 51_000,
+                trusted_pending: 10_000,
+                untrusted_pending: 25_000,
+                immature: 2_000,
+                frozen: 5_000,
+            }
+        );
+        assert_eq!(wallet.get_balance().unwrap().get_spendable(), 61_000);
+        assert_eq!(wallet.get_balance().unwrap().get_total(), 93_000);
+
+        // without knowing the current height all the coinbase outputs are immature
+        wallet.current_height.set(None);
+        assert_eq!(wallet.get_balance().unwrap().immature, 3_000);
+    }
+
     #[test]
     #[should_panic(expected = "InsufficientFunds")]
     fn test_create_tx_manually_selected_insufficient() {
@@ -4010,4 +4586,37 @@ pub(crate) mod test {
             }
         );
     }
+
+    #[test]
+    fn test_labels() {
+        let (wallet, _, txid) = get_funded_wallet(get_test_wpkh());
+        let outpoint = OutPoint { txid, vout: 0 };
+
+        wallet
+            .set_label(&LabelRef::Transaction(txid), "funding")
+            .unwrap();
+        wallet
+            .set_label(&LabelRef::Output(outpoint), "coin")
+            .unwrap();
+
+        let txs = wallet.list_transactions(false).unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].label, Some("funding".to_string()));
+
+        let utxos = wallet.list_unspent().unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].label, Some("coin".to_string()));
+        assert_eq!(
+            wallet.get_utxo(outpoint).unwrap().unwrap().label,
+            Some("coin".to_string())
+        );
+
+        assert_eq!(wallet.list_labels().unwrap().len(), 2);
+        assert_eq!(
+            wallet.del_label(&LabelRef::Output(outpoint)).unwrap(),
+            Some("coin".to_string())
+        );
+        assert_eq!(wallet.get_label(&LabelRef::Output(outpoint)).unwrap(), None);
+        assert_eq!(wallet.list_unspent().unwrap()[0].label, None);
+    }
 }
@@ -41,8 +41,10 @@ pub fn get_timestamp() -> u64 {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
 pub(crate) struct Instant(SystemInstant);
 #[cfg(target_arch = "wasm32")]
+#[derive(Debug)]
 pub(crate) struct Instant(Duration);
 
 impl Instant {
@@ -297,7 +297,8 @@ impl<'a, B, D: BatchDatabase, Cs: CoinSelectionAlgorithm<D>, Ctx: TxBuilderConte
     /// Add a utxo to the internal list of utxos that **must** be spent
     ///
     /// These have priority over the "unspendable" utxos, meaning that if a utxo is present both in
-    /// the "utxos" and the "unspendable" list, it will be spent.
+    /// the "utxos" and the "unspendable" list, it will be spent. This is also the only way to spend
+    /// a utxo frozen with [`Wallet::freeze_utxo`].
     pub fn add_utxo(&mut self, outpoint: OutPoint) -> Result<&mut Self, Error> {
         self.add_utxos(&[outpoint])
     }
@@ -395,6 +396,9 @@ impl<'a, B, D: BatchDatabase, Cs: CoinSelectionAlgorithm<D>, Ctx: TxBuilderConte
     ///
     /// It's important to note that the "must-be-spent" utxos added with [`TxBuilder::add_utxo`]
     /// have priority over these. See the docs of the two linked methods for more details.
+    ///
+    /// This list only applies to the transaction being built, use [`Wallet::freeze_utxo`] to
+    /// exclude a utxo persistently.
     pub fn unspendable(&mut self, unspendable: Vec<OutPoint>) -> &mut Self {
         self.params.unspendable = unspendable.into_iter().collect();
         self
@@ -495,6 +499,8 @@ impl<'a, B, D: BatchDatabase, Cs: CoinSelectionAlgorithm<D>, Ctx: TxBuilderConte
     }
 
     /// Spend all the available inputs. This respects filters like [`TxBuilder::unspendable`] and the change policy.
+    ///
+    /// Frozen utxos are not included, unless they are explicitly added with [`TxBuilder::add_utxo`].
     pub fn drain_wallet(&mut self) -> &mut Self {
         self.params.drain_wallet = true;
         self
@@ -799,6 +805,9 @@ mod test {
                 },
                 txout: Default::default(),
                 keychain: KeychainKind::External,
+                label: None,
+                is_frozen: false,
+                is_coinbase: false,
             },
             LocalUtxo {
                 outpoint: OutPoint {
@@ -807,6 +816,9 @@ mod test {
                 },
                 txout: Default::default(),
                 keychain: KeychainKind::Internal,
+                label: None,
+                is_frozen: false,
+                is_coinbase: false,
             },
         ]
     }
@@ -16,6 +16,9 @@ use miniscript::{MiniscriptKey, Satisfier, ToPublicKey};
 // De-facto standard "dust limit" (even though it should change based on the output type)
 pub const DUST_LIMIT_SATOSHI: u64 = 546;
 
+/// Number of confirmations a coinbase output needs before it can be spent
+pub(crate) const COINBASE_MATURITY: u32 = 100;
+
 // MSB of the nSequence. If set there's no consensus-constraint, so it must be disabled when
 // spending using CSV in order to enforce CSV rules
 pub(crate) const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
@@ -143,7 +146,11 @@ pub struct ChunksIterator<I: Iterator> {
     size: usize,
 }
 
-#[cfg(any(feature = "electrum", feature = "esplora"))]
+#[cfg(any(
+    feature = "electrum",
+    feature = "esplora",
+    feature = "esplora-blocking"
+))]
 impl<I: Iterator> ChunksIterator<I> {
     pub fn new(iter: I, size: usize) -> Self {
         ChunksIterator { iter, size }